@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+
+/// Whether `c` belongs to a script written without spaces between words
+/// (kana, CJK ideographs, Hangul). CJK punctuation is not included, so
+/// `、` and `。` end a run.
+pub fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{309F}'     // Hiragana
+        | '\u{30A0}'..='\u{30FF}'   // Katakana
+        | '\u{31F0}'..='\u{31FF}'   // Katakana phonetic extensions
+        | '\u{3400}'..='\u{4DBF}'   // CJK extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK unified ideographs
+        | '\u{AC00}'..='\u{D7AF}'   // Hangul syllables
+        | '\u{F900}'..='\u{FAFF}'   // CJK compatibility ideographs
+        | '\u{FF66}'..='\u{FF9F}'   // Halfwidth katakana
+        | '\u{20000}'..='\u{2FA1F}' // Supplementary ideographs
+    )
+}
+
+pub fn contains_cjk(s: &str) -> bool {
+    s.chars().any(is_cjk)
+}
+
+/// Maximal runs of CJK characters in `text`.
+fn cjk_runs(text: &str) -> impl Iterator<Item = Vec<char>> + '_ {
+    text.split(|c: char| !is_cjk(c))
+        .filter(|run| !run.is_empty())
+        .map(|run| run.chars().collect())
+}
+
+/// Overlapping character bigrams of every CJK run in `text`, in order.
+/// A run of a single character yields that character as a unigram.
+pub fn bigrams(text: &str) -> Vec<String> {
+    cjk_runs(text)
+        .flat_map(|run| {
+            if run.len() == 1 {
+                vec![run[0].to_string()]
+            } else {
+                run.windows(2).map(|pair| pair.iter().collect()).collect()
+            }
+        })
+        .collect()
+}
+
+/// Occurrence count of every gram of `text`, as stored in the side index.
+pub fn gram_counts(text: &str) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for gram in bigrams(text) {
+        *counts.entry(gram).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Splits a search input into keywords. Besides whitespace (including the
+/// ideographic space), words are split where CJK text meets other scripts,
+/// so `Rustのエラー` searches for `Rust` and `のエラー`. Punctuation left over
+/// from such a split is dropped.
+pub fn split_keywords(input: &str) -> Vec<String> {
+    let mut keywords = Vec::new();
+
+    for word in input.split_whitespace() {
+        if !contains_cjk(word) {
+            keywords.push(word.to_string());
+            continue;
+        }
+
+        let mut current = String::new();
+        let mut current_is_cjk = false;
+        for c in word.chars() {
+            if !current.is_empty() && is_cjk(c) != current_is_cjk {
+                push_piece(&mut keywords, std::mem::take(&mut current));
+            }
+            current_is_cjk = is_cjk(c);
+            current.push(c);
+        }
+        push_piece(&mut keywords, current);
+    }
+
+    keywords
+}
+
+fn push_piece(keywords: &mut Vec<String>, piece: String) {
+    if piece.chars().any(char::is_alphanumeric) {
+        keywords.push(piece);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bigrams_of_japanese_runs() {
+        assert_eq!(bigrams("エラー処理"), vec!["エラ", "ラー", "ー処", "処理"]);
+        // Latin text and punctuation separate runs
+        assert_eq!(bigrams("TODO.md の続き、2から"), vec!["の続", "続き", "から"]);
+        assert_eq!(bigrams("字"), vec!["字"]);
+        assert!(bigrams("plain english").is_empty());
+    }
+
+    #[test]
+    fn test_gram_counts() {
+        let counts = gram_counts("テストのテスト");
+        assert_eq!(counts.get("テス"), Some(&2));
+        assert_eq!(counts.get("スト"), Some(&2));
+        assert_eq!(counts.get("トの"), Some(&1));
+    }
+
+    #[test]
+    fn test_split_keywords_mixed_scripts() {
+        assert_eq!(split_keywords("Rustのエラー処理"), vec!["Rust", "のエラー処理"]);
+        assert_eq!(split_keywords("TODO.mdの続き"), vec!["TODO.md", "の続き"]);
+        assert_eq!(split_keywords("エラー、警告"), vec!["エラー", "警告"]);
+        assert_eq!(split_keywords("データベース\u{3000}設計 duckdb"), vec!["データベース", "設計", "duckdb"]);
+        assert_eq!(split_keywords("c++ a->b"), vec!["c++", "a->b"]);
+    }
+}
@@ -17,10 +17,21 @@ impl ClaudeReader {
         })
     }
 
+    /// Reader for a projects directory other than `~/.claude/projects`.
+    #[allow(dead_code)]
+    pub fn with_path(claude_projects_path: PathBuf) -> Self {
+        Self { claude_projects_path }
+    }
+
+    pub fn projects_path(&self) -> &Path {
+        &self.claude_projects_path
+    }
+
     pub fn check_directory_exists(&self) -> bool {
         self.claude_projects_path.exists() && self.claude_projects_path.is_dir()
     }
 
+    #[allow(dead_code)]
     pub fn list_project_directories(&self) -> Result<Vec<PathBuf>> {
         if !self.check_directory_exists() {
             return Ok(Vec::new());
@@ -101,9 +112,9 @@ mod tests {
         let claude_path = temp_dir.path().join(".claude").join("projects");
         fs::create_dir_all(&claude_path).unwrap();
         
-        fs::create_dir(&claude_path.join("project1")).unwrap();
-        fs::create_dir(&claude_path.join("project2")).unwrap();
-        fs::File::create(&claude_path.join("not_a_dir.txt")).unwrap();
+        fs::create_dir(claude_path.join("project1")).unwrap();
+        fs::create_dir(claude_path.join("project2")).unwrap();
+        fs::File::create(claude_path.join("not_a_dir.txt")).unwrap();
         
         let reader = ClaudeReader {
             claude_projects_path: claude_path.clone(),
@@ -120,9 +131,9 @@ mod tests {
         let project_path = claude_path.join("test_project");
         fs::create_dir_all(&project_path).unwrap();
         
-        fs::File::create(&project_path.join("conversation1.jsonl")).unwrap();
-        fs::File::create(&project_path.join("conversation2.jsonl")).unwrap();
-        fs::File::create(&project_path.join("not_jsonl.txt")).unwrap();
+        fs::File::create(project_path.join("conversation1.jsonl")).unwrap();
+        fs::File::create(project_path.join("conversation2.jsonl")).unwrap();
+        fs::File::create(project_path.join("not_jsonl.txt")).unwrap();
         
         let reader = ClaudeReader {
             claude_projects_path: claude_path.clone(),
@@ -1,16 +1,183 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use clap::{Parser, Subcommand};
 use crate::claude_reader::ClaudeReader;
-use crate::jsonl_parser::JsonlParser;
-use crate::db_connection::DatabaseConnection;
-use crate::data_importer::DataImporter;
-use crate::search::{SearchEngine, SearchQuery, SearchMode};
+use crate::db_schema::{latest_version, Migrator};
+use crate::notes::Note;
+use crate::raw_archive::same_content;
+use crate::obsidian::export_obsidian;
+use crate::date_filter::{parse_timezone, DateParser};
+use crate::export::{find_sessions, render_markdown, write_json_rows, ExportFormat, SessionExport};
+use crate::search::{SearchQuery, SearchMode, SearchResult};
+use crate::real_db_connection::ExtendedDatabaseConnection;
+use crate::session::SessionMessage;
+use crate::site::build_site;
+use crate::snippet::DEFAULT_SNIPPET_CHARS;
+use crate::store::{refresh_search_index, Target, VaultStore};
+use crate::sync::{SyncMode, Syncer};
+use crate::tags::{normalize_tag, TagCount, TagMode};
+use crate::tool_calls::ToolCallQuery;
+use crate::watch::VaultWatcher;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(feature = "tui")]
 use crate::tui::run_tui;
 
-#[cfg(test)]
-use crate::db_connection::MockDatabaseConnection;
+const ANSI_BOLD_YELLOW: &str = "\x1b[1;33m";
+const ANSI_BOLD_RED: &str = "\x1b[1;31m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// ANSI styling only when writing to a terminal, and never with `NO_COLOR` set.
+fn use_color() -> bool {
+    std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+fn print_indented(text: &str) {
+    for line in text.lines() {
+        println!("    {}", line);
+    }
+}
+
+/// One-line summary of a hit: its snippet with matches highlighted, or the
+/// start of its content when no snippet was generated.
+fn format_search_result(result: &SearchResult, color: bool) -> String {
+    let body = match &result.snippet {
+        Some(snippet) if color => snippet.render(ANSI_BOLD_RED, ANSI_RESET),
+        Some(snippet) => snippet.render("", ""),
+        None => result.message_content.as_deref()
+            .map(|content| content.chars().take(DEFAULT_SNIPPET_CHARS).collect::<String>().replace('\n', " "))
+            .unwrap_or_else(|| "(no content)".to_string()),
+    };
+    format!(
+        "- [{}] {} {}{}\n    {}{}",
+        result.id,
+        result.timestamp.format("%Y-%m-%d %H:%M"),
+        result.message_role.as_deref().unwrap_or("unknown"),
+        result.tags.iter().map(|tag| format!(" #{}", tag)).collect::<String>(),
+        body,
+        result.notes.iter()
+            .map(|note| format!("\n    note: {}", note.lines().next().unwrap_or_default()))
+            .collect::<String>()
+    )
+}
+
+fn search_mode(mode: &str, regex: bool) -> Result<SearchMode> {
+    let mode = match mode {
+        "and" => SearchMode::And,
+        "or" => SearchMode::Or,
+        "regex" => SearchMode::Regex,
+        other => return Err(anyhow!("Unknown search mode '{}' (expected and, or or regex)", other)),
+    };
+    Ok(if regex { SearchMode::Regex } else { mode })
+}
+
+/// A query with the filters `search` and `export` share; keywords, mode and
+/// limit are left at their defaults.
+fn search_filters(
+    project: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+    tz: Option<&str>,
+    favorites: bool,
+    tags: &[String],
+    tag_mode: &str,
+) -> Result<SearchQuery> {
+    let date_parser = match tz {
+        Some(tz) => DateParser::new(parse_timezone(tz)?),
+        None => DateParser::local(),
+    };
+    let date_from = from.map(|s| date_parser.parse_start(s)).transpose()?;
+    let date_to = to.map(|s| date_parser.parse_end(s)).transpose()?;
+    if let (Some(start), Some(end)) = (date_from, date_to) {
+        if start >= end {
+            return Err(anyhow!("--from ({}) must be earlier than --to ({})", from.unwrap_or_default(), to.unwrap_or_default()));
+        }
+    }
+    
+    let tag_mode = match tag_mode {
+        "or" => TagMode::Any,
+        _ => TagMode::All,
+    };
+    let tags = tags.iter().map(|tag| normalize_tag(tag)).collect::<Result<Vec<_>>>()?;
+    
+    Ok(SearchQuery {
+        project_filter: project.map(|s| s.to_string()),
+        date_from,
+        date_to,
+        favorites_only: Some(favorites),
+        tags,
+        tag_mode,
+        ..Default::default()
+    })
+}
+
+/// A session ID resolves to the session; message UUIDs and conversation IDs
+/// resolve to the message.
+fn resolve_target(store: &dyn VaultStore, id: &str) -> Result<Option<Target>> {
+    Ok(match store.resolve_session(id)? {
+        Some((session_id, None)) => Some(Target::Session(session_id)),
+        Some((_, Some(uuid))) => Some(Target::Message(uuid)),
+        None => None,
+    })
+}
+
+/// Opens `$EDITOR` (or `vi`) on a temporary file holding `initial` and
+/// returns the saved text, trimmed.
+fn edit_in_editor(initial: &str) -> Result<String> {
+    let editor = std::env::var("EDITOR").ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    edit_with(&editor, initial)
+}
+
+fn edit_with(editor: &str, initial: &str) -> Result<String> {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_nanos();
+    let path = std::env::temp_dir().join(format!("cc-vault-note-{}-{}.md", std::process::id(), nanos));
+    std::fs::write(&path, initial)?;
+    
+    // Run through the shell so EDITOR may carry arguments, e.g. `code --wait`
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status();
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    
+    let status = status.with_context(|| format!("Failed to run editor '{}'", editor))?;
+    if !status.success() {
+        return Err(anyhow!("Editor '{}' exited with {}; note not saved", editor, status));
+    }
+    Ok(edited?.trim().to_string())
+}
+
+fn print_note(note: &Note) {
+    println!(
+        "- {} {} ({})",
+        note.target.kind(),
+        note.target.id(),
+        note.updated_at.format("%Y-%m-%d %H:%M")
+    );
+    print_indented(&note.body);
+}
+
+fn print_context_message(message: &SessionMessage) {
+    println!(
+        "  [{}] {} ({})",
+        message.timestamp.format("%Y-%m-%d %H:%M:%S"),
+        message.message_role.as_deref().unwrap_or(&message.message_type),
+        message.uuid
+    );
+    let body = message.content_text.as_deref()
+        .map(str::to_string)
+        .or_else(|| message.block_summary.as_ref().map(|b| format!("({})", b)))
+        .unwrap_or_else(|| "(no content)".to_string());
+    print_indented(&body);
+}
 
 #[derive(Debug, Parser)]
 #[command(name = "cc-vault")]
@@ -32,6 +199,36 @@ pub enum Commands {
         /// Force re-import even if already imported
         #[arg(short, long)]
         force: bool,
+        
+        /// Also keep the raw transcript lines, so `restore-jsonl` can rebuild the file
+        #[arg(long)]
+        raw: bool,
+    },
+    
+    /// Import only what was appended to conversation files since the last sync
+    Sync {
+        /// Project path to sync (default: all projects)
+        #[arg(short, long)]
+        project: Option<String>,
+        
+        /// Also keep the raw transcript lines, so `restore-jsonl` can rebuild the file
+        #[arg(long)]
+        raw: bool,
+    },
+    
+    /// Keep the vault up to date by syncing conversation files as they change
+    ///
+    /// The vault stays open while watching, so other cc-vault commands cannot
+    /// use it until watch is stopped. The search index is rebuilt at most every
+    /// 30 seconds, and once more on exit.
+    Watch {
+        /// Milliseconds a file must stay unchanged before it is synced
+        #[arg(long, default_value = "500")]
+        debounce_ms: u64,
+        
+        /// Also keep the raw transcript lines, so `restore-jsonl` can rebuild the file
+        #[arg(long)]
+        raw: bool,
     },
     
     /// Search conversations
@@ -39,10 +236,14 @@ pub enum Commands {
         /// Keywords to search for
         keywords: Vec<String>,
         
-        /// Search mode (and/or)
+        /// Search mode (and/or/regex)
         #[arg(short, long, default_value = "and")]
         mode: String,
         
+        /// Treat keywords as regular expressions (all must match)
+        #[arg(short, long)]
+        regex: bool,
+        
         /// Filter by project
         #[arg(short, long)]
         project: Option<String>,
@@ -51,27 +252,183 @@ pub enum Commands {
         #[arg(long)]
         from: Option<String>,
         
-        /// Date to (e.g., "2024-01-31" or "yesterday")
+        /// Date to, inclusive (e.g., "2024-01-31" or "yesterday")
         #[arg(long)]
         to: Option<String>,
         
+        /// Timezone for interpreting dates (e.g., "Asia/Tokyo"; defaults to the system timezone)
+        #[arg(long)]
+        tz: Option<String>,
+        
         /// Show only favorites
         #[arg(short, long)]
         favorites: bool,
         
+        /// Show only messages with this tag, on the message or its session (repeatable)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        
+        /// How several --tag filters combine (and/or)
+        #[arg(long, default_value = "and")]
+        tag_mode: String,
+        
+        /// Maximum number of results
+        #[arg(short, long, default_value = "20")]
+        limit: usize,
+        
+        /// Show N messages before each hit
+        #[arg(short = 'B', long)]
+        before: Option<usize>,
+        
+        /// Show N messages after each hit
+        #[arg(short = 'A', long)]
+        after: Option<usize>,
+        
+        /// Show N messages before and after each hit
+        #[arg(short = 'C', long)]
+        context: Option<usize>,
+    },
+    
+    /// List tool calls (e.g., `tools --tool Bash --input-contains cargo`)
+    Tools {
+        /// Exact tool name (e.g., "Bash", "Edit")
+        #[arg(short, long)]
+        tool: Option<String>,
+        
+        /// Case-insensitive text to look for in the tool input
+        #[arg(short, long)]
+        input_contains: Option<String>,
+        
+        /// Filter by session ID
+        #[arg(short, long)]
+        session: Option<String>,
+        
         /// Maximum number of results
         #[arg(short, long, default_value = "20")]
         limit: usize,
     },
     
-    /// Mark or unmark conversations as favorite
+    /// Show a whole session thread, including branches
+    Show {
+        /// Session ID, or a message UUID to highlight within its session
+        id: String,
+    },
+    
+    /// Export sessions as markdown, or messages as rows for analysis
+    /// (e.g., `export <session-id> --out exports/`, `export --format parquet --out vault.parquet`)
+    Export {
+        /// Session ID, message UUID, conversation ID, or search keywords; for
+        /// row formats, keywords only, and none exports the whole vault
+        query: Vec<String>,
+        
+        /// Output format (markdown, json, ndjson, csv, parquet, obsidian)
+        #[arg(short, long, default_value = "markdown")]
+        format: String,
+        
+        /// Directory for markdown, one file per session; vault folder for obsidian;
+        /// file for the other formats (default: stdout)
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+        
+        /// Search mode (and/or/regex)
+        #[arg(short, long, default_value = "and")]
+        mode: String,
+        
+        /// Filter by project
+        #[arg(short, long)]
+        project: Option<String>,
+        
+        /// Date from (e.g., "2024-01-01" or "last week")
+        #[arg(long)]
+        from: Option<String>,
+        
+        /// Date to, inclusive (e.g., "2024-01-31" or "yesterday")
+        #[arg(long)]
+        to: Option<String>,
+        
+        /// Timezone for interpreting dates (defaults to the system timezone)
+        #[arg(long)]
+        tz: Option<String>,
+        
+        /// Export only favorites
+        #[arg(long)]
+        favorites: bool,
+        
+        /// Export only messages with this tag, on the message or its session (repeatable)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        
+        /// How several --tag filters combine (and/or)
+        #[arg(long, default_value = "and")]
+        tag_mode: String,
+        
+        /// Maximum number of search hits (default: all)
+        #[arg(short, long)]
+        limit: Option<usize>,
+    },
+    
+    /// Write a static HTML site for browsing the vault offline (e.g., `site --out ./vault-site`)
+    Site {
+        /// Directory to write the site to
+        #[arg(short, long)]
+        out: PathBuf,
+    },
+    
+    /// Write an archived session back out as the original `.jsonl` file, so
+    /// Claude Code can `--resume` it after cleaning up its own copy
+    RestoreJsonl {
+        /// Session ID, message UUID or search result ID
+        session: String,
+        
+        /// File or directory to write to (default: where the transcript was archived from)
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+        
+        /// Overwrite a file that differs from the archived copy
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Upgrade the vault schema, or show or change its version
+    Migrate {
+        /// List every migration and whether it has been applied
+        #[arg(long)]
+        status: bool,
+        
+        /// Migrate up or down to this version (default: latest)
+        #[arg(long, conflicts_with = "status")]
+        to: Option<u32>,
+    },
+    
+    /// Mark or unmark a message or a whole session as favorite
     Favorite {
-        /// Conversation ID
-        id: i64,
+        /// Session ID, message UUID, or the conversation ID shown by search
+        #[arg(required_unless_present = "list")]
+        id: Option<String>,
         
         /// Remove from favorites (default: add to favorites)
         #[arg(short, long)]
         remove: bool,
+        
+        /// Note to keep with the favorite
+        #[arg(short, long, conflicts_with = "remove")]
+        note: Option<String>,
+        
+        /// List favorites instead of changing one
+        #[arg(short, long, conflicts_with_all = ["id", "remove", "note"])]
+        list: bool,
+    },
+    
+    /// Tag messages and sessions (e.g., `tag add <uuid> postmortem`)
+    Tag {
+        #[command(subcommand)]
+        action: TagAction,
+    },
+    
+    /// Annotate a message or a whole session (e.g., `note add <uuid> "how we fixed it"`)
+    Note {
+        #[command(subcommand)]
+        action: NoteAction,
     },
     
     /// Launch interactive TUI mode
@@ -79,56 +436,185 @@ pub enum Commands {
     Tui,
 }
 
+#[derive(Debug, Subcommand)]
+pub enum TagAction {
+    /// Add tags to a message or a whole session
+    Add {
+        /// Session ID, message UUID, or the conversation ID shown by search
+        id: String,
+        
+        /// Tags to add
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    
+    /// Remove tags from a message or session
+    Remove {
+        /// Session ID, message UUID, or the conversation ID shown by search
+        id: String,
+        
+        /// Tags to remove
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    
+    /// List every tag, or the tags on one message or session
+    List {
+        /// Session ID, message UUID, or the conversation ID shown by search
+        id: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum NoteAction {
+    /// Add a note; opens $EDITOR when no text is given
+    Add {
+        /// Session ID, message UUID, or the conversation ID shown by search
+        id: String,
+        
+        /// Note text
+        text: Vec<String>,
+    },
+    
+    /// Edit a note in $EDITOR; saving it empty removes the note
+    Edit {
+        /// Session ID, message UUID, or the conversation ID shown by search
+        id: String,
+    },
+    
+    /// List every note, or the notes on one message or session
+    List {
+        /// Session ID, message UUID, or the conversation ID shown by search
+        id: Option<String>,
+    },
+}
+
 impl Cli {
     pub fn parse_args() -> Self {
         Cli::parse()
     }
     
-    pub fn execute(&self, connection: &dyn DatabaseConnection) -> Result<()> {
+    pub fn execute(&self, store: &dyn VaultStore) -> Result<()> {
         match &self.command {
-            Commands::Import { project, force } => {
-                self.execute_import(connection, project.as_deref(), *force)
+            Commands::Import { project, force, raw } => {
+                self.execute_import(store, &ClaudeReader::new()?, project.as_deref(), *force, *raw)
+            }
+            Commands::Sync { project, raw } => {
+                self.execute_sync(store, &ClaudeReader::new()?, project.as_deref(), *raw)
+            }
+            Commands::Watch { debounce_ms, raw } => {
+                self.execute_watch(store, *debounce_ms, *raw)
             }
             Commands::Search { 
                 keywords, 
                 mode, 
+                regex,
                 project, 
                 from, 
                 to, 
+                tz,
                 favorites, 
-                limit 
+                tags,
+                tag_mode,
+                limit,
+                before,
+                after,
+                context,
             } => {
                 self.execute_search(
-                    connection, 
+                    store, 
                     keywords, 
                     mode, 
+                    *regex,
                     project.as_deref(), 
                     from.as_deref(), 
                     to.as_deref(), 
+                    tz.as_deref(),
                     *favorites, 
-                    *limit
+                    tags,
+                    tag_mode,
+                    *limit,
+                    before.or(*context).unwrap_or(0),
+                    after.or(*context).unwrap_or(0)
                 )
             }
-            Commands::Favorite { id, remove } => {
-                self.execute_favorite(connection, *id, *remove)
+            Commands::Tools { tool, input_contains, session, limit } => {
+                let query = ToolCallQuery {
+                    tool_name: tool.clone(),
+                    input_contains: input_contains.clone(),
+                    session_id: session.clone(),
+                    limit: Some(*limit),
+                };
+                self.execute_tools(store, &query)
+            }
+            Commands::Show { id } => {
+                self.execute_show(store, id)
+            }
+            Commands::Export {
+                query,
+                format,
+                out,
+                mode,
+                project,
+                from,
+                to,
+                tz,
+                favorites,
+                tags,
+                tag_mode,
+                limit,
+            } => {
+                let query = SearchQuery {
+                    keywords: query.clone(),
+                    mode: search_mode(mode, false)?,
+                    limit: *limit,
+                    ..search_filters(project.as_deref(), from.as_deref(), to.as_deref(), tz.as_deref(), *favorites, tags, tag_mode)?
+                };
+                self.execute_export(store, &query, format, out.as_deref())
+            }
+            Commands::Site { out } => {
+                self.execute_site(store, out)
+            }
+            Commands::RestoreJsonl { session, out, force } => {
+                self.execute_restore_jsonl(store, session, out.as_deref(), *force)
+            }
+            Commands::Migrate { .. } => {
+                Err(anyhow!("migrate must be run against the vault database directly"))
+            }
+            Commands::Favorite { list: true, .. } => {
+                self.execute_list_favorites(store)
+            }
+            Commands::Favorite { id, remove, note, .. } => {
+                self.execute_favorite(store, id.as_deref().unwrap_or_default(), *remove, note.as_deref())
+            }
+            Commands::Tag { action } => {
+                self.execute_tag(store, action)
+            }
+            Commands::Note { action } => {
+                self.execute_note(store, action)
             }
             #[cfg(feature = "tui")]
             Commands::Tui => {
-                run_tui(connection)
+                run_tui(store)
             }
         }
     }
     
-    fn execute_import(&self, connection: &dyn DatabaseConnection, project: Option<&str>, force: bool) -> Result<()> {
-        let reader = ClaudeReader::new()?;
-        let parser = JsonlParser::new();
-        let importer = DataImporter::new(connection);
+    fn execute_import(
+        &self,
+        store: &dyn VaultStore,
+        reader: &ClaudeReader,
+        project: Option<&str>,
+        force: bool,
+        raw: bool,
+    ) -> Result<()> {
+        let syncer = Syncer::new(store).with_raw_archive(raw);
         
         println!("Importing conversations from Claude Code...");
         
         // Check if Claude projects directory exists
         if !reader.check_directory_exists() {
-            return Err(anyhow::anyhow!("Claude projects directory not found at ~/.claude/projects"));
+            return Err(anyhow!("Claude projects directory not found at {}", reader.projects_path().display()));
         }
         
         // Find all JSONL files
@@ -158,44 +644,30 @@ impl Cli {
             
             println!("\nProcessing project: {}", project_name);
             
-            // Read file content
-            let content = std::fs::read_to_string(&jsonl_path)?;
-            
-            // Parse messages
-            let parse_results = parser.parse_multiple_messages_skip_errors(&content);
-            
-            let mut project_imported = 0;
-            let mut project_errors = 0;
-            
-            for (line_num, result) in parse_results {
-                match result {
-                    Ok(message) => {
-                        // Import message
-                        match if force {
-                            importer.import_single_conversation(&message, &project_name)
-                        } else {
-                            importer.import_with_duplicate_check(&message, &project_name)
-                                .map(|_| ())
-                        } {
-                            Ok(_) => project_imported += 1,
-                            Err(e) => {
-                                eprintln!("  Error importing line {}: {}", line_num, e);
-                                project_errors += 1;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("  Error parsing line {}: {}", line_num, e);
-                        project_errors += 1;
-                    }
+            // Each file is imported in one transaction, so a failure leaves it untouched
+            let stats = match syncer.import_file(&jsonl_path, &project_name, force) {
+                Ok(stats) => stats,
+                Err(e) => {
+                    eprintln!("  Error importing {}: {}", jsonl_path.display(), e);
+                    total_errors += 1;
+                    continue;
                 }
-            }
+            };
             
-            println!("  Imported: {}, Errors: {}", project_imported, project_errors);
+            println!(
+                "  Inserted: {}, Updated: {}, Skipped: {}, Errors: {}",
+                stats.inserted, stats.updated, stats.skipped, stats.errors
+            );
+            let project_imported = stats.inserted + stats.updated;
+            let project_errors = stats.errors;
             total_imported += project_imported;
             total_errors += project_errors;
         }
         
+        if total_imported > 0 {
+            refresh_search_index(store);
+        }
+        
         println!("\nImport complete!");
         println!("Total imported: {}", total_imported);
         if total_errors > 0 {
@@ -205,54 +677,556 @@ impl Cli {
         Ok(())
     }
     
+    fn execute_sync(&self, store: &dyn VaultStore, reader: &ClaudeReader, project: Option<&str>, raw: bool) -> Result<()> {
+        let syncer = Syncer::new(store).with_raw_archive(raw);
+        
+        if !reader.check_directory_exists() {
+            return Err(anyhow!("Claude projects directory not found at {}", reader.projects_path().display()));
+        }
+        
+        let mut total_imported = 0;
+        let mut total_errors = 0;
+        let mut unchanged = 0;
+        let mut rescanned = 0;
+        
+        for jsonl_path in reader.find_jsonl_files()? {
+            let project_name = reader.get_project_name_from_path(&jsonl_path)
+                .unwrap_or_else(|| "unknown".to_string());
+            
+            if project.is_some_and(|proj| project_name != proj) {
+                continue;
+            }
+            
+            let report = match syncer.sync_file(&jsonl_path, &project_name) {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("Error syncing {}: {}", jsonl_path.display(), e);
+                    total_errors += 1;
+                    continue;
+                }
+            };
+            
+            match report.mode {
+                SyncMode::Unchanged => unchanged += 1,
+                SyncMode::Rescan => rescanned += 1,
+                SyncMode::Append => {}
+            }
+            if report.imported > 0 || report.errors > 0 {
+                println!("{}: {} new, {} errors", jsonl_path.display(), report.imported, report.errors);
+            }
+            total_imported += report.imported;
+            total_errors += report.errors;
+        }
+        
+        if total_imported > 0 {
+            refresh_search_index(store);
+        }
+        
+        println!("Sync complete: {} new messages ({} files unchanged, {} rescanned)", total_imported, unchanged, rescanned);
+        if total_errors > 0 {
+            println!("Total errors: {}", total_errors);
+        }
+        
+        Ok(())
+    }
+    
+    fn execute_watch(&self, store: &dyn VaultStore, debounce_ms: u64, raw: bool) -> Result<()> {
+        let reader = ClaudeReader::new()?;
+        
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handler_flag = shutdown.clone();
+        ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst))
+            .map_err(|e| anyhow!("Failed to install Ctrl+C handler: {}", e))?;
+        
+        println!("Watching {} (press Ctrl+C to stop)", reader.projects_path().display());
+        VaultWatcher::new(store, reader, Duration::from_millis(debounce_ms))
+            .with_raw_archive(raw)
+            .run(&shutdown)?;
+        println!("Stopped watching");
+        
+        Ok(())
+    }
+    
+    #[allow(clippy::too_many_arguments)]
     fn execute_search(
         &self, 
-        connection: &dyn DatabaseConnection, 
+        store: &dyn VaultStore, 
         keywords: &[String], 
         mode: &str,
+        regex: bool,
         project: Option<&str>,
-        _from: Option<&str>,
-        _to: Option<&str>,
+        from: Option<&str>,
+        to: Option<&str>,
+        tz: Option<&str>,
         favorites: bool,
-        limit: usize
+        tags: &[String],
+        tag_mode: &str,
+        limit: usize,
+        before: usize,
+        after: usize
     ) -> Result<()> {
-        let search_engine = SearchEngine::new(connection);
-        
-        let search_mode = match mode {
-            "or" => SearchMode::Or,
-            _ => SearchMode::And,
-        };
-        
         let query = SearchQuery {
             keywords: keywords.to_vec(),
-            mode: search_mode,
-            project_filter: project.map(|s| s.to_string()),
-            project_filters: None,
-            date_from: None, // TODO: Parse date strings
-            date_to: None,   // TODO: Parse date strings
-            favorites_only: Some(favorites),
+            mode: search_mode(mode, regex)?,
             limit: Some(limit),
+            ..search_filters(project, from, to, tz, favorites, tags, tag_mode)?
         };
         
-        let results = search_engine.search(&query)?;
+        if before > 0 || after > 0 {
+            let groups = store.search_with_context(&query, before, after)?;
+            println!("Found {} results", groups.len());
+            
+            let color = use_color();
+            for group in &groups {
+                println!("--");
+                for message in &group.before {
+                    print_context_message(message);
+                }
+                let hit = &group.hit;
+                let header = format!(
+                    "> [{}] {} ({})",
+                    hit.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                    hit.message_role.as_deref().unwrap_or("unknown"),
+                    hit.uuid
+                );
+                if color {
+                    println!("{}{}{}", ANSI_BOLD_YELLOW, header, ANSI_RESET);
+                } else {
+                    println!("{}", header);
+                }
+                print_indented(hit.message_content.as_deref().unwrap_or("(no content)"));
+                for message in &group.after {
+                    print_context_message(message);
+                }
+            }
+            if !groups.is_empty() {
+                println!("--");
+            }
+            return Ok(());
+        }
+        
+        let results = store.search(&query)?;
         
         println!("Found {} results", results.len());
-        for result in results.iter().take(5) {
-            println!("- [{}] {}", result.id, result.message_content.as_deref().unwrap_or("(no content)"));
+        let color = use_color();
+        for result in &results {
+            println!("{}", format_search_result(result, color));
+        }
+        
+        Ok(())
+    }
+    
+    fn execute_tools(&self, store: &dyn VaultStore, query: &ToolCallQuery) -> Result<()> {
+        let calls = store.find_tool_calls(query)?;
+        
+        println!("Found {} tool calls", calls.len());
+        for call in &calls {
+            let status = match call.is_error {
+                Some(true) => "error",
+                Some(false) => "ok",
+                None => "no result",
+            };
+            println!(
+                "- {} [{}] {} ({})",
+                call.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                call.tool_name,
+                call.input_json.as_deref().unwrap_or("{}"),
+                status
+            );
+            println!("  session: {}  uuid: {}", call.session_id, call.message_uuid);
+        }
+        
+        Ok(())
+    }
+    
+    fn execute_show(&self, store: &dyn VaultStore, id: &str) -> Result<()> {
+        let (session_id, highlight) = store.resolve_session(id)?
+            .ok_or_else(|| anyhow!("No session or message found for '{}'", id))?;
+        let thread = store.load_session(&session_id)?;
+        let color = use_color();
+        
+        let entries = thread.flatten();
+        let project = entries.first().map(|e| e.message.project_path.as_str()).unwrap_or_default();
+        println!("Session: {}  Project: {}  Messages: {}", session_id, project, thread.len());
+        for summary in store.session_summaries(&session_id)? {
+            println!("Summary: {}", summary);
+        }
+        
+        for entry in entries {
+            let indent = "  ".repeat(entry.depth);
+            let message = entry.message;
+            
+            if entry.branch_count > 1 {
+                println!("{}-- branch {}/{} --", indent, entry.branch_index + 1, entry.branch_count);
+            }
+            
+            let is_hit = highlight.as_deref() == Some(message.uuid.as_str());
+            let header = format!(
+                "[{}] {} ({})",
+                message.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                message.message_role.as_deref().unwrap_or(&message.message_type),
+                message.uuid
+            );
+            match (is_hit, color) {
+                (true, true) => println!("{}{}>> {}{}", indent, ANSI_BOLD_YELLOW, header, ANSI_RESET),
+                (true, false) => println!("{}>> {}", indent, header),
+                (false, _) => println!("{}{}", indent, header),
+            }
+            
+            let body = match (&message.content_text, &message.block_summary) {
+                (Some(text), _) => text.clone(),
+                (None, Some(blocks)) => format!("({})", blocks),
+                (None, None) => "(no content)".to_string(),
+            };
+            for line in body.lines() {
+                println!("{}   {}", indent, line);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Runs `migrate`, which works on the schema rather than through a store.
+    /// `main` calls this before the startup upgrade so `--status` shows what is pending.
+    pub fn execute_migrate<C: ExtendedDatabaseConnection>(&self, migrator: &Migrator<'_, C>) -> Result<()> {
+        let Commands::Migrate { status, to } = &self.command else {
+            return Err(anyhow!("Not a migrate command"));
+        };
+        
+        if *status {
+            for migration in migrator.status()? {
+                let applied = migration.applied_at
+                    .map(|at| format!("applied {}", at.format("%Y-%m-%d %H:%M")))
+                    .unwrap_or_else(|| "pending".to_string());
+                println!("{:>3}  {:<22} {}", migration.version, applied, migration.description);
+            }
+            return Ok(());
         }
         
+        let from = migrator.current_version()?;
+        let target = to.unwrap_or_else(latest_version);
+        let ran = migrator.migrate_to(target)?;
+        if ran.is_empty() {
+            println!("Vault schema is already at version {}", target);
+        } else {
+            println!("Migrated vault schema from version {} to {}", from, target);
+        }
+        
+        Ok(())
+    }
+    
+    fn execute_site(&self, store: &dyn VaultStore, out: &Path) -> Result<()> {
+        let stats = build_site(store, out)?;
+        println!(
+            "Wrote {} sessions from {} projects ({} messages) to {}",
+            stats.sessions,
+            stats.projects,
+            stats.messages,
+            out.display()
+        );
+        println!("Open {} in a browser; search works when the folder is served over HTTP", out.join("index.html").display());
+        Ok(())
+    }
+
+    fn execute_restore_jsonl(&self, store: &dyn VaultStore, id: &str, out: Option<&Path>, force: bool) -> Result<()> {
+        // Archived files are keyed by session, which the vault may not know if no line parsed
+        let session_id = store.resolve_session(id)?
+            .map_or_else(|| id.to_string(), |(session_id, _)| session_id);
+        let source = store.raw_source(&session_id)?
+            .ok_or_else(|| anyhow!(
+                "No raw transcript archived for session {}; import or sync with --raw to keep them",
+                session_id
+            ))?;
+
+        let path = match out {
+            Some(out) if out.is_dir() => out.join(format!("{}.jsonl", session_id)),
+            Some(out) => out.to_path_buf(),
+            None => PathBuf::from(&source.source_path),
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Lines are streamed to a file next to the target, which only replaces
+        // it once the whole transcript is written
+        let mut staging_name = path.file_name().unwrap_or_default().to_os_string();
+        staging_name.push(".restoring");
+        let staging = path.with_file_name(staging_name);
+        let restored = Self::write_raw_file(store, &source.source_path, &staging)
+            .and_then(|written| Ok(Self::should_replace(&path, &staging, force)?.then_some(written)));
+        if !matches!(restored, Ok(Some(_))) {
+            let _ = std::fs::remove_file(&staging);
+        }
+        let Some(written) = restored? else {
+            println!("{} already matches the archived transcript", path.display());
+            return Ok(());
+        };
+
+        std::fs::rename(&staging, &path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("Restored {} ({} bytes)", path.display(), written);
         Ok(())
     }
     
-    fn execute_favorite(&self, connection: &dyn DatabaseConnection, id: i64, remove: bool) -> Result<()> {
-        let search_engine = SearchEngine::new(connection);
+    /// Whether the restored `staging` file should replace `path`: not when they
+    /// already match, and only with `force` when `path` holds something else.
+    fn should_replace(path: &Path, staging: &Path, force: bool) -> Result<bool> {
+        if !path.exists() {
+            return Ok(true);
+        }
+        if same_content(path, staging)? {
+            return Ok(false);
+        }
+        if !force {
+            return Err(anyhow!("{} exists and differs from the archived transcript; use --force to overwrite it", path.display()));
+        }
+        Ok(true)
+    }
+    
+    fn write_raw_file(store: &dyn VaultStore, source_path: &str, path: &Path) -> Result<u64> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        let mut out = std::io::BufWriter::new(file);
+        let written = store.write_raw_file(source_path, &mut out)?;
+        out.flush()?;
+        Ok(written)
+    }
+    
+    fn execute_favorite(&self, store: &dyn VaultStore, id: &str, remove: bool, note: Option<&str>) -> Result<()> {
+        let target = resolve_target(store, id)?;
         
         if remove {
-            search_engine.unmark_as_favorite(id)?;
-            println!("Removed conversation {} from favorites", id);
+            // A session that is no longer in the vault can only be found among the favorites
+            let target = match target {
+                Some(target) => target,
+                None => store.favorites()?.into_iter()
+                    .map(|favorite| favorite.target)
+                    .find(|target| target.id() == id)
+                    .ok_or_else(|| anyhow!("No favorite found for '{}'", id))?,
+            };
+            if store.remove_favorite(&target)? {
+                println!("Removed {} {} from favorites", target.kind(), target.id());
+            } else {
+                println!("{} {} was not a favorite", target.kind(), target.id());
+            }
         } else {
-            search_engine.mark_as_favorite(id)?;
-            println!("Added conversation {} to favorites", id);
+            let target = target.ok_or_else(|| anyhow!("No session or message found for '{}'", id))?;
+            store.add_favorite(&target, note)?;
+            println!("Added {} {} to favorites", target.kind(), target.id());
+        }
+        
+        Ok(())
+    }
+    
+    fn execute_list_favorites(&self, store: &dyn VaultStore) -> Result<()> {
+        let favorites = store.favorites()?;
+        if favorites.is_empty() {
+            println!("No favorites yet");
+            return Ok(());
+        }
+        
+        for favorite in favorites {
+            println!(
+                "- {} {} ({})",
+                favorite.target.kind(),
+                favorite.target.id(),
+                favorite.created_at.format("%Y-%m-%d %H:%M")
+            );
+            if let Some(note) = &favorite.note {
+                print_indented(note);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn execute_tag(&self, store: &dyn VaultStore, action: &TagAction) -> Result<()> {
+        match action {
+            TagAction::Add { id, tags } => {
+                let target = resolve_target(store, id)?
+                    .ok_or_else(|| anyhow!("No session or message found for '{}'", id))?;
+                for tag in tags {
+                    store.add_tag(&target, &normalize_tag(tag)?)?;
+                }
+                println!("Tagged {} {}: {}", target.kind(), target.id(), store.tags_of(&target)?.join(", "));
+            }
+            TagAction::Remove { id, tags } => {
+                // An id the vault no longer knows could have been tagged as either kind
+                let targets = match resolve_target(store, id)? {
+                    Some(target) => vec![target],
+                    None => vec![Target::Session(id.clone()), Target::Message(id.clone())],
+                };
+                for tag in tags {
+                    let tag = normalize_tag(tag)?;
+                    let mut removed = false;
+                    for target in &targets {
+                        removed |= store.remove_tag(target, &tag)?;
+                    }
+                    if removed {
+                        println!("Removed tag {} from {}", tag, id);
+                    } else {
+                        println!("{} was not tagged {}", id, tag);
+                    }
+                }
+            }
+            TagAction::List { id: Some(id) } => {
+                let (session_id, uuid) = store.resolve_session(id)?
+                    .ok_or_else(|| anyhow!("No session or message found for '{}'", id))?;
+                // Session tags apply to every message in it, so show both
+                let targets = uuid.map(Target::Message).into_iter().chain([Target::Session(session_id)]);
+                for target in targets {
+                    let tags = store.tags_of(&target)?;
+                    let tags = if tags.is_empty() { "(none)".to_string() } else { tags.join(", ") };
+                    println!("{} {}: {}", target.kind(), target.id(), tags);
+                }
+            }
+            TagAction::List { id: None } => {
+                let counts = store.tag_counts()?;
+                if counts.is_empty() {
+                    println!("No tags yet");
+                }
+                for TagCount { tag, count } in counts {
+                    println!("- {} ({})", tag, count);
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn execute_export(&self, store: &dyn VaultStore, query: &SearchQuery, format: &str, out: Option<&Path>) -> Result<()> {
+        let format = ExportFormat::parse(format)?;
+        if format == ExportFormat::Obsidian {
+            return self.execute_export_obsidian(store, query, out);
+        }
+        if format != ExportFormat::Markdown {
+            return self.execute_export_rows(store, query, format, out);
+        }
+        if query.keywords.is_empty() && query.tags.is_empty() {
+            return Err(anyhow!("Markdown export needs a session ID, message UUID, search keywords or --tag"));
+        }
+        let sessions = find_sessions(store, query)?;
+        
+        if let Some(dir) = out {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        for (i, (session_id, through)) in sessions.iter().enumerate() {
+            let export = SessionExport::load(store, session_id, through.as_deref())?;
+            let rendered = render_markdown(&export);
+            match out {
+                Some(dir) => {
+                    let path = dir.join(format!("{}.{}", session_id, format.extension()));
+                    std::fs::write(&path, rendered)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    println!("Wrote {}", path.display());
+                }
+                None => {
+                    if i > 0 {
+                        println!();
+                    }
+                    print!("{}", rendered);
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn execute_export_obsidian(&self, store: &dyn VaultStore, query: &SearchQuery, out: Option<&Path>) -> Result<()> {
+        let vault = out.ok_or_else(|| anyhow!("obsidian exports need --out <vault folder>"))?;
+        let stats = export_obsidian(store, query, vault)?;
+        println!(
+            "Wrote {} notes to {} ({} unchanged)",
+            stats.written,
+            vault.display(),
+            stats.unchanged
+        );
+        Ok(())
+    }
+    
+    fn execute_export_rows(&self, store: &dyn VaultStore, query: &SearchQuery, format: ExportFormat, out: Option<&Path>) -> Result<()> {
+        if format.copy_options().is_some() {
+            let path = out.ok_or_else(|| anyhow!("{} exports need --out <file>", format.extension()))?;
+            store.copy_rows(query, format, path)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!("Wrote {}", path.display());
+            return Ok(());
+        }
+        
+        let rows = store.export_rows(query)?;
+        match out {
+            Some(path) => {
+                let mut file = std::io::BufWriter::new(std::fs::File::create(path)
+                    .with_context(|| format!("Failed to create {}", path.display()))?);
+                write_json_rows(&rows, format, &mut file)?;
+                std::io::Write::flush(&mut file)?;
+                println!("Wrote {} rows to {}", rows.len(), path.display());
+            }
+            None => write_json_rows(&rows, format, &mut std::io::stdout().lock())?,
+        }
+        Ok(())
+    }
+    
+    fn execute_note(&self, store: &dyn VaultStore, action: &NoteAction) -> Result<()> {
+        match action {
+            NoteAction::Add { id, text } => {
+                let target = resolve_target(store, id)?
+                    .ok_or_else(|| anyhow!("No session or message found for '{}'", id))?;
+                if store.note(&target)?.is_some() {
+                    return Err(anyhow!(
+                        "{} {} already has a note; use `cc-vault note edit {}`",
+                        target.kind(), target.id(), id
+                    ));
+                }
+                let body = if text.is_empty() { edit_in_editor("")? } else { text.join(" ") };
+                if body.trim().is_empty() {
+                    println!("Empty note, nothing saved");
+                    return Ok(());
+                }
+                store.save_note(&target, body.trim())?;
+                println!("Added note to {} {}", target.kind(), target.id());
+            }
+            NoteAction::Edit { id } => {
+                // Notes on sessions that are gone from the vault stay editable by their id
+                let target = match resolve_target(store, id)? {
+                    Some(target) => target,
+                    None => store.notes()?.into_iter()
+                        .map(|note| note.target)
+                        .find(|target| target.id() == id)
+                        .ok_or_else(|| anyhow!("No session, message or note found for '{}'", id))?,
+                };
+                let current = store.note(&target)?.map(|note| note.body).unwrap_or_default();
+                let body = edit_in_editor(&current)?;
+                if body == current {
+                    println!("Note unchanged");
+                } else if body.is_empty() {
+                    store.remove_note(&target)?;
+                    println!("Removed note from {} {}", target.kind(), target.id());
+                } else {
+                    store.save_note(&target, &body)?;
+                    println!("Saved note on {} {}", target.kind(), target.id());
+                }
+            }
+            NoteAction::List { id: Some(id) } => {
+                let (session_id, uuid) = store.resolve_session(id)?
+                    .ok_or_else(|| anyhow!("No session or message found for '{}'", id))?;
+                let targets = uuid.map(Target::Message).into_iter().chain([Target::Session(session_id)]);
+                let notes = targets
+                    .map(|target| store.note(&target))
+                    .collect::<Result<Vec<_>>>()?;
+                if notes.iter().all(Option::is_none) {
+                    println!("No notes on {}", id);
+                }
+                notes.iter().flatten().for_each(print_note);
+            }
+            NoteAction::List { id: None } => {
+                let notes = store.notes()?;
+                if notes.is_empty() {
+                    println!("No notes yet");
+                }
+                notes.iter().for_each(print_note);
+            }
         }
         
         Ok(())
@@ -262,6 +1236,17 @@ impl Cli {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::export::ExportRow;
+    use crate::jsonl_parser::JsonlParser;
+    use crate::memory_store::MemoryStore;
+    use crate::test_support::line;
+    
+    fn store_with_message() -> MemoryStore {
+        let store = MemoryStore::new();
+        let record = JsonlParser::new().parse_record(&line("uuid-1", None, "session-1", 48, "user", "hello".into()));
+        store.upsert_messages(&mut std::iter::once(record), "/test", false).unwrap();
+        store
+    }
     
     #[test]
     fn test_parse_import_command() {
@@ -272,9 +1257,10 @@ mod tests {
         let cli = cli.unwrap();
         
         match cli.command {
-            Commands::Import { project, force } => {
+            Commands::Import { project, force, raw } => {
                 assert_eq!(project, None);
-                assert_eq!(force, false);
+                assert!(!force);
+                assert!(!raw);
             }
             _ => panic!("Expected Import command"),
         }
@@ -282,21 +1268,51 @@ mod tests {
     
     #[test]
     fn test_parse_import_with_options() {
-        let args = vec!["cc-vault", "import", "--project", "/my/project", "--force"];
+        let args = vec!["cc-vault", "import", "--project", "/my/project", "--force", "--raw"];
         let cli = Cli::try_parse_from(args);
         
         assert!(cli.is_ok());
         let cli = cli.unwrap();
         
         match cli.command {
-            Commands::Import { project, force } => {
+            Commands::Import { project, force, raw } => {
                 assert_eq!(project, Some("/my/project".to_string()));
-                assert_eq!(force, true);
+                assert!(force);
+                assert!(raw);
             }
             _ => panic!("Expected Import command"),
         }
     }
     
+    #[test]
+    fn test_parse_sync_command() {
+        let args = vec!["cc-vault", "sync", "--project", "my-project"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        
+        match cli.command {
+            Commands::Sync { project, raw } => {
+                assert_eq!(project, Some("my-project".to_string()));
+                assert!(!raw);
+            }
+            _ => panic!("Expected Sync command"),
+        }
+    }
+    
+    #[test]
+    fn test_parse_watch_command() {
+        let cli = Cli::try_parse_from(vec!["cc-vault", "watch"]).unwrap();
+        match cli.command {
+            Commands::Watch { debounce_ms, raw } => assert_eq!((debounce_ms, raw), (500, false)),
+            _ => panic!("Expected Watch command"),
+        }
+        
+        let cli = Cli::try_parse_from(vec!["cc-vault", "watch", "--debounce-ms", "2000", "--raw"]).unwrap();
+        match cli.command {
+            Commands::Watch { debounce_ms, raw } => assert_eq!((debounce_ms, raw), (2000, true)),
+            _ => panic!("Expected Watch command"),
+        }
+    }
+    
     #[test]
     fn test_parse_search_command() {
         let args = vec!["cc-vault", "search", "rust", "programming"];
@@ -334,24 +1350,179 @@ mod tests {
             Commands::Search { 
                 keywords, 
                 mode, 
+                regex,
                 project, 
                 from, 
                 to, 
+                tz,
                 favorites, 
-                limit 
+                tags,
+                tag_mode,
+                limit,
+                before,
+                after,
+                context,
             } => {
                 assert_eq!(keywords, vec!["test"]);
                 assert_eq!(mode, "or");
+                assert!(!regex);
                 assert_eq!(project, Some("/my/project".to_string()));
                 assert_eq!(from, Some("2024-01-01".to_string()));
                 assert_eq!(to, Some("2024-01-31".to_string()));
-                assert_eq!(favorites, true);
+                assert_eq!(tz, None);
+                assert!(favorites);
+                assert!(tags.is_empty());
+                assert_eq!(tag_mode, "and");
                 assert_eq!(limit, 50);
+                assert_eq!((before, after, context), (None, None, None));
+            }
+            _ => panic!("Expected Search command"),
+        }
+    }
+    
+    #[test]
+    fn test_parse_search_regex() {
+        let args = vec!["cc-vault", "search", "--regex", r"fn\s+\w+\s*\("];
+        let cli = Cli::try_parse_from(args).unwrap();
+        
+        match cli.command {
+            Commands::Search { keywords, regex, .. } => {
+                assert_eq!(keywords, vec![r"fn\s+\w+\s*\("]);
+                assert!(regex);
+            }
+            _ => panic!("Expected Search command"),
+        }
+    }
+    
+    #[test]
+    fn test_search_mode_rejects_unknown_modes() {
+        assert!(matches!(search_mode("or", false).unwrap(), SearchMode::Or));
+        assert!(matches!(search_mode("or", true).unwrap(), SearchMode::Regex));
+        
+        let cli = Cli::try_parse_from(["cc-vault", "search", "--mode", "xor", "test"]).unwrap();
+        let result = cli.execute(&MemoryStore::new());
+        assert!(result.unwrap_err().to_string().contains("Unknown search mode 'xor'"));
+    }
+    
+    #[test]
+    fn test_execute_search_with_invalid_regex() {
+        let args = vec!["cc-vault", "search", "--regex", "fn("];
+        let cli = Cli::try_parse_from(args).unwrap();
+        
+        let store = MemoryStore::new();
+        
+        let result = cli.execute(&store);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Invalid regex pattern 'fn('"));
+    }
+    
+    #[test]
+    fn test_execute_search_with_invalid_dates() {
+        let store = MemoryStore::new();
+        
+        let cli = Cli::try_parse_from(["cc-vault", "search", "test", "--from", "someday"]).unwrap();
+        let result = cli.execute(&store);
+        assert!(result.unwrap_err().to_string().contains("Cannot parse date: 'someday'"));
+        
+        let cli = Cli::try_parse_from(["cc-vault", "search", "test", "--tz", "Mars/Olympus"]).unwrap();
+        let result = cli.execute(&store);
+        assert!(result.unwrap_err().to_string().contains("Unknown timezone"));
+        
+        let cli = Cli::try_parse_from([
+            "cc-vault", "search", "test", "--from", "2024-02-01", "--to", "2024-01-31", "--tz", "UTC"
+        ]).unwrap();
+        let result = cli.execute(&store);
+        assert!(result.unwrap_err().to_string().contains("must be earlier than"));
+    }
+    
+    #[test]
+    fn test_execute_search_with_date_range() {
+        let args = vec![
+            "cc-vault", "search", "test",
+            "--from", "2024-01-01",
+            "--to", "2024-01-01",
+            "--tz", "Asia/Tokyo",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        
+        let store = MemoryStore::new();
+        
+        // A single day is a valid range; the empty store returns no rows
+        let result = cli.execute(&store);
+        assert!(result.is_ok());
+    }
+    
+    #[test]
+    fn test_parse_search_context_options() {
+        let cli = Cli::try_parse_from(["cc-vault", "search", "rust", "-C", "2", "--after", "5"]).unwrap();
+        
+        match cli.command {
+            Commands::Search { before, after, context, .. } => {
+                assert_eq!(context, Some(2));
+                assert_eq!(before, None);
+                assert_eq!(after, Some(5));
             }
             _ => panic!("Expected Search command"),
         }
     }
     
+    #[test]
+    fn test_execute_search_with_context() {
+        let cli = Cli::try_parse_from(["cc-vault", "search", "rust", "--context", "1"]).unwrap();
+        
+        let store = MemoryStore::new();
+        
+        let result = cli.execute(&store);
+        assert!(result.is_ok());
+    }
+    
+    #[test]
+    fn test_parse_tools_command() {
+        let args = vec!["cc-vault", "tools", "--tool", "Bash", "--input-contains", "cargo"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        
+        match cli.command {
+            Commands::Tools { tool, input_contains, session, limit } => {
+                assert_eq!(tool, Some("Bash".to_string()));
+                assert_eq!(input_contains, Some("cargo".to_string()));
+                assert_eq!(session, None);
+                assert_eq!(limit, 20);
+            }
+            _ => panic!("Expected Tools command"),
+        }
+    }
+    
+    #[test]
+    fn test_execute_tools_command() {
+        let args = vec!["cc-vault", "tools", "--tool", "Edit"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        
+        let store = MemoryStore::new();
+        
+        let result = cli.execute(&store);
+        assert!(result.is_ok());
+    }
+    
+    #[test]
+    fn test_parse_show_command() {
+        let cli = Cli::try_parse_from(["cc-vault", "show", "session-123"]).unwrap();
+        
+        match cli.command {
+            Commands::Show { id } => assert_eq!(id, "session-123"),
+            _ => panic!("Expected Show command"),
+        }
+    }
+    
+    #[test]
+    fn test_execute_show_unknown_id() {
+        let cli = Cli::try_parse_from(["cc-vault", "show", "missing"]).unwrap();
+        
+        let store = MemoryStore::new();
+        
+        let result = cli.execute(&store);
+        assert!(result.unwrap_err().to_string().contains("No session or message found for 'missing'"));
+    }
+    
     #[test]
     fn test_parse_favorite_command() {
         let args = vec!["cc-vault", "favorite", "123"];
@@ -361,9 +1532,11 @@ mod tests {
         let cli = cli.unwrap();
         
         match cli.command {
-            Commands::Favorite { id, remove } => {
-                assert_eq!(id, 123);
-                assert_eq!(remove, false);
+            Commands::Favorite { id, remove, note, list } => {
+                assert_eq!(id.as_deref(), Some("123"));
+                assert!(!remove);
+                assert_eq!(note, None);
+                assert!(!list);
             }
             _ => panic!("Expected Favorite command"),
         }
@@ -378,9 +1551,9 @@ mod tests {
         let cli = cli.unwrap();
         
         match cli.command {
-            Commands::Favorite { id, remove } => {
-                assert_eq!(id, 456);
-                assert_eq!(remove, true);
+            Commands::Favorite { id, remove, .. } => {
+                assert_eq!(id.as_deref(), Some("456"));
+                assert!(remove);
             }
             _ => panic!("Expected Favorite command"),
         }
@@ -404,15 +1577,64 @@ mod tests {
     
     #[test]
     fn test_execute_import_command() {
-        let args = vec!["cc-vault", "import"];
-        let cli = Cli::try_parse_from(args).unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let app = dir.path().join("-work-app");
+        let other = dir.path().join("-work-other");
+        std::fs::create_dir_all(&app).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(app.join("session-1.jsonl"), [
+            line("uuid-1", None, "session-1", 0, "user", "hello".into()),
+            line("uuid-2", Some("uuid-1"), "session-1", 1, "assistant", "hi".into()),
+            "not json".to_string(),
+        ].join("\n") + "\n").unwrap();
+        std::fs::write(other.join("session-2.jsonl"), line("uuid-3", None, "session-2", 0, "user", "elsewhere".into())).unwrap();
+        let reader = ClaudeReader::with_path(dir.path().to_path_buf());
+        let cli = Cli::try_parse_from(["cc-vault", "import"]).unwrap();
+        let all = SearchQuery { limit: None, ..Default::default() };
         
-        let mut mock_conn = MockDatabaseConnection::new();
-        mock_conn.expect_is_connected()
-            .returning(|| true);
+        let store = MemoryStore::new();
+        cli.execute_import(&store, &reader, Some("-work-app"), false, false).unwrap();
+        let rows = store.export_rows(&all).unwrap();
+        let uuids: Vec<&str> = rows.iter().map(|row| row.uuid.as_str()).collect();
+        assert_eq!(uuids, vec!["uuid-1", "uuid-2"]);
+        assert!(store.import_state(&app.join("session-1.jsonl").to_string_lossy()).unwrap().is_some());
         
-        let result = cli.execute(&mock_conn);
-        assert!(result.is_ok());
+        cli.execute_import(&store, &reader, None, false, false).unwrap();
+        assert_eq!(store.export_rows(&all).unwrap().len(), 3);
+        
+        let missing = ClaudeReader::with_path(dir.path().join("missing"));
+        let result = cli.execute_import(&store, &missing, None, false, false);
+        assert!(result.unwrap_err().to_string().contains("Claude projects directory not found"));
+    }
+    
+    #[test]
+    fn test_execute_sync_command() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let app = dir.path().join("-work-app");
+        let other = dir.path().join("-work-other");
+        std::fs::create_dir_all(&app).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        let transcript = app.join("session-1.jsonl");
+        std::fs::write(&transcript, line("uuid-1", None, "session-1", 0, "user", "hello".into()) + "\n").unwrap();
+        std::fs::write(other.join("session-2.jsonl"), line("uuid-3", None, "session-2", 0, "user", "elsewhere".into()) + "\n").unwrap();
+        let reader = ClaudeReader::with_path(dir.path().to_path_buf());
+        let cli = Cli::try_parse_from(["cc-vault", "sync"]).unwrap();
+        let all = SearchQuery { limit: None, ..Default::default() };
+        
+        let store = MemoryStore::new();
+        cli.execute_sync(&store, &reader, Some("-work-app"), false).unwrap();
+        let rows = store.export_rows(&all).unwrap();
+        assert_eq!(rows.iter().map(|row| row.uuid.as_str()).collect::<Vec<_>>(), vec!["uuid-1"]);
+        
+        // A second sync picks up the appended line and the other project
+        let mut file = std::fs::OpenOptions::new().append(true).open(&transcript).unwrap();
+        writeln!(file, "{}", line("uuid-2", Some("uuid-1"), "session-1", 1, "assistant", "hi".into())).unwrap();
+        cli.execute_sync(&store, &reader, None, false).unwrap();
+        assert_eq!(store.export_rows(&all).unwrap().len(), 3);
+        
+        let missing = ClaudeReader::with_path(dir.path().join("missing"));
+        let message = cli.execute_sync(&store, &missing, None, false).unwrap_err().to_string();
+        assert!(message.contains(&dir.path().join("missing").display().to_string()));
     }
     
     #[test]
@@ -420,38 +1642,305 @@ mod tests {
         let args = vec!["cc-vault", "search", "test"];
         let cli = Cli::try_parse_from(args).unwrap();
         
-        let mut mock_conn = MockDatabaseConnection::new();
-        mock_conn.expect_is_connected()
-            .returning(|| true);
+        let store = MemoryStore::new();
         
-        let result = cli.execute(&mock_conn);
+        let result = cli.execute(&store);
         assert!(result.is_ok());
     }
-    
+
+    #[test]
+    fn test_format_search_result_highlights_snippet() {
+        let content = "エラーハンドリングには Result を使います";
+        let keywords = vec!["result".to_string()];
+        let result = SearchResult {
+            id: 7,
+            uuid: "uuid-7".to_string(),
+            session_id: "session-1".to_string(),
+            message_content: Some(content.to_string()),
+            message_role: Some("assistant".to_string()),
+            project_path: "/test".to_string(),
+            timestamp: chrono::DateTime::from_timestamp(0, 0).unwrap(),
+            rank: 1.0,
+            is_favorite: false,
+            tags: Vec::new(),
+            notes: Vec::new(),
+            snippet: Some(crate::snippet::Snippet::generate(content, &keywords, &SearchMode::And, 100)),
+        };
+
+        let plain = format_search_result(&result, false);
+        assert_eq!(plain, "- [7] 1970-01-01 00:00 assistant\n    エラーハンドリングには Result を使います");
+
+        let colored = format_search_result(&result, true);
+        assert!(colored.contains("\x1b[1;31mResult\x1b[0m"));
+    }
+
     #[test]
     fn test_execute_favorite_command() {
-        let args = vec!["cc-vault", "favorite", "123"];
-        let cli = Cli::try_parse_from(args).unwrap();
+        let store = store_with_message();
         
-        let mut mock_conn = MockDatabaseConnection::new();
-        mock_conn.expect_is_connected()
-            .returning(|| true);
+        // The conversation ID from search output is stored as the message uuid
+        let cli = Cli::try_parse_from(["cc-vault", "favorite", "1"]).unwrap();
+        cli.execute(&store).unwrap();
+        let cli = Cli::try_parse_from(["cc-vault", "favorite", "session-1", "--note", "worth rereading"]).unwrap();
+        cli.execute(&store).unwrap();
         
-        let result = cli.execute(&mock_conn);
-        assert!(result.is_ok());
+        let favorites = store.favorites().unwrap();
+        let mut targets: Vec<(Target, Option<String>)> = favorites.into_iter().map(|f| (f.target, f.note)).collect();
+        targets.sort();
+        assert_eq!(targets, vec![
+            (Target::Message("uuid-1".to_string()), None),
+            (Target::Session("session-1".to_string()), Some("worth rereading".to_string())),
+        ]);
+        
+        let cli = Cli::try_parse_from(["cc-vault", "favorite", "missing"]).unwrap();
+        assert!(cli.execute(&store).unwrap_err().to_string().contains("No session or message found for 'missing'"));
+        
+        let cli = Cli::try_parse_from(["cc-vault", "favorite", "--list"]).unwrap();
+        assert!(cli.execute(&store).is_ok());
+        assert!(Cli::try_parse_from(["cc-vault", "favorite", "--list", "1"]).is_err());
     }
     
     #[test]
     fn test_execute_favorite_remove_command() {
-        let args = vec!["cc-vault", "favorite", "456", "--remove"];
+        let args = vec!["cc-vault", "favorite", "uuid-1", "--remove"];
         let cli = Cli::try_parse_from(args).unwrap();
         
-        let mut mock_conn = MockDatabaseConnection::new();
-        mock_conn.expect_is_connected()
-            .returning(|| true);
+        let store = store_with_message();
+        store.add_favorite(&Target::Message("uuid-1".to_string()), None).unwrap();
+        // A favorite whose session is not in the vault can still be removed
+        store.add_favorite(&Target::Session("gone".to_string()), None).unwrap();
         
-        let result = cli.execute(&mock_conn);
+        let result = cli.execute(&store);
         assert!(result.is_ok());
+        let cli = Cli::try_parse_from(["cc-vault", "favorite", "gone", "--remove"]).unwrap();
+        cli.execute(&store).unwrap();
+        assert!(store.favorites().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_parse_tag_commands() {
+        let cli = Cli::try_parse_from(["cc-vault", "tag", "add", "uuid-1", "postmortem", "snippet"]).unwrap();
+        match cli.command {
+            Commands::Tag { action: TagAction::Add { id, tags } } => {
+                assert_eq!(id, "uuid-1");
+                assert_eq!(tags, vec!["postmortem", "snippet"]);
+            }
+            _ => panic!("Expected tag add"),
+        }
+        
+        let cli = Cli::try_parse_from(["cc-vault", "search", "--tag", "a", "--tag", "b", "--tag-mode", "or"]).unwrap();
+        match cli.command {
+            Commands::Search { keywords, tags, tag_mode, .. } => {
+                assert!(keywords.is_empty());
+                assert_eq!(tags, vec!["a", "b"]);
+                assert_eq!(tag_mode, "or");
+            }
+            _ => panic!("Expected Search command"),
+        }
+        
+        assert!(Cli::try_parse_from(["cc-vault", "tag", "add", "uuid-1"]).is_err());
+        assert!(Cli::try_parse_from(["cc-vault", "tag", "list"]).is_ok());
+    }
+    
+    #[test]
+    fn test_execute_tag_commands() {
+        let store = store_with_message();
+        let run = |args: &[&str]| Cli::try_parse_from(args).unwrap().execute(&store);
+        
+        run(&["cc-vault", "tag", "add", "1", "Snippet"]).unwrap();
+        run(&["cc-vault", "tag", "add", "session-1", "postmortem", "good-prompt"]).unwrap();
+        assert_eq!(store.tags_of(&Target::Message("uuid-1".to_string())).unwrap(), vec!["snippet"]);
+        assert!(run(&["cc-vault", "tag", "add", "missing", "x"]).is_err());
+        assert!(run(&["cc-vault", "tag", "add", "1", "two words"]).is_err());
+        run(&["cc-vault", "tag", "list", "uuid-1"]).unwrap();
+        run(&["cc-vault", "tag", "list"]).unwrap();
+        
+        // Session tags reach the session's messages
+        let query = SearchQuery { tags: vec!["postmortem".to_string(), "snippet".to_string()], ..Default::default() };
+        let results = store.search(&query).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].tags, vec!["good-prompt", "postmortem", "snippet"]);
+        assert!(format_search_result(&results[0], false).contains("user #good-prompt #postmortem #snippet"));
+        
+        run(&["cc-vault", "tag", "remove", "session-1", "good-prompt"]).unwrap();
+        run(&["cc-vault", "tag", "remove", "uuid-1", "snippet"]).unwrap();
+        assert!(store.search(&query).unwrap().is_empty());
+        assert_eq!(store.tag_counts().unwrap(), vec![TagCount { tag: "postmortem".to_string(), count: 1 }]);
+    }
+    
+    #[test]
+    fn test_edit_with_editor() {
+        let edited = edit_with("sed -i 's/lock/DuckDB lock/'", "how we fixed the lock issue\n").unwrap();
+        assert_eq!(edited, "how we fixed the DuckDB lock issue");
+        
+        let err = edit_with("false", "unchanged").unwrap_err();
+        assert!(err.to_string().contains("note not saved"));
+    }
+    
+    #[test]
+    fn test_execute_note_commands() {
+        let store = store_with_message();
+        let run = |args: &[&str]| Cli::try_parse_from(args).unwrap().execute(&store);
+        
+        run(&["cc-vault", "note", "add", "session-1", "how", "we", "fixed", "it"]).unwrap();
+        let session = Target::Session("session-1".to_string());
+        assert_eq!(store.note(&session).unwrap().unwrap().body, "how we fixed it");
+        let err = run(&["cc-vault", "note", "add", "session-1", "again"]).unwrap_err();
+        assert!(err.to_string().contains("already has a note"));
+        assert!(run(&["cc-vault", "note", "add", "missing", "text"]).is_err());
+        
+        run(&["cc-vault", "note", "list", "1"]).unwrap();
+        run(&["cc-vault", "note", "list"]).unwrap();
+        assert!(Cli::try_parse_from(["cc-vault", "note", "edit"]).is_err());
+        
+        // Notes are searchable and come back with the hit
+        let results = store.search(&SearchQuery { keywords: vec!["fixed".to_string()], ..Default::default() }).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].notes, vec!["how we fixed it"]);
+        assert!(format_search_result(&results[0], false).ends_with("\n    note: how we fixed it"));
+    }
+    
+    #[test]
+    fn test_execute_export_command() {
+        let store = store_with_message();
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("exports");
+        
+        let cli = Cli::try_parse_from(["cc-vault", "export", "hello", "--out", out.to_str().unwrap()]).unwrap();
+        cli.execute(&store).unwrap();
+        let markdown = std::fs::read_to_string(out.join("session-1.md")).unwrap();
+        assert!(markdown.starts_with("# Session session-1\n"));
+        assert!(markdown.contains("## User"));
+        
+        let cli = Cli::try_parse_from(["cc-vault", "export", "session-1", "--format", "pdf"]).unwrap();
+        assert!(cli.execute(&store).unwrap_err().to_string().contains("Unknown export format 'pdf'"));
+        let cli = Cli::try_parse_from(["cc-vault", "export"]).unwrap();
+        assert!(cli.execute(&store).unwrap_err().to_string().contains("Markdown export needs"));
+    }
+    
+    #[test]
+    fn test_execute_export_rows_command() {
+        let store = store_with_message();
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("vault.ndjson");
+        
+        // Without keywords the whole vault is exported
+        let cli = Cli::try_parse_from(["cc-vault", "export", "--format", "ndjson", "--out", out.to_str().unwrap()]).unwrap();
+        cli.execute(&store).unwrap();
+        let ndjson = std::fs::read_to_string(&out).unwrap();
+        let rows: Vec<ExportRow> = ndjson.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(rows.iter().map(|r| r.uuid.as_str()).collect::<Vec<_>>(), vec!["uuid-1"]);
+        
+        let cli = Cli::try_parse_from(["cc-vault", "export", "nothing", "--format", "json", "--out", out.to_str().unwrap()]).unwrap();
+        cli.execute(&store).unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "[]\n");
+        
+        let cli = Cli::try_parse_from(["cc-vault", "export", "--format", "parquet"]).unwrap();
+        assert!(cli.execute(&store).unwrap_err().to_string().contains("parquet exports need --out"));
+    }
+    
+    #[test]
+    fn test_execute_export_obsidian_command() {
+        let store = store_with_message();
+        let dir = tempfile::tempdir().unwrap();
+        
+        let cli = Cli::try_parse_from(["cc-vault", "export", "--format", "obsidian", "--out", dir.path().to_str().unwrap()]).unwrap();
+        cli.execute(&store).unwrap();
+        cli.execute(&store).unwrap();
+        let note = std::fs::read_to_string(dir.path().join("test/hello (session-).md")).unwrap();
+        assert!(note.starts_with("---\nsession_id: \"session-1\"\n"));
+        
+        let cli = Cli::try_parse_from(["cc-vault", "export", "--format", "obsidian"]).unwrap();
+        assert!(cli.execute(&store).unwrap_err().to_string().contains("obsidian exports need --out"));
+    }
+    
+    #[test]
+    fn test_execute_restore_jsonl_command() {
+        use crate::raw_archive::RawSource;
+
+        let store = store_with_message();
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("projects/-test/session-1.jsonl");
+        let content = b"{\"sessionId\":\"session-1\",\"uuid\":\"uuid-1\",\"requestId\":\"req_1\"}\n\n".to_vec();
+        store.archive_raw(&RawSource::new(&source, "/test"), 0, &mut &content[..]).unwrap();
+        let restore = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["cc-vault", "restore-jsonl"].iter().chain(args)).unwrap();
+            cli.execute(&store)
+        };
+
+        // By default the file goes back where it was archived from
+        restore(&["session-1"]).unwrap();
+        assert_eq!(std::fs::read(&source).unwrap(), content);
+
+        // Message UUIDs resolve to their session, and directories get the session's file name
+        restore(&["uuid-1", "--out", dir.path().to_str().unwrap()]).unwrap();
+        assert_eq!(std::fs::read(dir.path().join("session-1.jsonl")).unwrap(), content);
+
+        std::fs::write(&source, "edited\n").unwrap();
+        assert!(restore(&["session-1"]).unwrap_err().to_string().contains("--force"));
+        assert_eq!(std::fs::read(&source).unwrap(), b"edited\n");
+        restore(&["session-1", "--force"]).unwrap();
+        assert_eq!(std::fs::read(&source).unwrap(), content);
+        // Nothing is left of the file the lines were streamed to
+        assert!(!source.with_file_name("session-1.jsonl.restoring").exists());
+
+        assert!(restore(&["missing"]).unwrap_err().to_string().contains("No raw transcript"));
+    }
+
+    #[test]
+    fn test_execute_site_command() {
+        let store = store_with_message();
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("vault-site");
+        
+        let cli = Cli::try_parse_from(["cc-vault", "site", "--out", out.to_str().unwrap()]).unwrap();
+        cli.execute(&store).unwrap();
+        assert!(out.join("index.html").exists());
+        assert!(out.join("sessions/session-1.html").exists());
+        assert!(Cli::try_parse_from(["cc-vault", "site"]).is_err());
+    }
+    
+    #[test]
+    fn test_parse_migrate_command() {
+        let cli = Cli::try_parse_from(["cc-vault", "migrate", "--to", "3"]).unwrap();
+        match cli.command {
+            Commands::Migrate { status, to } => {
+                assert!(!status);
+                assert_eq!(to, Some(3));
+            }
+            _ => panic!("Expected Migrate command"),
+        }
+        
+        assert!(Cli::try_parse_from(["cc-vault", "migrate", "--status", "--to", "3"]).is_err());
+    }
+    
+    #[test]
+    fn test_execute_migrate_command() {
+        use crate::db_connection::{ConnectionConfig, DatabaseConnection};
+        use crate::real_db_connection::RealDuckDBConnection;
+        
+        let conn = RealDuckDBConnection::new(ConnectionConfig {
+            database: ":memory:".to_string(),
+            ..Default::default()
+        });
+        conn.connect().unwrap();
+        let migrator = Migrator::new(&conn);
+        
+        let cli = Cli::try_parse_from(["cc-vault", "migrate"]).unwrap();
+        cli.execute_migrate(&migrator).unwrap();
+        assert_eq!(migrator.current_version().unwrap(), latest_version());
+        
+        let cli = Cli::try_parse_from(["cc-vault", "migrate", "--to", "2"]).unwrap();
+        cli.execute_migrate(&migrator).unwrap();
+        assert_eq!(migrator.current_version().unwrap(), 2);
+        
+        let cli = Cli::try_parse_from(["cc-vault", "migrate", "--status"]).unwrap();
+        cli.execute_migrate(&migrator).unwrap();
+        assert_eq!(migrator.current_version().unwrap(), 2);
+        
+        // Other commands go through the store, not the migrator
+        let cli = Cli::try_parse_from(["cc-vault", "show", "session-1"]).unwrap();
+        assert!(cli.execute_migrate(&migrator).is_err());
     }
     
     #[test]
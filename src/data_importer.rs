@@ -1,34 +1,151 @@
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
-use crate::db_connection::DatabaseConnection;
-use crate::jsonl_parser::ClaudeMessage;
+use crate::cjk;
+use duckdb::params;
+use crate::db_connection::SqlValue;
+use crate::jsonl_parser::{ContentBlock, JsonlRecord};
 use crate::real_db_connection::ExtendedDatabaseConnection;
 
-#[allow(dead_code)]
-pub const INSERT_CONVERSATION: &str = r#"
-INSERT INTO conversations (
-    uuid, parent_uuid, session_id, user_type, message_type, 
-    message_role, message_content, project_path, cwd, git_branch, 
-    version, timestamp, is_favorite
-) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-"#;
-
-#[allow(dead_code)]
-pub const CHECK_UUID_EXISTS: &str = "SELECT COUNT(*) as count FROM conversations WHERE uuid = ?";
-
-#[allow(dead_code)]
-pub const UPDATE_CONVERSATION: &str = r#"
-UPDATE conversations SET 
-    parent_uuid = ?, session_id = ?, user_type = ?, message_type = ?,
-    message_role = ?, message_content = ?, project_path = ?, cwd = ?, 
-    git_branch = ?, version = ?, timestamp = ?, updated_at = CURRENT_TIMESTAMP
-WHERE uuid = ?
-"#;
-
-#[allow(dead_code)]
 pub const GET_LAST_UPDATE_TIME: &str = 
-    "SELECT MAX(timestamp) as last_update FROM conversations WHERE project_path = ?";
-
+    "SELECT epoch_us(MAX(timestamp)) as last_update FROM conversations WHERE project_path = ?";
+
+/// Per-batch staging tables filled through the Appender by `bulk_import`.
+/// `seq` is a record's position in the batch, so that when a uuid repeats
+/// the last copy wins.
+const CREATE_STAGING_TABLES: &str = r#"
+CREATE OR REPLACE TEMP TABLE staged_conversations (
+    seq BIGINT, uuid TEXT, parent_uuid TEXT, session_id TEXT, user_type TEXT,
+    message_type TEXT, message_role TEXT, message_content TEXT, content_text TEXT,
+    project_path TEXT, cwd TEXT, git_branch TEXT, version TEXT, timestamp_us BIGINT, extra TEXT,
+    is_sidechain BOOLEAN, message_extra TEXT
+);
+CREATE OR REPLACE TEMP TABLE staged_content_blocks (
+    seq BIGINT, message_uuid TEXT, block_index INTEGER, block_type TEXT,
+    tool_name TEXT, tool_use_id TEXT, block_text TEXT
+);
+CREATE OR REPLACE TEMP TABLE staged_cjk_bigrams (
+    seq BIGINT, message_uuid TEXT, gram TEXT, occurrences INTEGER
+);
+CREATE OR REPLACE TEMP TABLE staged_tool_uses (
+    seq BIGINT, tool_use_id TEXT, tool_name TEXT, input_json TEXT,
+    session_id TEXT, message_uuid TEXT, timestamp_us BIGINT
+);
+CREATE OR REPLACE TEMP TABLE staged_tool_results (
+    seq BIGINT, tool_use_id TEXT, result_text TEXT, is_error BOOLEAN, result_uuid TEXT
+);
+CREATE OR REPLACE TEMP TABLE staged_summaries (
+    leaf_uuid TEXT, summary TEXT, extra TEXT
+)"#;
+
+/// The last copy of every staged message, flagged by whether it already
+/// exists and whether it differs from what is stored. `session_id`,
+/// `project_path` and `timestamp` are left out of the comparison because the
+/// upsert cannot change them (see `MERGE_CONVERSATIONS`).
+const CREATE_IMPORT_BATCH: &str = r#"
+CREATE OR REPLACE TEMP TABLE import_batch AS
+SELECT s.*,
+       c.uuid IS NOT NULL AS existed,
+       c.uuid IS NOT NULL AND NOT {force}
+           AND c.parent_uuid IS NOT DISTINCT FROM s.parent_uuid
+           AND c.user_type IS NOT DISTINCT FROM s.user_type
+           AND c.message_type IS NOT DISTINCT FROM s.message_type
+           AND c.message_role IS NOT DISTINCT FROM s.message_role
+           AND c.message_content IS NOT DISTINCT FROM s.message_content
+           AND c.content_text IS NOT DISTINCT FROM s.content_text
+           AND c.cwd IS NOT DISTINCT FROM s.cwd
+           AND c.git_branch IS NOT DISTINCT FROM s.git_branch
+           AND c.version IS NOT DISTINCT FROM s.version
+           AND c.extra IS NOT DISTINCT FROM s.extra
+           AND c.is_sidechain IS NOT DISTINCT FROM s.is_sidechain
+           AND c.message_extra IS NOT DISTINCT FROM s.message_extra AS unchanged
+FROM (
+    SELECT * FROM staged_conversations
+    QUALIFY row_number() OVER (PARTITION BY uuid ORDER BY seq DESC) = 1
+) s
+LEFT JOIN conversations c ON c.uuid = s.uuid"#;
+
+const COUNT_IMPORT_BATCH: &str = r#"
+SELECT count(*) FILTER (WHERE NOT existed),
+       count(*) FILTER (WHERE existed AND NOT unchanged),
+       (SELECT count(*) FROM staged_conversations) - count(*) FILTER (WHERE NOT existed OR NOT unchanged)
+FROM import_batch"#;
+
+/// DuckDB cannot assign to indexed columns in `DO UPDATE`, so `session_id`,
+/// `project_path` and `timestamp` keep their first imported values. They do
+/// not change for a given uuid in practice.
+const MERGE_CONVERSATIONS: &str = r#"
+INSERT INTO conversations (
+    uuid, parent_uuid, session_id, user_type, message_type, message_role, message_content,
+    content_text, project_path, cwd, git_branch, version, timestamp, extra, is_sidechain, message_extra
+)
+SELECT uuid, parent_uuid, session_id, user_type, message_type, message_role, message_content,
+       content_text, project_path, cwd, git_branch, version, make_timestamp(timestamp_us), extra,
+       is_sidechain, message_extra
+FROM import_batch
+WHERE NOT unchanged
+ORDER BY seq
+ON CONFLICT (uuid) DO UPDATE SET
+    parent_uuid = excluded.parent_uuid, user_type = excluded.user_type,
+    message_type = excluded.message_type, message_role = excluded.message_role,
+    message_content = excluded.message_content, content_text = excluded.content_text,
+    cwd = excluded.cwd, git_branch = excluded.git_branch, version = excluded.version,
+    extra = excluded.extra, is_sidechain = excluded.is_sidechain,
+    message_extra = excluded.message_extra, updated_at = now()"#;
+
+/// Blocks, grams and tool calls of new or changed messages, dropped and
+/// rewritten so nothing stale is left behind.
+const MERGE_SIDE_TABLES: &str = r#"
+DELETE FROM content_blocks
+WHERE message_uuid IN (SELECT uuid FROM import_batch WHERE existed AND NOT unchanged);
+INSERT INTO content_blocks (message_uuid, block_index, block_type, tool_name, tool_use_id, block_text)
+SELECT b.message_uuid, b.block_index, b.block_type, b.tool_name, b.tool_use_id, b.block_text
+FROM staged_content_blocks b JOIN import_batch i ON i.uuid = b.message_uuid AND i.seq = b.seq
+WHERE NOT i.unchanged;
+DELETE FROM cjk_bigrams
+WHERE message_uuid IN (SELECT uuid FROM import_batch WHERE existed AND NOT unchanged);
+INSERT INTO cjk_bigrams (message_uuid, gram, occurrences)
+SELECT g.message_uuid, g.gram, g.occurrences
+FROM staged_cjk_bigrams g JOIN import_batch i ON i.uuid = g.message_uuid AND i.seq = g.seq
+WHERE NOT i.unchanged;
+UPDATE tool_calls SET result_text = NULL, is_error = NULL, result_uuid = NULL
+WHERE result_uuid IN (SELECT uuid FROM import_batch WHERE existed AND NOT unchanged);
+DELETE FROM tool_calls
+WHERE message_uuid IN (SELECT uuid FROM import_batch WHERE existed AND NOT unchanged)
+   OR (message_uuid IS NULL AND result_uuid IS NULL);
+INSERT INTO tool_calls (tool_use_id, tool_name, input_json, session_id, message_uuid, timestamp)
+SELECT u.tool_use_id, u.tool_name, u.input_json, u.session_id, u.message_uuid, make_timestamp(u.timestamp_us)
+FROM staged_tool_uses u JOIN import_batch i ON i.uuid = u.message_uuid AND i.seq = u.seq
+WHERE NOT i.unchanged
+QUALIFY row_number() OVER (PARTITION BY u.tool_use_id ORDER BY u.seq DESC) = 1
+ON CONFLICT (tool_use_id) DO UPDATE SET tool_name = excluded.tool_name, input_json = excluded.input_json,
+    session_id = excluded.session_id, message_uuid = excluded.message_uuid, timestamp = excluded.timestamp;
+INSERT INTO tool_calls (tool_use_id, result_text, is_error, result_uuid)
+SELECT r.tool_use_id, r.result_text, r.is_error, r.result_uuid
+FROM staged_tool_results r JOIN import_batch i ON i.uuid = r.result_uuid AND i.seq = r.seq
+WHERE NOT i.unchanged
+QUALIFY row_number() OVER (PARTITION BY r.tool_use_id ORDER BY r.seq DESC) = 1
+ON CONFLICT (tool_use_id) DO UPDATE SET result_text = excluded.result_text,
+    is_error = excluded.is_error, result_uuid = excluded.result_uuid"#;
+
+const COUNT_NEW_SUMMARIES: &str = r#"
+SELECT count(*) FROM (SELECT DISTINCT leaf_uuid, summary FROM staged_summaries) s
+WHERE NOT EXISTS (SELECT 1 FROM summaries t WHERE t.leaf_uuid = s.leaf_uuid AND t.summary = s.summary)"#;
+
+const MERGE_SUMMARIES: &str = r#"
+INSERT INTO summaries (leaf_uuid, summary, extra)
+SELECT leaf_uuid, summary, any_value(extra) FROM staged_summaries GROUP BY leaf_uuid, summary
+ON CONFLICT DO NOTHING"#;
+
+const DROP_STAGING_TABLES: &str = r#"
+DROP TABLE IF EXISTS import_batch;
+DROP TABLE IF EXISTS staged_conversations;
+DROP TABLE IF EXISTS staged_content_blocks;
+DROP TABLE IF EXISTS staged_cjk_bigrams;
+DROP TABLE IF EXISTS staged_tool_uses;
+DROP TABLE IF EXISTS staged_tool_results;
+DROP TABLE IF EXISTS staged_summaries"#;
+
+#[derive(Debug, Default, PartialEq)]
 #[allow(dead_code)]
 pub struct ImportStats {
     pub inserted: usize,
@@ -53,353 +170,216 @@ impl ImportStats {
     }
 }
 
-pub struct DataImporter<'a> {
-    connection: &'a dyn DatabaseConnection,
+/// Imports batches of transcript records, staging each batch and merging it
+/// in one transaction.
+pub struct DataImporter<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
 }
 
-impl<'a> DataImporter<'a> {
-    pub fn new(connection: &'a dyn DatabaseConnection) -> Self {
+impl<'a, C: ExtendedDatabaseConnection> DataImporter<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
         Self { connection }
     }
     
-    fn escape_sql_string(s: &str) -> String {
-        s.replace('\'', "''")
-    }
-
-    pub fn import_single_conversation(&self, message: &ClaudeMessage, project_path: &str) -> Result<()> {
-        if !self.connection.is_connected() {
-            return Err(anyhow!("Database not connected"));
-        }
-
-        // Extract message content as JSON string
-        let message_content = message.message.content.as_ref()
-            .map(|v| serde_json::to_string(v).unwrap_or_default());
-
-        // For now, we'll use the execute method with a formatted query
-        // In a real implementation, we'd use prepared statements
-        let query = format!(
-            "INSERT INTO conversations (uuid, parent_uuid, session_id, user_type, message_type, message_role, message_content, project_path, cwd, git_branch, version, timestamp, is_favorite) VALUES ('{}', {}, '{}', '{}', '{}', {}, {}, '{}', '{}', {}, '{}', '{}', {})",
-            Self::escape_sql_string(&message.uuid),
-            message.parent_uuid.as_ref().map(|s| format!("'{}'", Self::escape_sql_string(s))).unwrap_or("NULL".to_string()),
-            Self::escape_sql_string(&message.session_id),
-            Self::escape_sql_string(&message.user_type),
-            Self::escape_sql_string(&message.message_type),
-            message.message.role.as_ref().map(|s| format!("'{}'", Self::escape_sql_string(s))).unwrap_or("NULL".to_string()),
-            message_content.as_ref().map(|s| format!("'{}'", Self::escape_sql_string(s))).unwrap_or("NULL".to_string()),
-            Self::escape_sql_string(project_path),
-            Self::escape_sql_string(&message.cwd),
-            message.git_branch.as_ref().map(|s| format!("'{}'", Self::escape_sql_string(s))).unwrap_or("NULL".to_string()),
-            Self::escape_sql_string(&message.version),
-            Self::escape_sql_string(&message.timestamp.to_rfc3339()),
-            false
-        );
-
-        self.connection.execute(&query)?;
-        Ok(())
-    }
-
-    pub fn check_uuid_exists(&self, uuid: &str) -> Result<bool> {
-        if !self.connection.is_connected() {
-            return Err(anyhow!("Database not connected"));
-        }
-
-        // Check if the connection is RealDuckDBConnection which implements ExtendedDatabaseConnection
-        if let Some(extended_conn) = self.connection.as_any().downcast_ref::<crate::real_db_connection::RealDuckDBConnection>() {
-            let query = format!(
-                "SELECT COUNT(*) as count FROM conversations WHERE uuid = '{}'",
-                Self::escape_sql_string(uuid)
-            );
-            
-            let count: Option<i32> = extended_conn.query_row(&query, |row| {
-                Ok(row.get(0)?)
-            })?;
-            
-            Ok(count.unwrap_or(0) > 0)
+    /// Unmodelled record fields as a JSON object, or `None` when there are none.
+    fn extra_json(extra: &serde_json::Map<String, serde_json::Value>) -> Option<String> {
+        if extra.is_empty() {
+            None
         } else {
-            // Fallback: always return false if extended connection not available
-            Ok(false)
+            serde_json::to_string(extra).ok()
         }
     }
 
-    pub fn update_conversation(&self, message: &ClaudeMessage, project_path: &str) -> Result<()> {
+    /// Imports a batch of records, typically one file, in a single
+    /// transaction: rows are staged through the Appender and merged with one
+    /// upsert per table. Messages identical to the stored copy are skipped
+    /// unless `force` is set; `Err` items are counted as errors.
+    pub fn bulk_import<I>(&self, records: I, project_path: &str, force: bool) -> Result<ImportStats>
+    where
+        I: IntoIterator<Item = Result<JsonlRecord>>,
+    {
         if !self.connection.is_connected() {
             return Err(anyhow!("Database not connected"));
         }
 
-        // Extract message content as JSON string
-        let message_content = message.message.content.as_ref()
-            .map(|v| serde_json::to_string(v).unwrap_or_default());
-
-        let query = format!(
-            "UPDATE conversations SET parent_uuid = {}, session_id = '{}', user_type = '{}', message_type = '{}', message_role = {}, message_content = {}, project_path = '{}', cwd = '{}', git_branch = {}, version = '{}', timestamp = '{}', updated_at = CURRENT_TIMESTAMP WHERE uuid = '{}'",
-            message.parent_uuid.as_ref().map(|s| format!("'{}'", Self::escape_sql_string(s))).unwrap_or("NULL".to_string()),
-            Self::escape_sql_string(&message.session_id),
-            Self::escape_sql_string(&message.user_type),
-            Self::escape_sql_string(&message.message_type),
-            message.message.role.as_ref().map(|s| format!("'{}'", Self::escape_sql_string(s))).unwrap_or("NULL".to_string()),
-            message_content.as_ref().map(|s| format!("'{}'", Self::escape_sql_string(s))).unwrap_or("NULL".to_string()),
-            Self::escape_sql_string(project_path),
-            Self::escape_sql_string(&message.cwd),
-            message.git_branch.as_ref().map(|s| format!("'{}'", Self::escape_sql_string(s))).unwrap_or("NULL".to_string()),
-            Self::escape_sql_string(&message.version),
-            Self::escape_sql_string(&message.timestamp.to_rfc3339()),
-            Self::escape_sql_string(&message.uuid)
-        );
-
-        self.connection.execute(&query)?;
-        Ok(())
+        self.connection.transaction(|conn| {
+            conn.execute_batch(CREATE_STAGING_TABLES)?;
+            let mut stats = Self::stage_records(conn, records, project_path)?;
+
+            conn.execute_batch(&CREATE_IMPORT_BATCH.replace("{force}", &force.to_string()))?;
+            let (inserted, updated, skipped): (i64, i64, i64) = conn.query_row(
+                COUNT_IMPORT_BATCH, [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?;
+            conn.execute_batch(MERGE_CONVERSATIONS)?;
+            conn.execute_batch(MERGE_SIDE_TABLES)?;
+
+            let staged_summaries: i64 = conn.query_row("SELECT count(*) FROM staged_summaries", [], |row| row.get(0))?;
+            let new_summaries: i64 = conn.query_row(COUNT_NEW_SUMMARIES, [], |row| row.get(0))?;
+            conn.execute_batch(MERGE_SUMMARIES)?;
+            conn.execute_batch(DROP_STAGING_TABLES)?;
+
+            stats.inserted += (inserted + new_summaries) as usize;
+            stats.updated += updated as usize;
+            stats.skipped += (skipped + staged_summaries - new_summaries) as usize;
+            Ok(stats)
+        })
     }
 
-    pub fn import_with_duplicate_check(&self, message: &ClaudeMessage, project_path: &str) -> Result<ImportAction> {
-        // Check if UUID already exists
-        if self.check_uuid_exists(&message.uuid)? {
-            // UUID exists, update the conversation
-            self.update_conversation(message, project_path)?;
-            Ok(ImportAction::Updated)
-        } else {
-            // UUID doesn't exist, insert new conversation
-            match self.import_single_conversation(message, project_path) {
-                Ok(_) => Ok(ImportAction::Inserted),
-                Err(e) => {
-                    // If it's still a duplicate key error (race condition), try to update
-                    if e.to_string().contains("Duplicate key") {
-                        self.update_conversation(message, project_path)?;
-                        Ok(ImportAction::Updated)
-                    } else {
-                        // Re-throw other errors
-                        Err(e)
+    /// Appends every record to the staging tables. The returned stats count
+    /// only what is settled before the merge: unusable records and errors.
+    fn stage_records<I>(conn: &duckdb::Connection, records: I, project_path: &str) -> Result<ImportStats>
+    where
+        I: IntoIterator<Item = Result<JsonlRecord>>,
+    {
+        let mut conversations = conn.appender("staged_conversations")?;
+        let mut blocks = conn.appender("staged_content_blocks")?;
+        let mut grams = conn.appender("staged_cjk_bigrams")?;
+        let mut tool_uses = conn.appender("staged_tool_uses")?;
+        let mut tool_results = conn.appender("staged_tool_results")?;
+        let mut summaries = conn.appender("staged_summaries")?;
+        let mut stats = ImportStats::new();
+
+        for (seq, record) in records.into_iter().enumerate() {
+            let seq = seq as i64;
+            let message = match record {
+                Ok(JsonlRecord::Message(message)) | Ok(JsonlRecord::System(message)) => message,
+                Ok(JsonlRecord::Summary(summary)) => {
+                    match summary.leaf_uuid.as_deref() {
+                        Some(leaf_uuid) => summaries.append_row(params![
+                            leaf_uuid, summary.summary, Self::extra_json(&summary.extra),
+                        ])?,
+                        None => stats.skipped += 1,
                     }
+                    continue;
+                }
+                Ok(JsonlRecord::Unknown { .. }) => {
+                    stats.skipped += 1;
+                    continue;
+                }
+                Err(_) => {
+                    stats.errors += 1;
+                    continue;
+                }
+            };
+
+            let content_text = message.message.plain_text();
+            let timestamp_us = message.timestamp.timestamp_micros();
+            conversations.append_row(params![
+                seq,
+                message.uuid,
+                message.parent_uuid,
+                message.session_id,
+                message.user_type,
+                message.message_type,
+                message.message.role,
+                message.message.content.as_ref().map(|v| v.to_string()),
+                content_text,
+                project_path,
+                message.cwd,
+                message.git_branch,
+                message.version,
+                timestamp_us,
+                Self::extra_json(&message.extra),
+                message.is_sidechain,
+                message.message.metadata_json(),
+            ])?;
+
+            for (index, block) in message.message.blocks().iter().enumerate() {
+                blocks.append_row(params![
+                    seq,
+                    message.uuid,
+                    index as i64,
+                    block.block_type(),
+                    block.tool_name(),
+                    block.tool_use_id(),
+                    block.text(),
+                ])?;
+
+                match block {
+                    ContentBlock::ToolUse { id, name, input } if !id.is_empty() => tool_uses.append_row(params![
+                        seq, id, name, input.to_string(), message.session_id, message.uuid, timestamp_us,
+                    ])?,
+                    ContentBlock::ToolResult { tool_use_id, content, is_error } if !tool_use_id.is_empty() => tool_results.append_row(params![
+                        seq, tool_use_id, content, is_error, message.uuid,
+                    ])?,
+                    _ => {}
                 }
             }
-        }
-    }
-
-    #[allow(dead_code)]
-    pub fn bulk_import(&self, messages: &[ClaudeMessage], project_path: &str) -> Result<ImportStats> {
-        let mut stats = ImportStats::new();
 
-        for message in messages {
-            match self.import_with_duplicate_check(message, project_path) {
-                Ok(ImportAction::Inserted) => stats.inserted += 1,
-                Ok(ImportAction::Updated) => stats.updated += 1,
-                Ok(ImportAction::Skipped) => stats.skipped += 1,
-                Err(_) => stats.errors += 1,
+            for (gram, occurrences) in cjk::gram_counts(content_text.as_deref().unwrap_or_default()) {
+                grams.append_row(params![seq, message.uuid, gram, occurrences as i64])?;
             }
         }
 
+        for appender in [&mut conversations, &mut blocks, &mut grams, &mut tool_uses, &mut tool_results, &mut summaries] {
+            appender.flush()?;
+        }
         Ok(stats)
     }
 
+    /// Timestamp of the newest message imported for `project_path`.
     #[allow(dead_code)]
-    pub fn get_last_update_time(&self, _project_path: &str) -> Result<Option<DateTime<Utc>>> {
+    pub fn get_last_update_time(&self, project_path: &str) -> Result<Option<DateTime<Utc>>> {
         if !self.connection.is_connected() {
             return Err(anyhow!("Database not connected"));
         }
 
-        // Mock implementation
-        Ok(None)
-    }
-}
+        let last_us: Option<Option<i64>> = self.connection.query_row(
+            GET_LAST_UPDATE_TIME,
+            &[SqlValue::Text(project_path.to_string())],
+            |row| Ok(row.get(0)?),
+        )?;
 
-#[derive(Debug, PartialEq)]
-#[allow(dead_code)]
-pub enum ImportAction {
-    Inserted,
-    Updated,
-    Skipped,
+        last_us.flatten()
+            .map(|us| DateTime::from_timestamp_micros(us).ok_or_else(|| anyhow!("Invalid timestamp: {}", us)))
+            .transpose()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db_connection::MockDatabaseConnection;
-    use crate::jsonl_parser::MessageContent;
+    use crate::test_support::{message, setup_db};
+    use crate::real_db_connection::RealDuckDBConnection;
+    use crate::jsonl_parser::{ClaudeMessage, JsonlParser, SummaryRecord};
 
     fn create_test_message() -> ClaudeMessage {
-        ClaudeMessage {
-            parent_uuid: None,
-            is_sidechain: false,
-            user_type: "external".to_string(),
-            cwd: "/test/path".to_string(),
-            session_id: "session123".to_string(),
-            version: "1.0.0".to_string(),
-            git_branch: Some("main".to_string()),
-            message_type: "user".to_string(),
-            message: MessageContent {
-                role: Some("user".to_string()),
-                content: Some(serde_json::json!("Test message")),
-                id: None,
-                content_type: None,
-                model: None,
-            },
-            uuid: "test-uuid-123".to_string(),
-            timestamp: Utc::now(),
-        }
+        message("test-uuid-123", None, "session123", "user", serde_json::json!("Test message"), chrono::Duration::zero())
     }
 
-    #[test]
-    fn test_insert_single_conversation() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        mock_conn.expect_is_connected()
-            .times(1)
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .times(1)
-            .returning(|_| Ok(()));
-        
-        let importer = DataImporter::new(&mock_conn);
-        let message = create_test_message();
-        let result = importer.import_single_conversation(&message, "/test/project");
-        
-        assert!(result.is_ok());
+    fn count(conn: &RealDuckDBConnection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT count(*) FROM {}", table), &[], |row| Ok(row.get(0)?))
+            .unwrap()
+            .unwrap()
     }
 
-    #[test]
-    fn test_insert_when_not_connected() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        mock_conn.expect_is_connected()
-            .times(1)
-            .returning(|| false);
-        
-        let importer = DataImporter::new(&mock_conn);
-        let message = create_test_message();
-        let result = importer.import_single_conversation(&message, "/test/project");
-        
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("Database not connected"));
+    fn unconnected_db() -> RealDuckDBConnection {
+        RealDuckDBConnection::new(crate::db_connection::ConnectionConfig {
+            database: ":memory:".to_string(),
+            ..Default::default()
+        })
     }
 
-    #[test]
-    fn test_check_uuid_exists() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        mock_conn.expect_is_connected()
-            .times(1)
-            .returning(|| true);
-        
-        let importer = DataImporter::new(&mock_conn);
-        let result = importer.check_uuid_exists("test-uuid");
-        
-        assert!(result.is_ok());
-        assert!(!result.unwrap()); // Mock always returns false
-    }
-
-    #[test]
-    fn test_update_conversation() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        mock_conn.expect_is_connected()
-            .times(1)
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .times(1)
-            .returning(|_| Ok(()));
-        
-        let importer = DataImporter::new(&mock_conn);
-        let message = create_test_message();
-        let result = importer.update_conversation(&message, "/test/project");
-        
-        assert!(result.is_ok());
+    /// Imports `messages` as one batch.
+    fn import(importer: &DataImporter<RealDuckDBConnection>, messages: &[ClaudeMessage], project_path: &str) -> ImportStats {
+        let records = messages.iter().map(|message| Ok(JsonlRecord::Message(message.clone())));
+        importer.bulk_import(records, project_path, false).unwrap()
     }
 
     #[test]
-    fn test_import_with_duplicate_check_insert() {
-        let mut mock_conn = MockDatabaseConnection::new();
+    fn test_import_when_not_connected() {
+        let conn = unconnected_db();
         
-        // First check if UUID exists (returns false)
-        mock_conn.expect_is_connected()
-            .times(2) // Once for check, once for insert
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .times(1)
-            .returning(|_| Ok(()));
+        let importer = DataImporter::new(&conn);
+        let result = importer.bulk_import([Ok(JsonlRecord::Message(create_test_message()))], "/test/project", false);
         
-        let importer = DataImporter::new(&mock_conn);
-        let message = create_test_message();
-        let result = importer.import_with_duplicate_check(&message, "/test/project");
-        
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), ImportAction::Inserted);
-    }
-
-    #[test]
-    fn test_uuid_duplicate_detection() {
-        // Test with mock that simulates existing UUID
-        struct MockImporter {
-            uuid_exists: bool,
-        }
-        
-        impl MockImporter {
-            fn check_uuid_exists(&self, _uuid: &str) -> Result<bool> {
-                Ok(self.uuid_exists)
-            }
-        }
-        
-        // Simulate UUID already exists
-        let mock_importer = MockImporter {
-            uuid_exists: true,
-        };
-        
-        assert!(mock_importer.check_uuid_exists("test-uuid").unwrap());
-    }
-
-    #[test]
-    fn test_import_with_duplicate_check_update() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        // Mock implementation where we simulate UUID exists
-        // We need to override the default behavior for this test
-        mock_conn.expect_is_connected()
-            .times(1) // Only for update
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .times(1)
-            .returning(|_| Ok(()));
-        
-        // Create a custom DataImporter for testing duplicate scenario
-        struct TestDataImporter<'a> {
-            connection: &'a dyn DatabaseConnection,
-        }
-        
-        impl<'a> TestDataImporter<'a> {
-            fn check_uuid_exists(&self, _uuid: &str) -> Result<bool> {
-                Ok(true) // Simulate UUID exists
-            }
-            
-            fn update_conversation(&self, message: &ClaudeMessage, project_path: &str) -> Result<()> {
-                DataImporter::new(self.connection).update_conversation(message, project_path)
-            }
-            
-            fn import_with_duplicate_check(&self, message: &ClaudeMessage, project_path: &str) -> Result<ImportAction> {
-                if self.check_uuid_exists(&message.uuid)? {
-                    self.update_conversation(message, project_path)?;
-                    Ok(ImportAction::Updated)
-                } else {
-                    Err(anyhow!("Should not reach here in this test"))
-                }
-            }
-        }
-        
-        let test_importer = TestDataImporter {
-            connection: &mock_conn,
-        };
-        
-        let message = create_test_message();
-        let result = test_importer.import_with_duplicate_check(&message, "/test/project");
-        
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), ImportAction::Updated);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Database not connected"));
     }
 
     #[test]
     fn test_update_existing_with_new_timestamp() {
-        let mut mock_conn = MockDatabaseConnection::new();
+        let conn = setup_db();
         
         // First message with initial timestamp
         let initial_message = create_test_message();
@@ -409,24 +389,23 @@ mod tests {
         updated_message.timestamp = Utc::now() + chrono::Duration::hours(1);
         updated_message.message.content = Some(serde_json::json!("Updated message"));
         
-        // The update should preserve the UUID but update other fields including timestamp
-        mock_conn.expect_is_connected()
-            .times(1)
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .times(1)
-            .returning(|query| {
-                // Verify that the UPDATE query contains the new timestamp
-                assert!(query.contains("UPDATE conversations"));
-                assert!(query.contains("updated_at = CURRENT_TIMESTAMP"));
-                Ok(())
-            });
-        
-        let importer = DataImporter::new(&mock_conn);
-        let result = importer.update_conversation(&updated_message, "/test/project");
-        
-        assert!(result.is_ok());
+        let importer = DataImporter::new(&conn);
+        import(&importer, std::slice::from_ref(&initial_message), "/test/project");
+        let stats = import(&importer, &[updated_message.clone()], "/test/project");
+        assert_eq!(stats, ImportStats { inserted: 0, updated: 1, skipped: 0, errors: 0 });
+        
+        // The update replaces the content; the indexed timestamp keeps its first value
+        let row: Option<(Option<String>, i64)> = conn.query_row(
+            "SELECT content_text, epoch_us(timestamp) FROM conversations WHERE uuid = 'test-uuid-123'", &[],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!(row, Some((Some("Updated message".to_string()), initial_message.timestamp.timestamp_micros())));
+        
+        // Stale blocks are replaced
+        let blocks: Vec<Option<String>> = conn.query_all(
+            "SELECT block_text FROM content_blocks", &[], |row| Ok(row.get(0)?),
+        ).unwrap();
+        assert_eq!(blocks, vec![Some("Updated message".to_string())]);
     }
 
     #[test]
@@ -441,38 +420,34 @@ mod tests {
 
     #[test]
     fn test_bulk_import() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        // Expect multiple calls for bulk import
-        mock_conn.expect_is_connected()
-            .times(6) // 2 per message (check + insert) × 3 messages
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .times(3) // 3 inserts
-            .returning(|_| Ok(()));
+        let conn = setup_db();
         
-        let importer = DataImporter::new(&mock_conn);
+        let importer = DataImporter::new(&conn);
+        let mut other = create_test_message();
+        other.uuid = "other-uuid".to_string();
         let messages = vec![
             create_test_message(),
             create_test_message(),
-            create_test_message(),
+            other,
         ];
         
-        let result = importer.bulk_import(&messages, "/test/project");
+        let records = messages.into_iter().map(|message| Ok(JsonlRecord::Message(message)));
+        let result = importer.bulk_import(records, "/test/project", false);
         
         assert!(result.is_ok());
         let stats = result.unwrap();
-        assert_eq!(stats.inserted, 3);
+        // The repeated uuid is stored once
+        assert_eq!(stats.inserted, 2);
         assert_eq!(stats.updated, 0);
-        assert_eq!(stats.skipped, 0);
+        assert_eq!(stats.skipped, 1);
         assert_eq!(stats.errors, 0);
         assert_eq!(stats.total_processed(), 3);
+        assert_eq!(count(&conn, "conversations"), 2);
     }
 
     #[test]
-    fn test_bulk_import_performance() {
-        let mut mock_conn = MockDatabaseConnection::new();
+    fn test_bulk_import_large_batch() {
+        let conn = setup_db();
         
         // Create a large batch of messages
         let num_messages = 1000;
@@ -484,29 +459,16 @@ mod tests {
             messages.push(msg);
         }
         
-        // Mock expectations for bulk operations
-        mock_conn.expect_is_connected()
-            .times(num_messages * 2) // Check + insert for each message
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .times(num_messages)
-            .returning(|_| Ok(()));
+        let importer = DataImporter::new(&conn);
         
-        let importer = DataImporter::new(&mock_conn);
-        
-        let start_time = std::time::Instant::now();
-        let result = importer.bulk_import(&messages, "/test/project");
-        let elapsed = start_time.elapsed();
+        let records = messages.into_iter().map(|message| Ok(JsonlRecord::Message(message)));
+        let result = importer.bulk_import(records, "/test/project", false);
         
         assert!(result.is_ok());
         let stats = result.unwrap();
         assert_eq!(stats.inserted, num_messages);
         assert_eq!(stats.updated, 0);
         assert_eq!(stats.errors, 0);
-        
-        // Performance check: should complete in reasonable time (< 1 second for mock operations)
-        assert!(elapsed.as_secs() < 1, "Bulk import took too long: {:?}", elapsed);
     }
 
     #[test]
@@ -539,4 +501,241 @@ mod tests {
         
         assert_eq!(stats.total_processed(), 10);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_import_stores_plain_text_and_content_blocks() {
+        let conn = setup_db();
+
+        let mut message = create_test_message();
+        message.message.content = Some(serde_json::json!([
+            {"type": "text", "text": "I'll run the tests."},
+            {"type": "tool_use", "id": "toolu_01", "name": "Bash", "input": {"command": "cargo test"}}
+        ]));
+
+        let importer = DataImporter::new(&conn);
+        import(&importer, &[message.clone()], "/test/project");
+
+        let content_text: Option<String> = conn.query_row(
+            "SELECT content_text FROM conversations WHERE uuid = 'test-uuid-123'", &[],
+            |row| Ok(row.get(0)?),
+        ).unwrap();
+        assert_eq!(content_text, Some("I'll run the tests.".to_string()));
+
+        let blocks: Vec<(i32, String, Option<String>, Option<String>)> = conn.query_all(
+            "SELECT block_index, block_type, tool_name, tool_use_id FROM content_blocks ORDER BY block_index", &[],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        ).unwrap();
+        assert_eq!(blocks, vec![
+            (0, "text".to_string(), None, None),
+            (1, "tool_use".to_string(), Some("Bash".to_string()), Some("toolu_01".to_string())),
+        ]);
+
+        // Re-importing replaces the blocks instead of duplicating them
+        message.message.content = Some(serde_json::json!("Edited"));
+        import(&importer, &[message], "/test/project");
+        assert_eq!(count(&conn, "content_blocks"), 1);
+    }
+
+    #[test]
+    fn test_get_last_update_time() {
+        let conn = setup_db();
+        let importer = DataImporter::new(&conn);
+
+        assert_eq!(importer.get_last_update_time("/test/project").unwrap(), None);
+
+        let older = create_test_message();
+        let mut newer = create_test_message();
+        newer.uuid = "test-uuid-456".to_string();
+        newer.timestamp = older.timestamp + chrono::Duration::minutes(5);
+        import(&importer, &[older, newer.clone()], "/test/project");
+
+        let last = importer.get_last_update_time("/test/project").unwrap().unwrap();
+        assert_eq!(last.timestamp_micros(), newer.timestamp.timestamp_micros());
+        assert_eq!(importer.get_last_update_time("/other/project").unwrap(), None);
+    }
+
+    #[test]
+    fn test_import_links_summaries_and_keeps_extra_fields() {
+        let conn = setup_db();
+        let importer = DataImporter::new(&conn);
+        let parser = JsonlParser::new();
+
+        let lines = [
+            r#"{"type":"summary","summary":"Fixing the parser","leafUuid":"uuid-1"}"#,
+            r#"{"type":"user","sessionId":"s1","message":{"role":"user","content":"hi"},"uuid":"uuid-1","timestamp":"2025-07-21T12:48:30.283Z","requestId":"req-1"}"#,
+            r#"{"type":"file-history-snapshot","snapshot":{}}"#,
+            r#"{"type":"summary","summary":"Orphan"}"#,
+        ];
+        let stats = importer.bulk_import(lines.iter().map(|line| parser.parse_record(line)), "/test/project", false).unwrap();
+        assert_eq!(stats, ImportStats { inserted: 2, updated: 0, skipped: 2, errors: 0 });
+
+        let extra: Option<String> = conn.query_row(
+            "SELECT extra FROM conversations WHERE uuid = 'uuid-1'", &[],
+            |row| Ok(row.get(0)?),
+        ).unwrap();
+        assert_eq!(extra.as_deref(), Some(r#"{"requestId":"req-1"}"#));
+
+        // The summary arrives before its leaf and joins to it once both are in
+        let linked: Option<(String, String)> = conn.query_row(
+            "SELECT s.summary, c.session_id FROM summaries s JOIN conversations c ON c.uuid = s.leaf_uuid", &[],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!(linked, Some(("Fixing the parser".to_string(), "s1".to_string())));
+    }
+
+    #[test]
+    fn test_adversarial_content_round_trips() {
+        let conn = setup_db();
+        let importer = DataImporter::new(&conn);
+
+        let contents = [
+            r"C:\Users\dev\path \' \\ trailing\".to_string(),
+            "nul\0in\0the\0middle".to_string(),
+            "'); DROP TABLE conversations; --".to_string(),
+            "$1 ? %s {} \"quoted\" ''".to_string(),
+            "x".repeat(4 * 1024 * 1024) + "'\\",
+        ];
+        let project = r"/tmp/it's \ odd";
+
+        let content_of = |uuid: &str| -> (Option<String>, String) {
+            conn.query_row(
+                "SELECT content_text, project_path FROM conversations WHERE uuid = ?",
+                &[SqlValue::Text(uuid.to_string())],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            ).unwrap().unwrap()
+        };
+
+        let records = contents.iter().enumerate().map(|(i, content)| {
+            let mut message = create_test_message();
+            message.uuid = format!("bulk-{}", i);
+            message.message.content = Some(serde_json::json!(content));
+            Ok(JsonlRecord::Message(message))
+        });
+        let stats = importer.bulk_import(records, project, false).unwrap();
+        assert_eq!(stats.inserted, contents.len());
+        for (i, content) in contents.iter().enumerate() {
+            assert_eq!(content_of(&format!("bulk-{}", i)), (Some(content.clone()), project.to_string()));
+        }
+
+        // The update path binds the same values
+        let records = contents.iter().enumerate().map(|(i, content)| {
+            let mut message = create_test_message();
+            message.uuid = format!("bulk-{}", i);
+            message.message.content = Some(serde_json::json!(format!("{}{}", content, content)));
+            Ok(JsonlRecord::Message(message))
+        });
+        let stats = importer.bulk_import(records, project, false).unwrap();
+        assert_eq!(stats.updated, contents.len());
+        for (i, content) in contents.iter().enumerate() {
+            assert_eq!(content_of(&format!("bulk-{}", i)).0, Some(format!("{}{}", content, content)));
+        }
+    }
+
+    #[test]
+    fn test_bulk_import_upserts_in_one_pass() {
+        let conn = setup_db();
+        let importer = DataImporter::new(&conn);
+
+        let mut tool_use = create_test_message();
+        tool_use.uuid = "uuid-use".to_string();
+        tool_use.message.content = Some(serde_json::json!([
+            {"type": "text", "text": "エラー処理を確認します"},
+            {"type": "tool_use", "id": "toolu_1", "name": "Bash", "input": {"command": "ls"}}
+        ]));
+        let mut tool_result = create_test_message();
+        tool_result.uuid = "uuid-result".to_string();
+        tool_result.message.content = Some(serde_json::json!([
+            {"type": "tool_result", "tool_use_id": "toolu_1", "content": "Cargo.toml"}
+        ]));
+        let summary = SummaryRecord {
+            summary: "Listing files".to_string(),
+            leaf_uuid: Some("uuid-result".to_string()),
+            extra: Default::default(),
+        };
+        let records = || vec![
+            Ok(JsonlRecord::Message(tool_use.clone())),
+            Ok(JsonlRecord::Message(tool_result.clone())),
+            Ok(JsonlRecord::Summary(summary.clone())),
+            Ok(JsonlRecord::Unknown { record_type: Some("file-history-snapshot".to_string()), raw: serde_json::json!({}) }),
+            Err(anyhow!("line 5: Failed to parse JSON message")),
+        ];
+
+        let stats = importer.bulk_import(records(), "/test/project", false).unwrap();
+        assert_eq!(stats, ImportStats { inserted: 3, updated: 0, skipped: 1, errors: 1 });
+
+        let tool_call: Option<(String, String, String)> = conn.query_row(
+            "SELECT tool_name, result_text, result_uuid FROM tool_calls WHERE tool_use_id = 'toolu_1'", &[],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).unwrap();
+        assert_eq!(tool_call, Some(("Bash".to_string(), "Cargo.toml".to_string(), "uuid-result".to_string())));
+        let grams: Option<i64> = conn.query_row(
+            "SELECT count(*) FROM cjk_bigrams WHERE message_uuid = 'uuid-use'", &[],
+            |row| Ok(row.get(0)?),
+        ).unwrap();
+        assert!(grams.unwrap() > 0);
+
+        // Re-importing the same file changes nothing
+        let stats = importer.bulk_import(records(), "/test/project", false).unwrap();
+        assert_eq!(stats, ImportStats { inserted: 0, updated: 0, skipped: 4, errors: 1 });
+
+        // An edited message is updated and its blocks rewritten; a repeated uuid keeps its last copy
+        let mut edited = tool_use.clone();
+        edited.message.content = Some(serde_json::json!("Edited"));
+        let stats = importer.bulk_import(vec![
+            Ok(JsonlRecord::Message(tool_use.clone())),
+            Ok(JsonlRecord::Message(edited)),
+        ], "/test/project", false).unwrap();
+        assert_eq!(stats, ImportStats { inserted: 0, updated: 1, skipped: 1, errors: 0 });
+
+        let blocks: Vec<(String, Option<String>)> = conn.query_all(
+            "SELECT block_type, block_text FROM content_blocks WHERE message_uuid = 'uuid-use' ORDER BY block_index", &[],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!(blocks, vec![("text".to_string(), Some("Edited".to_string()))]);
+        let grams: Option<i64> = conn.query_row(
+            "SELECT count(*) FROM cjk_bigrams WHERE message_uuid = 'uuid-use'", &[],
+            |row| Ok(row.get(0)?),
+        ).unwrap();
+        assert_eq!(grams, Some(0));
+
+        // --force rewrites rows even when unchanged
+        let stats = importer.bulk_import(vec![Ok(JsonlRecord::Message(tool_result))], "/test/project", true).unwrap();
+        assert_eq!(stats, ImportStats { inserted: 0, updated: 1, skipped: 0, errors: 0 });
+    }
+
+    #[test]
+    #[ignore = "slow; run with --ignored to check bulk import at scale"]
+    fn test_bulk_import_100k_messages() {
+        const MESSAGES: usize = 100_000;
+
+        let conn = setup_db();
+        let importer = DataImporter::new(&conn);
+        let parser = JsonlParser::new();
+
+        let mut fixture = String::new();
+        for i in 0..MESSAGES {
+            fixture.push_str(&format!(
+                r#"{{"parentUuid":{},"sessionId":"session-{}","userType":"external","cwd":"/bench","version":"1.0.0","type":"{}","message":{{"role":"user","content":"Benchmark message {} about error handling"}},"uuid":"bench-{}","timestamp":"2025-07-21T12:48:30.283Z"}}"#,
+                if i == 0 { "null".to_string() } else { format!(r#""bench-{}""#, i - 1) },
+                i / 1000,
+                if i % 2 == 0 { "user" } else { "assistant" },
+                i,
+                i
+            ));
+            fixture.push('\n');
+        }
+
+        fn records<'a>(parser: &'a JsonlParser, input: &'a str) -> impl Iterator<Item = Result<JsonlRecord>> + 'a {
+            parser.stream(input.as_bytes()).map(|line| line.and_then(|line| line.record))
+        }
+
+        let stats = importer.bulk_import(records(&parser, &fixture), "/bench", false).unwrap();
+        assert_eq!(stats, ImportStats { inserted: MESSAGES, updated: 0, skipped: 0, errors: 0 });
+
+        let stats = importer.bulk_import(records(&parser, &fixture), "/bench", false).unwrap();
+        assert_eq!(stats, ImportStats { inserted: 0, updated: 0, skipped: MESSAGES, errors: 0 });
+
+        let count: Option<i64> = conn.query_row("SELECT count(*) FROM conversations", &[], |row| Ok(row.get(0)?)).unwrap();
+        assert_eq!(count, Some(MESSAGES as i64));
+    }
+}
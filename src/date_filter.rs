@@ -0,0 +1,395 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, LocalResult, Months, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use regex::Regex;
+
+/// A half-open `[start, end)` interval of time.
+///
+/// Point-in-time expressions such as "3 days ago" produce an empty range
+/// where `start == end`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateRange {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl DateRange {
+    fn instant(at: DateTime<Utc>) -> Self {
+        Self { start: at, end: at }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Unit {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim_end_matches('s') {
+            "minute" | "min" => Some(Unit::Minute),
+            "hour" => Some(Unit::Hour),
+            "day" => Some(Unit::Day),
+            "week" => Some(Unit::Week),
+            "month" => Some(Unit::Month),
+            "quarter" => Some(Unit::Quarter),
+            "year" => Some(Unit::Year),
+            _ => None,
+        }
+    }
+}
+
+/// Resolves absolute and relative date expressions into UTC ranges, using
+/// a fixed "now" and a local timezone for calendar boundaries.
+///
+/// Supported forms:
+/// - absolute: `2024-01-01`, `2024-01`, `2024`, `2024-Q3`, `2024-01-01T09:30`, RFC3339
+/// - named days: `now`, `today`, `yesterday`
+/// - calendar periods: `this week|month|quarter|year`
+/// - rolling windows: `last week|month|year`, `last 3 days`
+/// - points in the past: `3 days ago`, `2 months ago`
+pub struct DateParser {
+    tz: Tz,
+    now: DateTime<Utc>,
+}
+
+impl DateParser {
+    pub fn new(tz: Tz) -> Self {
+        Self::with_now(tz, Utc::now())
+    }
+
+    pub fn with_now(tz: Tz, now: DateTime<Utc>) -> Self {
+        Self { tz, now }
+    }
+
+    /// Parser for the system's local timezone, falling back to UTC when it
+    /// cannot be determined.
+    pub fn local() -> Self {
+        Self::new(system_timezone())
+    }
+
+    #[allow(dead_code)]
+    pub fn timezone(&self) -> Tz {
+        self.tz
+    }
+
+    pub fn parse(&self, input: &str) -> Result<DateRange> {
+        let normalized = input
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+
+        if normalized.is_empty() {
+            return Err(anyhow!("Cannot parse date: empty expression"));
+        }
+
+        self.parse_absolute(&normalized)
+            .or_else(|| self.parse_relative(&normalized))
+            .transpose()?
+            .ok_or_else(|| anyhow!("Cannot parse date: '{}'", input.trim()))
+    }
+
+    /// Start of the range described by `input`, for use as a lower bound.
+    pub fn parse_start(&self, input: &str) -> Result<DateTime<Utc>> {
+        Ok(self.parse(input)?.start)
+    }
+
+    /// Exclusive end of the range described by `input`, for use as an upper bound.
+    /// `--to 2024-01-31` therefore includes the whole of January 31st.
+    pub fn parse_end(&self, input: &str) -> Result<DateTime<Utc>> {
+        Ok(self.parse(input)?.end)
+    }
+
+    fn parse_absolute(&self, s: &str) -> Option<Result<DateRange>> {
+        if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+            return Some(Ok(DateRange::instant(dt.with_timezone(&Utc))));
+        }
+
+        for format in ["%Y-%m-%dt%H:%M:%S", "%Y-%m-%dt%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+            if let Ok(naive) = NaiveDateTime::parse_from_str(s, format) {
+                return Some(self.resolve_local(naive).map(DateRange::instant));
+            }
+        }
+
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Some(self.calendar_range(date, Unit::Day));
+        }
+
+        let month = Regex::new(r"^(\d{4})-(\d{1,2})$").unwrap();
+        if let Some(caps) = month.captures(s) {
+            let date = NaiveDate::from_ymd_opt(caps[1].parse().ok()?, caps[2].parse().ok()?, 1);
+            return Some(match date {
+                Some(date) => self.calendar_range(date, Unit::Month),
+                None => Err(anyhow!("Invalid month: '{}'", s)),
+            });
+        }
+
+        let quarter = Regex::new(r"^(\d{4})-?q([1-4])$").unwrap();
+        if let Some(caps) = quarter.captures(s) {
+            let q: u32 = caps[2].parse().ok()?;
+            let date = NaiveDate::from_ymd_opt(caps[1].parse().ok()?, (q - 1) * 3 + 1, 1)?;
+            return Some(self.calendar_range(date, Unit::Quarter));
+        }
+
+        let year = Regex::new(r"^(\d{4})$").unwrap();
+        if let Some(caps) = year.captures(s) {
+            let date = NaiveDate::from_ymd_opt(caps[1].parse().ok()?, 1, 1)?;
+            return Some(self.calendar_range(date, Unit::Year));
+        }
+
+        None
+    }
+
+    fn parse_relative(&self, s: &str) -> Option<Result<DateRange>> {
+        let today = self.now.with_timezone(&self.tz).date_naive();
+
+        match s {
+            "now" => return Some(Ok(DateRange::instant(self.now))),
+            "today" => return Some(self.calendar_range(today, Unit::Day)),
+            "yesterday" => return Some(self.calendar_range(today - Duration::days(1), Unit::Day)),
+            _ => {}
+        }
+
+        if let Some(unit) = s.strip_prefix("this ").and_then(Unit::parse) {
+            if matches!(unit, Unit::Minute | Unit::Hour | Unit::Day) {
+                return None;
+            }
+            return Some(self.calendar_range(today, unit));
+        }
+
+        // "last week" / "last 3 days": rolling window ending now
+        let last = Regex::new(r"^(?:last|past) (?:(\d+) )?([a-z]+)$").unwrap();
+        if let Some(caps) = last.captures(s) {
+            let amount = match caps.get(1) {
+                Some(m) => m.as_str().parse().ok()?,
+                None => 1,
+            };
+            let unit = Unit::parse(&caps[2])?;
+            return Some(self.shift_back(amount, unit).map(|start| DateRange { start, end: self.now }));
+        }
+
+        let ago = Regex::new(r"^(-?\d+) ([a-z]+) ago$").unwrap();
+        if let Some(caps) = ago.captures(s) {
+            let unit = Unit::parse(&caps[2])?;
+            let amount: i64 = match caps[1].parse() {
+                Ok(n) => n,
+                Err(_) => return Some(Err(anyhow!("Invalid number in '{}'", s))),
+            };
+            return Some(self.shift_back(amount, unit).map(DateRange::instant));
+        }
+
+        None
+    }
+
+    /// `now` moved back by `amount` units, using calendar arithmetic for
+    /// months, quarters and years (e.g. March 31 minus one month is February 28/29).
+    fn shift_back(&self, amount: i64, unit: Unit) -> Result<DateTime<Utc>> {
+        if amount <= 0 {
+            return Err(anyhow!("Invalid time value: {}", amount));
+        }
+
+        let months = match unit {
+            Unit::Minute => return Ok(self.now - Duration::minutes(amount)),
+            Unit::Hour => return Ok(self.now - Duration::hours(amount)),
+            Unit::Day => return Ok(self.now - Duration::days(amount)),
+            Unit::Week => return Ok(self.now - Duration::weeks(amount)),
+            Unit::Month => amount,
+            Unit::Quarter => amount * 3,
+            Unit::Year => amount * 12,
+        };
+
+        let local = self.now.with_timezone(&self.tz).naive_local();
+        let shifted = u32::try_from(months)
+            .ok()
+            .and_then(|m| local.checked_sub_months(Months::new(m)))
+            .ok_or_else(|| anyhow!("Date out of range: {} months ago", months))?;
+        self.resolve_local(shifted)
+    }
+
+    /// The calendar period of `unit` that contains `date`, in local time.
+    fn calendar_range(&self, date: NaiveDate, unit: Unit) -> Result<DateRange> {
+        let (first, next) = match unit {
+            Unit::Day => (date, date + Duration::days(1)),
+            Unit::Week => {
+                let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+                (monday, monday + Duration::weeks(1))
+            }
+            Unit::Month | Unit::Quarter | Unit::Year => {
+                let (month, length) = match unit {
+                    Unit::Month => (date.month(), 1),
+                    Unit::Quarter => ((date.month() - 1) / 3 * 3 + 1, 3),
+                    _ => (1, 12),
+                };
+                let first = NaiveDate::from_ymd_opt(date.year(), month, 1)
+                    .ok_or_else(|| anyhow!("Invalid date: {}", date))?;
+                let next = first
+                    .checked_add_months(Months::new(length))
+                    .ok_or_else(|| anyhow!("Date out of range: {}", date))?;
+                (first, next)
+            }
+            Unit::Minute | Unit::Hour => return Err(anyhow!("Unsupported calendar unit")),
+        };
+
+        Ok(DateRange {
+            start: self.resolve_local(first.and_hms_opt(0, 0, 0).unwrap())?,
+            end: self.resolve_local(next.and_hms_opt(0, 0, 0).unwrap())?,
+        })
+    }
+
+    /// Converts a wall-clock time in the parser's timezone to UTC. Ambiguous
+    /// times (DST fall-back) take the earlier instant; times skipped by a DST
+    /// gap are moved forward past the gap.
+    fn resolve_local(&self, naive: NaiveDateTime) -> Result<DateTime<Utc>> {
+        match self.tz.from_local_datetime(&naive) {
+            LocalResult::Single(dt) => Ok(dt.with_timezone(&Utc)),
+            LocalResult::Ambiguous(earliest, _) => Ok(earliest.with_timezone(&Utc)),
+            LocalResult::None => (1..=4)
+                .find_map(|h| self.tz.from_local_datetime(&(naive + Duration::hours(h))).earliest())
+                .map(|dt| dt.with_timezone(&Utc))
+                .ok_or_else(|| anyhow!("Local time {} does not exist in {}", naive, self.tz)),
+        }
+    }
+}
+
+/// Parses a timezone name such as `Asia/Tokyo` or `UTC`.
+pub fn parse_timezone(name: &str) -> Result<Tz> {
+    name.trim()
+        .parse::<Tz>()
+        .map_err(|_| anyhow!("Unknown timezone: '{}' (expected an IANA name like 'Asia/Tokyo')", name))
+}
+
+/// The system timezone from the environment (`TZ`) or OS settings, or UTC.
+pub fn system_timezone() -> Tz {
+    std::env::var("TZ")
+        .ok()
+        .and_then(|tz| parse_timezone(&tz).ok())
+        .or_else(|| iana_time_zone::get_timezone().ok().and_then(|tz| parse_timezone(&tz).ok()))
+        .unwrap_or(chrono_tz::UTC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn tokyo_parser() -> DateParser {
+        // Wednesday 2024-07-17 10:00 in Tokyo
+        DateParser::with_now(chrono_tz::Asia::Tokyo, utc("2024-07-17T01:00:00Z"))
+    }
+
+    #[test]
+    fn test_absolute_day_uses_local_midnight() {
+        let range = tokyo_parser().parse("2024-01-01").unwrap();
+        assert_eq!(range.start, utc("2023-12-31T15:00:00Z"));
+        assert_eq!(range.end, utc("2024-01-01T15:00:00Z"));
+    }
+
+    #[test]
+    fn test_absolute_month_year_and_quarter() {
+        let parser = DateParser::with_now(chrono_tz::UTC, utc("2024-07-17T01:00:00Z"));
+
+        let month = parser.parse("2024-02").unwrap();
+        assert_eq!(month.start, utc("2024-02-01T00:00:00Z"));
+        assert_eq!(month.end, utc("2024-03-01T00:00:00Z"));
+
+        let year = parser.parse("2023").unwrap();
+        assert_eq!(year.start, utc("2023-01-01T00:00:00Z"));
+        assert_eq!(year.end, utc("2024-01-01T00:00:00Z"));
+
+        let quarter = parser.parse("2024-Q3").unwrap();
+        assert_eq!(quarter.start, utc("2024-07-01T00:00:00Z"));
+        assert_eq!(quarter.end, utc("2024-10-01T00:00:00Z"));
+
+        assert!(parser.parse("2024-13").is_err());
+    }
+
+    #[test]
+    fn test_rfc3339_and_local_datetime() {
+        let parser = tokyo_parser();
+
+        let exact = parser.parse("2024-03-01T12:00:00+02:00").unwrap();
+        assert_eq!(exact.start, utc("2024-03-01T10:00:00Z"));
+        assert_eq!(exact.start, exact.end);
+
+        let local = parser.parse("2024-03-01T09:30").unwrap();
+        assert_eq!(local.start, utc("2024-03-01T00:30:00Z"));
+    }
+
+    #[test]
+    fn test_today_and_yesterday_follow_timezone() {
+        // 01:00 UTC is already 10:00 on the 17th in Tokyo
+        let today = tokyo_parser().parse("today").unwrap();
+        assert_eq!(today.start, utc("2024-07-16T15:00:00Z"));
+        assert_eq!(today.end, utc("2024-07-17T15:00:00Z"));
+
+        let yesterday = tokyo_parser().parse("Yesterday").unwrap();
+        assert_eq!(yesterday.start, utc("2024-07-15T15:00:00Z"));
+        assert_eq!(yesterday.end, today.start);
+    }
+
+    #[test]
+    fn test_this_week_starts_on_monday() {
+        let range = tokyo_parser().parse("this week").unwrap();
+        assert_eq!(range.start, utc("2024-07-14T15:00:00Z"));
+        assert_eq!(range.end, utc("2024-07-21T15:00:00Z"));
+
+        let month = tokyo_parser().parse("this month").unwrap();
+        assert_eq!(month.start, utc("2024-06-30T15:00:00Z"));
+    }
+
+    #[test]
+    fn test_calendar_accurate_month_arithmetic() {
+        let parser = DateParser::with_now(chrono_tz::UTC, utc("2024-03-31T12:00:00Z"));
+
+        // One month before March 31st clamps to the end of February (leap year)
+        assert_eq!(parser.parse_start("1 month ago").unwrap(), utc("2024-02-29T12:00:00Z"));
+        assert_eq!(parser.parse_start("last month").unwrap(), utc("2024-02-29T12:00:00Z"));
+        assert_eq!(parser.parse_start("1 year ago").unwrap(), utc("2023-03-31T12:00:00Z"));
+        assert_eq!(parser.parse_start("3 days ago").unwrap(), utc("2024-03-28T12:00:00Z"));
+
+        let last_week = parser.parse("last week").unwrap();
+        assert_eq!(last_week.start, utc("2024-03-24T12:00:00Z"));
+        assert_eq!(last_week.end, utc("2024-03-31T12:00:00Z"));
+
+        assert_eq!(parser.parse_start("last 2 months").unwrap(), utc("2024-01-31T12:00:00Z"));
+    }
+
+    #[test]
+    fn test_dst_gap_is_skipped() {
+        // 02:30 on 2024-03-10 does not exist in New York
+        let parser = DateParser::with_now(chrono_tz::America::New_York, utc("2024-07-01T00:00:00Z"));
+        let range = parser.parse("2024-03-10T02:30").unwrap();
+        assert_eq!(range.start, utc("2024-03-10T07:30:00Z"));
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        let parser = tokyo_parser();
+
+        assert!(parser.parse("").is_err());
+        assert!(parser.parse("tomorrow").is_err());
+        assert!(parser.parse("7 days").is_err());
+        assert!(parser.parse("-5 days ago").is_err());
+        assert!(parser.parse("0 days ago").is_err());
+        assert!(parser.parse("3 fortnights ago").is_err());
+        assert!(parser.parse("2024-02-30").is_err());
+        assert!(parser.parse("this day").is_err());
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        assert_eq!(parse_timezone("Asia/Tokyo").unwrap(), chrono_tz::Asia::Tokyo);
+        assert_eq!(parse_timezone("UTC").unwrap(), chrono_tz::UTC);
+        assert!(parse_timezone("Mars/Olympus").is_err());
+    }
+}
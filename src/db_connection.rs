@@ -3,6 +3,32 @@ use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::time::sleep;
 
+/// A value bound to a statement placeholder.
+pub use duckdb::types::Value as SqlValue;
+
+/// Values for a statement being assembled piece by piece. `bind` returns the
+/// `$n` placeholder to splice into the SQL text, so pieces can be combined in
+/// any order without their values getting out of step.
+#[derive(Debug, Default)]
+pub struct SqlParams {
+    values: Vec<SqlValue>,
+}
+
+impl SqlParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind(&mut self, value: impl Into<SqlValue>) -> String {
+        self.values.push(value.into());
+        format!("${}", self.values.len())
+    }
+
+    pub fn values(&self) -> &[SqlValue] {
+        &self.values
+    }
+}
+
 #[cfg_attr(test, mockall::automock)]
 pub trait DatabaseConnection: Send + Sync {
     fn connect(&self) -> Result<()>;
@@ -11,12 +37,8 @@ pub trait DatabaseConnection: Send + Sync {
     fn is_connected(&self) -> bool;
     #[allow(dead_code)]
     fn execute(&self, query: &str) -> Result<()>;
-    
-    // Enable downcasting to extended connections
-    fn as_any(&self) -> &dyn std::any::Any {
-        // Default implementation returns a dummy Any
-        &()
-    }
+    /// Runs one statement with `params` bound to its `?` or `$n` placeholders.
+    fn execute_with_params(&self, query: &str, params: &[SqlValue]) -> Result<()>;
 }
 
 #[derive(Clone)]
@@ -114,6 +136,9 @@ impl DatabaseConnection for DuckDBConnector {
         // Mock implementation
         Ok(())
     }
+    fn execute_with_params(&self, query: &str, _params: &[SqlValue]) -> Result<()> {
+        self.execute(query)
+    }
 }
 
 #[allow(dead_code)]
@@ -201,9 +226,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_connect_with_retry_success() {
-        let mut config = ConnectionConfig::default();
-        config.max_retries = 3;
-        config.retry_delay_ms = 10;
+        let config = ConnectionConfig {
+            max_retries: 3,
+            retry_delay_ms: 10,
+            ..Default::default()
+        };
         
         let connector = DuckDBConnector::new(config);
         let result = connector.connect_with_retry().await;
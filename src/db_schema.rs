@@ -1,29 +1,176 @@
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use duckdb::params;
 use crate::db_connection::DatabaseConnection;
+use crate::real_db_connection::ExtendedDatabaseConnection;
 
 #[allow(dead_code)]
 pub const CREATE_CONVERSATIONS_SEQUENCE: &str = 
     "CREATE SEQUENCE IF NOT EXISTS conversations_id_seq START 1";
 
+// Databases created before `content_text` existed get the column added in place.
 #[allow(dead_code)]
-pub const CREATE_CONVERSATIONS_TABLE: &str = r#"
-CREATE TABLE IF NOT EXISTS conversations (
-    id INTEGER PRIMARY KEY DEFAULT nextval('conversations_id_seq'),
-    uuid TEXT NOT NULL UNIQUE,
-    parent_uuid TEXT,
-    session_id TEXT NOT NULL,
-    user_type TEXT NOT NULL,
-    message_type TEXT NOT NULL,
-    message_role TEXT,
-    message_content TEXT,
+pub const ADD_CONTENT_TEXT_COLUMN: &str = 
+    "ALTER TABLE conversations ADD COLUMN IF NOT EXISTS content_text TEXT";
+
+// Top-level record fields the parser does not model, as a JSON object.
+#[allow(dead_code)]
+pub const ADD_EXTRA_COLUMN: &str = 
+    "ALTER TABLE conversations ADD COLUMN IF NOT EXISTS extra TEXT";
+
+// Whether the message belongs to a sidechain, i.e. a subagent's conversation.
+pub const ADD_IS_SIDECHAIN_COLUMN: &str =
+    "ALTER TABLE conversations ADD COLUMN IF NOT EXISTS is_sidechain BOOLEAN DEFAULT false";
+
+// Fields of the API message besides role and content (`id`, `model`, `usage`,
+// `stop_reason`, ...), as a JSON object.
+pub const ADD_MESSAGE_EXTRA_COLUMN: &str =
+    "ALTER TABLE conversations ADD COLUMN IF NOT EXISTS message_extra TEXT";
+
+/// One row per entry of a message's `content` array, in original order.
+#[allow(dead_code)]
+pub const CREATE_CONTENT_BLOCKS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS content_blocks (
+    message_uuid TEXT NOT NULL,
+    block_index INTEGER NOT NULL,
+    block_type TEXT NOT NULL,
+    tool_name TEXT,
+    tool_use_id TEXT,
+    block_text TEXT,
+    PRIMARY KEY (message_uuid, block_index)
+)"#;
+
+#[allow(dead_code)]
+pub const CREATE_CONTENT_BLOCKS_TOOL_USE_INDEX: &str = 
+    "CREATE INDEX IF NOT EXISTS idx_content_blocks_tool_use_id ON content_blocks(tool_use_id)";
+
+/// One row per `tool_use` block, joined with its `tool_result` by `tool_use_id`.
+/// Either side may be imported first, so every column but the id is nullable.
+/// No secondary indexes: DuckDB cannot upsert into columns covered by one.
+#[allow(dead_code)]
+pub const CREATE_TOOL_CALLS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS tool_calls (
+    tool_use_id TEXT PRIMARY KEY,
+    tool_name TEXT,
+    input_json TEXT,
+    result_text TEXT,
+    is_error BOOLEAN,
+    session_id TEXT,
+    message_uuid TEXT,
+    result_uuid TEXT,
+    timestamp TIMESTAMP
+)"#;
+
+/// Side index for CJK text, which the FTS tokenizer cannot split into words:
+/// occurrence counts of the character bigrams of every message's plain text.
+#[allow(dead_code)]
+pub const CREATE_CJK_BIGRAMS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS cjk_bigrams (
+    message_uuid TEXT NOT NULL,
+    gram TEXT NOT NULL,
+    occurrences INTEGER NOT NULL,
+    PRIMARY KEY (message_uuid, gram)
+)"#;
+
+#[allow(dead_code)]
+pub const CREATE_CJK_BIGRAMS_GRAM_INDEX: &str = 
+    "CREATE INDEX IF NOT EXISTS idx_cjk_bigrams_gram ON cjk_bigrams(gram)";
+
+/// Titles from `summary` records, keyed by the last message they describe.
+/// A leaf may collect several summaries as a session is resumed.
+#[allow(dead_code)]
+pub const CREATE_SUMMARIES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS summaries (
+    leaf_uuid TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    extra TEXT,
+    PRIMARY KEY (leaf_uuid, summary)
+)"#;
+
+/// How far `sync` has read each source `.jsonl` file.
+#[allow(dead_code)]
+pub const CREATE_IMPORT_STATE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS import_state (
+    source_path TEXT PRIMARY KEY,
     project_path TEXT NOT NULL,
-    cwd TEXT NOT NULL,
-    git_branch TEXT,
-    version TEXT NOT NULL,
-    timestamp TIMESTAMP NOT NULL,
-    is_favorite BOOLEAN DEFAULT FALSE,
+    file_size BIGINT NOT NULL,
+    modified_at TIMESTAMP NOT NULL,
+    byte_offset BIGINT NOT NULL,
+    last_uuid TEXT,
+    synced_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+)"#;
+
+/// Favorite messages and sessions, keyed by uuid and session id so they
+/// outlive the conversation rows they point at.
+#[allow(dead_code)]
+pub const CREATE_FAVORITES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS favorites (
+    target_kind TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    note TEXT,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+    PRIMARY KEY (target_kind, target_id)
+)"#;
+
+// `conversations.is_favorite` predates the favorites table and is no longer
+// read or written; these carry its flags across in both directions.
+pub const COPY_LEGACY_FAVORITES: &str = r#"
+INSERT INTO favorites (target_kind, target_id)
+SELECT 'message', uuid FROM conversations WHERE is_favorite
+ON CONFLICT DO NOTHING"#;
+pub const RESTORE_LEGACY_FAVORITES: &str = r#"
+UPDATE conversations
+SET is_favorite = uuid IN (SELECT target_id FROM favorites WHERE target_kind = 'message')"#;
+
+/// Tag names, one row each; `taggings` links them to messages and sessions.
+#[allow(dead_code)]
+pub const CREATE_TAGS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS tags (
+    name TEXT PRIMARY KEY,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+)"#;
+
+/// Targets are keyed like favorites, so tags outlive the conversation rows too.
+#[allow(dead_code)]
+pub const CREATE_TAGGINGS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS taggings (
+    tag TEXT NOT NULL,
+    target_kind TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (tag, target_kind, target_id)
+)"#;
+
+#[allow(dead_code)]
+pub const CREATE_NOTES_SEQUENCE: &str =
+    "CREATE SEQUENCE IF NOT EXISTS notes_id_seq START 1";
+
+/// At most one note per message or session. The numeric id is only there
+/// because the FTS index needs a single key column.
+#[allow(dead_code)]
+pub const CREATE_NOTES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS notes (
+    id INTEGER PRIMARY KEY DEFAULT nextval('notes_id_seq'),
+    target_kind TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (target_kind, target_id)
+)"#;
+
+/// Every archived line, newline included, with the `sessionId` of its record.
+/// `raw_json` is a BLOB so lines that are not valid UTF-8 come back unchanged;
+/// concatenated in offset order the lines reproduce the file.
+#[allow(dead_code)]
+pub const CREATE_RAW_LINES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS raw_lines (
+    source_path TEXT NOT NULL,
+    byte_offset BIGINT NOT NULL,
+    project_path TEXT NOT NULL,
+    session_id TEXT,
+    raw_json BLOB NOT NULL,
+    archived_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
 )"#;
 
 #[allow(dead_code)]
@@ -43,86 +190,351 @@ pub const CREATE_PROJECT_INDEX: &str =
     "CREATE INDEX IF NOT EXISTS idx_conversations_project ON conversations(project_path)";
 
 #[allow(dead_code)]
-pub const CREATE_FTS_INDEX: &str = r#"
--- DuckDB doesn't support FTS5, we'll use standard indexes for now
-CREATE INDEX IF NOT EXISTS idx_conversations_content ON conversations(message_content)
-"#;
+pub const INSTALL_FTS_EXTENSION: &str = "INSTALL fts";
 
 #[allow(dead_code)]
-pub const CREATE_FTS_TRIGGERS: &str = r#"
--- No triggers needed for basic text search
-"#;
+pub const LOAD_FTS_EXTENSION: &str = "LOAD fts";
+
+/// Name of the schema DuckDB's `fts` extension creates for the conversations index.
+pub const FTS_SCHEMA: &str = "fts_main_conversations";
+
+/// Name of the schema DuckDB's `fts` extension creates for the notes index.
+pub const NOTES_FTS_SCHEMA: &str = "fts_main_notes";
+
+// DuckDB FTS indexes are not updated automatically, so this is re-run
+// (with overwrite) after every import.
+#[allow(dead_code)]
+pub const CREATE_FTS_INDEX: &str =
+    "PRAGMA create_fts_index('conversations', 'uuid', 'content_text', overwrite = 1)";
+
+// Notes are few, so their index is simply rebuilt whenever one changes
+#[allow(dead_code)]
+pub const CREATE_NOTES_FTS_INDEX: &str =
+    "PRAGMA create_fts_index('notes', 'id', 'body', overwrite = 1)";
 
 #[allow(dead_code)]
 pub const DROP_CONVERSATIONS_TABLE: &str = "DROP TABLE IF EXISTS conversations";
 #[allow(dead_code)]
-pub const DROP_FTS_TABLE: &str = "-- No FTS table to drop";
+pub const DROP_TOOL_CALLS_TABLE: &str = "DROP TABLE IF EXISTS tool_calls";
+#[allow(dead_code)]
+pub const DROP_CONTENT_BLOCKS_TABLE: &str = "DROP TABLE IF EXISTS content_blocks";
+#[allow(dead_code)]
+pub const DROP_CJK_BIGRAMS_TABLE: &str = "DROP TABLE IF EXISTS cjk_bigrams";
+#[allow(dead_code)]
+pub const DROP_SUMMARIES_TABLE: &str = "DROP TABLE IF EXISTS summaries";
+#[allow(dead_code)]
+pub const DROP_IMPORT_STATE_TABLE: &str = "DROP TABLE IF EXISTS import_state";
+#[allow(dead_code)]
+pub const DROP_FAVORITES_TABLE: &str = "DROP TABLE IF EXISTS favorites";
+#[allow(dead_code)]
+pub const DROP_TAGGINGS_TABLE: &str = "DROP TABLE IF EXISTS taggings";
+#[allow(dead_code)]
+pub const DROP_TAGS_TABLE: &str = "DROP TABLE IF EXISTS tags";
+#[allow(dead_code)]
+pub const DROP_NOTES_TABLE: &str = "DROP TABLE IF EXISTS notes";
+pub const DROP_NOTES_SEQUENCE: &str = "DROP SEQUENCE IF EXISTS notes_id_seq";
+#[allow(dead_code)]
+pub const DROP_RAW_LINES_TABLE: &str = "DROP TABLE IF EXISTS raw_lines";
+#[allow(dead_code)]
+pub const DROP_FTS_INDEX: &str = "DROP SCHEMA IF EXISTS fts_main_conversations CASCADE";
+#[allow(dead_code)]
+pub const DROP_NOTES_FTS_INDEX: &str = "DROP SCHEMA IF EXISTS fts_main_notes CASCADE";
 
-pub struct SchemaManager<'a> {
-    connection: &'a dyn DatabaseConnection,
+/// One row per applied migration, so the current version is the highest row.
+pub const CREATE_SCHEMA_VERSION_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER PRIMARY KEY,
+    description TEXT NOT NULL,
+    applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+)"#;
+
+pub const DROP_CONVERSATIONS_SEQUENCE: &str = "DROP SEQUENCE IF EXISTS conversations_id_seq";
+pub const DROP_CONTENT_TEXT_COLUMN: &str = "ALTER TABLE conversations DROP COLUMN IF EXISTS content_text";
+pub const DROP_EXTRA_COLUMN: &str = "ALTER TABLE conversations DROP COLUMN IF EXISTS extra";
+pub const DROP_IS_SIDECHAIN_COLUMN: &str = "ALTER TABLE conversations DROP COLUMN IF EXISTS is_sidechain";
+pub const DROP_MESSAGE_EXTRA_COLUMN: &str = "ALTER TABLE conversations DROP COLUMN IF EXISTS message_extra";
+
+// DuckDB refuses to drop a column while the table has indexes, so migrations
+// that drop one take the indexes down first and recreate them afterwards.
+pub const DROP_CONVERSATIONS_INDEXES: &str = r#"
+DROP INDEX IF EXISTS idx_conversations_uuid;
+DROP INDEX IF EXISTS idx_conversations_session;
+DROP INDEX IF EXISTS idx_conversations_timestamp;
+DROP INDEX IF EXISTS idx_conversations_project"#;
+
+/// A numbered schema change. `up` statements must be safe to re-run, because
+/// vaults created before `schema_version` existed replay every migration.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub up: &'static [&'static str],
+    pub down: &'static [&'static str],
 }
 
-impl<'a> SchemaManager<'a> {
-    pub fn new(connection: &'a dyn DatabaseConnection) -> Self {
+/// Every schema change in order. Append new migrations; never edit applied ones.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "conversations table",
+        up: &[
+            CREATE_CONVERSATIONS_SEQUENCE,
+            // As the first release created it; later columns come from later migrations
+            r#"
+CREATE TABLE IF NOT EXISTS conversations (
+    id INTEGER PRIMARY KEY DEFAULT nextval('conversations_id_seq'),
+    uuid TEXT NOT NULL UNIQUE,
+    parent_uuid TEXT,
+    session_id TEXT NOT NULL,
+    user_type TEXT NOT NULL,
+    message_type TEXT NOT NULL,
+    message_role TEXT,
+    message_content TEXT,
+    project_path TEXT NOT NULL,
+    cwd TEXT NOT NULL,
+    git_branch TEXT,
+    version TEXT NOT NULL,
+    timestamp TIMESTAMP NOT NULL,
+    is_favorite BOOLEAN DEFAULT FALSE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+)"#,
+            CREATE_UUID_INDEX,
+            CREATE_SESSION_INDEX,
+            CREATE_TIMESTAMP_INDEX,
+            CREATE_PROJECT_INDEX,
+        ],
+        down: &[DROP_FTS_INDEX, DROP_CONVERSATIONS_TABLE, DROP_CONVERSATIONS_SEQUENCE],
+    },
+    Migration {
+        version: 2,
+        description: "plain message text and content blocks",
+        up: &[ADD_CONTENT_TEXT_COLUMN, CREATE_CONTENT_BLOCKS_TABLE, CREATE_CONTENT_BLOCKS_TOOL_USE_INDEX],
+        // The FTS index is built over content_text
+        down: &[
+            DROP_FTS_INDEX,
+            DROP_CONTENT_BLOCKS_TABLE,
+            DROP_CONVERSATIONS_INDEXES,
+            DROP_CONTENT_TEXT_COLUMN,
+            CREATE_UUID_INDEX,
+            CREATE_SESSION_INDEX,
+            CREATE_TIMESTAMP_INDEX,
+            CREATE_PROJECT_INDEX,
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "tool calls",
+        up: &[CREATE_TOOL_CALLS_TABLE],
+        down: &[DROP_TOOL_CALLS_TABLE],
+    },
+    Migration {
+        version: 4,
+        description: "CJK bigram index",
+        up: &[CREATE_CJK_BIGRAMS_TABLE, CREATE_CJK_BIGRAMS_GRAM_INDEX],
+        down: &[DROP_CJK_BIGRAMS_TABLE],
+    },
+    Migration {
+        version: 5,
+        description: "sync state",
+        up: &[CREATE_IMPORT_STATE_TABLE],
+        down: &[DROP_IMPORT_STATE_TABLE],
+    },
+    Migration {
+        version: 6,
+        description: "summaries and unparsed record fields",
+        up: &[CREATE_SUMMARIES_TABLE, ADD_EXTRA_COLUMN],
+        down: &[
+            DROP_SUMMARIES_TABLE,
+            DROP_CONVERSATIONS_INDEXES,
+            DROP_EXTRA_COLUMN,
+            CREATE_UUID_INDEX,
+            CREATE_SESSION_INDEX,
+            CREATE_TIMESTAMP_INDEX,
+            CREATE_PROJECT_INDEX,
+        ],
+    },
+    Migration {
+        version: 7,
+        description: "favorites by uuid or session",
+        up: &[CREATE_FAVORITES_TABLE, COPY_LEGACY_FAVORITES],
+        down: &[RESTORE_LEGACY_FAVORITES, DROP_FAVORITES_TABLE],
+    },
+    Migration {
+        version: 8,
+        description: "tags",
+        up: &[CREATE_TAGS_TABLE, CREATE_TAGGINGS_TABLE],
+        down: &[DROP_TAGGINGS_TABLE, DROP_TAGS_TABLE],
+    },
+    Migration {
+        version: 9,
+        description: "notes",
+        up: &[CREATE_NOTES_SEQUENCE, CREATE_NOTES_TABLE],
+        down: &[DROP_NOTES_FTS_INDEX, DROP_NOTES_TABLE, DROP_NOTES_SEQUENCE],
+    },
+    Migration {
+        version: 10,
+        description: "raw transcript archive",
+        up: &[CREATE_RAW_LINES_TABLE],
+        down: &[DROP_RAW_LINES_TABLE],
+    },
+    Migration {
+        version: 11,
+        description: "sidechain flag and API message fields",
+        up: &[ADD_IS_SIDECHAIN_COLUMN, ADD_MESSAGE_EXTRA_COLUMN],
+        down: &[
+            DROP_CONVERSATIONS_INDEXES,
+            DROP_MESSAGE_EXTRA_COLUMN,
+            DROP_IS_SIDECHAIN_COLUMN,
+            CREATE_UUID_INDEX,
+            CREATE_SESSION_INDEX,
+            CREATE_TIMESTAMP_INDEX,
+            CREATE_PROJECT_INDEX,
+        ],
+    },
+];
+
+/// The schema version this build expects.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// A migration and when it was applied, if it has been.
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: u32,
+    pub description: &'static str,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+/// Moves a vault between schema versions, one transaction per migration.
+pub struct Migrator<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
+}
+
+impl<'a, C: ExtendedDatabaseConnection> Migrator<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
         Self { connection }
     }
 
-    pub fn create_schema(&self) -> Result<()> {
+    /// The highest applied version, 0 for an empty or pre-versioning vault.
+    pub fn current_version(&self) -> Result<u32> {
         if !self.connection.is_connected() {
             return Err(anyhow!("Database not connected"));
         }
+        self.connection.execute(CREATE_SCHEMA_VERSION_TABLE)?;
 
-        // Create sequence first
-        self.connection.execute(CREATE_CONVERSATIONS_SEQUENCE)?;
-        
-        // Create main table
-        self.connection.execute(CREATE_CONVERSATIONS_TABLE)?;
-        
-        // Create indexes
-        self.connection.execute(CREATE_UUID_INDEX)?;
-        self.connection.execute(CREATE_SESSION_INDEX)?;
-        self.connection.execute(CREATE_TIMESTAMP_INDEX)?;
-        self.connection.execute(CREATE_PROJECT_INDEX)?;
-        
-        Ok(())
+        let version: Option<i64> = self.connection.query_row(
+            "SELECT max(version) FROM schema_version", &[], |row| Ok(row.get(0)?),
+        )?.flatten();
+        Ok(version.unwrap_or(0) as u32)
     }
 
-    pub fn create_fts_indexes(&self) -> Result<()> {
-        if !self.connection.is_connected() {
-            return Err(anyhow!("Database not connected"));
+    pub fn status(&self) -> Result<Vec<MigrationStatus>> {
+        self.current_version()?;
+        let applied: Vec<(u32, DateTime<Utc>)> = self.connection.query_all(
+            "SELECT version, epoch_us(applied_at) FROM schema_version", &[], |row| {
+                let applied_us: i64 = row.get(1)?;
+                let applied_at = DateTime::from_timestamp_micros(applied_us)
+                    .ok_or_else(|| anyhow!("Invalid timestamp: {}", applied_us))?;
+                Ok((row.get(0)?, applied_at))
+            },
+        )?;
+
+        Ok(MIGRATIONS.iter()
+            .map(|m| MigrationStatus {
+                version: m.version,
+                description: m.description,
+                applied_at: applied.iter().find(|(v, _)| *v == m.version).map(|(_, at)| *at),
+            })
+            .collect())
+    }
+
+    /// Applies or reverts migrations until the vault is at `target`, returning
+    /// the versions that ran in the order they ran.
+    pub fn migrate_to(&self, target: u32) -> Result<Vec<u32>> {
+        if target > latest_version() {
+            return Err(anyhow!("Unknown schema version {} (latest is {})", target, latest_version()));
         }
 
-        // Create FTS virtual table
-        self.connection.execute(CREATE_FTS_INDEX)?;
-        
-        // Create FTS triggers
-        self.connection.execute(CREATE_FTS_TRIGGERS)?;
-        
-        Ok(())
+        let current = self.current_version()?;
+        let mut ran = Vec::new();
+
+        if target > current {
+            for migration in MIGRATIONS.iter().filter(|m| m.version > current && m.version <= target) {
+                self.connection.transaction(|conn| {
+                    for statement in migration.up {
+                        conn.execute_batch(statement)?;
+                    }
+                    conn.execute(
+                        "INSERT INTO schema_version (version, description) VALUES (?, ?)",
+                        params![migration.version, migration.description],
+                    )?;
+                    Ok(())
+                }).map_err(|e| anyhow!("Migration {} ({}) failed: {}", migration.version, migration.description, e))?;
+                ran.push(migration.version);
+            }
+        } else {
+            for migration in MIGRATIONS.iter().rev().filter(|m| m.version <= current && m.version > target) {
+                self.connection.transaction(|conn| {
+                    for statement in migration.down {
+                        conn.execute_batch(statement)?;
+                    }
+                    conn.execute("DELETE FROM schema_version WHERE version = ?", params![migration.version])?;
+                    Ok(())
+                }).map_err(|e| anyhow!("Reverting migration {} ({}) failed: {}", migration.version, migration.description, e))?;
+                ran.push(migration.version);
+            }
+        }
+
+        Ok(ran)
     }
 
-    pub fn drop_schema(&self) -> Result<()> {
+    /// Startup check: brings an older vault up to date and refuses one written
+    /// by a newer build, whose schema this build does not know.
+    pub fn ensure_latest(&self) -> Result<Vec<u32>> {
+        let current = self.current_version()?;
+        if current > latest_version() {
+            return Err(anyhow!(
+                "Vault schema version {} is newer than this cc-vault supports ({}); please upgrade cc-vault",
+                current, latest_version()
+            ));
+        }
+        self.migrate_to(latest_version())
+    }
+}
+
+pub struct SchemaManager<'a> {
+    connection: &'a dyn DatabaseConnection,
+}
+
+impl<'a> SchemaManager<'a> {
+    pub fn new(connection: &'a dyn DatabaseConnection) -> Self {
+        Self { connection }
+    }
+
+    pub fn load_fts_extension(&self) -> Result<()> {
         if !self.connection.is_connected() {
             return Err(anyhow!("Database not connected"));
         }
 
-        // Drop FTS table first (due to foreign key constraints)
-        self.connection.execute(DROP_FTS_TABLE)?;
-        
-        // Drop main table
-        self.connection.execute(DROP_CONVERSATIONS_TABLE)?;
+        // INSTALL is a no-op once the extension is cached locally
+        self.connection.execute(INSTALL_FTS_EXTENSION)?;
+        self.connection.execute(LOAD_FTS_EXTENSION)?;
         
         Ok(())
     }
 
-    pub fn migrate_up(&self) -> Result<()> {
-        self.create_schema()?;
-        self.create_fts_indexes()?;
-        Ok(())
+    pub fn create_fts_indexes(&self) -> Result<()> {
+        self.load_fts_extension()?;
+
+        // Build (or rebuild) the BM25 index over the plain message text
+        self.connection.execute(CREATE_FTS_INDEX)?;
+        self.create_notes_fts_index()
     }
 
-    pub fn migrate_down(&self) -> Result<()> {
-        self.drop_schema()?;
+    /// Rebuilds only the notes index; expects the `fts` extension to be loaded.
+    pub fn create_notes_fts_index(&self) -> Result<()> {
+        self.connection.execute(CREATE_NOTES_FTS_INDEX)?;
         Ok(())
     }
 }
@@ -130,11 +542,12 @@ impl<'a> SchemaManager<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db_connection::MockDatabaseConnection;
+    use crate::real_db_connection::RealDuckDBConnection;
+    use crate::db_connection::{ConnectionConfig, MockDatabaseConnection, SqlValue};
     use mockall::predicate::*;
 
     #[test]
-    fn test_create_conversations_table() {
+    fn test_create_fts_indexes() {
         let mut mock_conn = MockDatabaseConnection::new();
         
         mock_conn.expect_is_connected()
@@ -142,158 +555,219 @@ mod tests {
             .returning(|| true);
             
         mock_conn.expect_execute()
-            .with(eq(CREATE_CONVERSATIONS_TABLE))
+            .with(eq(INSTALL_FTS_EXTENSION))
             .times(1)
             .returning(|_| Ok(()));
             
         mock_conn.expect_execute()
-            .with(eq(CREATE_UUID_INDEX))
+            .with(eq(LOAD_FTS_EXTENSION))
             .times(1)
             .returning(|_| Ok(()));
             
         mock_conn.expect_execute()
-            .with(eq(CREATE_SESSION_INDEX))
-            .times(1)
-            .returning(|_| Ok(()));
-            
-        mock_conn.expect_execute()
-            .with(eq(CREATE_TIMESTAMP_INDEX))
+            .with(eq(CREATE_FTS_INDEX))
             .times(1)
             .returning(|_| Ok(()));
             
         mock_conn.expect_execute()
-            .with(eq(CREATE_PROJECT_INDEX))
+            .with(eq(CREATE_NOTES_FTS_INDEX))
             .times(1)
             .returning(|_| Ok(()));
         
         let schema_manager = SchemaManager::new(&mock_conn);
-        let result = schema_manager.create_schema();
+        let result = schema_manager.create_fts_indexes();
         
         assert!(result.is_ok());
     }
 
+    /// A vault as the first release wrote it: schema version 1, one message.
+    const V1_VAULT: &str = r#"
+CREATE SEQUENCE conversations_id_seq START 1;
+CREATE TABLE conversations (
+    id INTEGER PRIMARY KEY DEFAULT nextval('conversations_id_seq'),
+    uuid TEXT NOT NULL UNIQUE,
+    parent_uuid TEXT,
+    session_id TEXT NOT NULL,
+    user_type TEXT NOT NULL,
+    message_type TEXT NOT NULL,
+    message_role TEXT,
+    message_content TEXT,
+    project_path TEXT NOT NULL,
+    cwd TEXT NOT NULL,
+    git_branch TEXT,
+    version TEXT NOT NULL,
+    timestamp TIMESTAMP NOT NULL,
+    is_favorite BOOLEAN DEFAULT FALSE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_conversations_uuid ON conversations(uuid);
+CREATE INDEX idx_conversations_session ON conversations(session_id);
+CREATE INDEX idx_conversations_timestamp ON conversations(timestamp);
+CREATE INDEX idx_conversations_project ON conversations(project_path);
+INSERT INTO conversations (uuid, session_id, user_type, message_type, message_role, message_content, project_path, cwd, version, timestamp, is_favorite)
+VALUES ('v1-uuid', 'v1-session', 'external', 'user', 'user', 'written by v1', '/v1/project', '/v1', '1.0.0', '2025-01-01 00:00:00', TRUE);
+"#;
+
+    fn v1_vault(versioned: bool) -> RealDuckDBConnection {
+        let conn = RealDuckDBConnection::new(ConnectionConfig {
+            database: ":memory:".to_string(),
+            ..Default::default()
+        });
+        conn.connect().unwrap();
+        conn.execute(V1_VAULT).unwrap();
+        if versioned {
+            conn.execute(CREATE_SCHEMA_VERSION_TABLE).unwrap();
+            conn.execute("INSERT INTO schema_version (version, description) VALUES (1, 'conversations table')").unwrap();
+        }
+        conn
+    }
+
+    fn table_exists(conn: &RealDuckDBConnection, table: &str) -> bool {
+        let count: Option<i64> = conn.query_row(
+            "SELECT count(*) FROM information_schema.tables WHERE table_name = ?",
+            &[SqlValue::Text(table.to_string())], |row| Ok(row.get(0)?),
+        ).unwrap();
+        count == Some(1)
+    }
+
+    fn columns(conn: &RealDuckDBConnection, table: &str) -> Vec<String> {
+        conn.query_all(
+            "SELECT column_name FROM information_schema.columns WHERE table_name = ? ORDER BY ordinal_position",
+            &[SqlValue::Text(table.to_string())], |row| Ok(row.get(0)?),
+        ).unwrap()
+    }
+
     #[test]
-    fn test_create_schema_when_not_connected() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        mock_conn.expect_is_connected()
-            .times(1)
-            .returning(|| false);
-        
-        let schema_manager = SchemaManager::new(&mock_conn);
-        let result = schema_manager.create_schema();
-        
-        assert!(result.is_err());
+    fn test_migrator_when_not_connected() {
+        let conn = RealDuckDBConnection::new(ConnectionConfig::default());
+        let result = Migrator::new(&conn).ensure_latest();
         assert!(result.unwrap_err().to_string().contains("Database not connected"));
     }
 
     #[test]
-    fn test_create_fts_indexes() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        mock_conn.expect_is_connected()
-            .times(1)
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .with(eq(CREATE_FTS_INDEX))
-            .times(1)
-            .returning(|_| Ok(()));
-            
-        mock_conn.expect_execute()
-            .with(eq(CREATE_FTS_TRIGGERS))
-            .times(1)
-            .returning(|_| Ok(()));
-        
-        let schema_manager = SchemaManager::new(&mock_conn);
-        let result = schema_manager.create_fts_indexes();
-        
-        assert!(result.is_ok());
+    fn test_new_vault_gets_every_migration() {
+        let conn = RealDuckDBConnection::new(ConnectionConfig {
+            database: ":memory:".to_string(),
+            ..Default::default()
+        });
+        conn.connect().unwrap();
+        let migrator = Migrator::new(&conn);
+
+        // Version 1 is the table exactly as the first release created it
+        migrator.migrate_to(1).unwrap();
+        let v1_columns = columns(&conn, "conversations");
+        assert!(!v1_columns.contains(&"content_text".to_string()));
+        assert!(!v1_columns.contains(&"extra".to_string()));
+
+        assert_eq!(migrator.ensure_latest().unwrap(), (2..=latest_version()).collect::<Vec<_>>());
+        assert!(migrator.ensure_latest().unwrap().is_empty());
+        let latest_columns = columns(&conn, "conversations");
+        assert!(latest_columns.contains(&"content_text".to_string()));
+        assert!(latest_columns.contains(&"extra".to_string()));
+        for table in ["content_blocks", "tool_calls", "cjk_bigrams", "import_state", "summaries", "favorites", "tags", "taggings", "notes", "raw_lines"] {
+            assert!(table_exists(&conn, table), "{} missing", table);
+        }
+
+        migrator.migrate_to(0).unwrap();
+        assert!(!table_exists(&conn, "conversations"));
     }
 
     #[test]
-    fn test_drop_schema() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        mock_conn.expect_is_connected()
-            .times(1)
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .with(eq(DROP_FTS_TABLE))
-            .times(1)
-            .returning(|_| Ok(()));
-            
-        mock_conn.expect_execute()
-            .with(eq(DROP_CONVERSATIONS_TABLE))
-            .times(1)
-            .returning(|_| Ok(()));
-        
-        let schema_manager = SchemaManager::new(&mock_conn);
-        let result = schema_manager.drop_schema();
-        
-        assert!(result.is_ok());
+    fn test_migrations_are_numbered_in_order() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, i + 1);
+        }
+        assert_eq!(latest_version(), MIGRATIONS.len() as u32);
     }
 
     #[test]
-    fn test_migrate_up() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        // Expect is_connected to be called twice (once for create_schema, once for create_fts_indexes)
-        mock_conn.expect_is_connected()
-            .times(2)
-            .returning(|| true);
-            
-        // Expect all table and index creation calls
-        mock_conn.expect_execute()
-            .times(7)  // 5 for create_schema + 2 for create_fts_indexes
-            .returning(|_| Ok(()));
-        
-        let schema_manager = SchemaManager::new(&mock_conn);
-        let result = schema_manager.migrate_up();
-        
-        assert!(result.is_ok());
+    fn test_upgrade_v1_vault() {
+        let conn = v1_vault(true);
+        let migrator = Migrator::new(&conn);
+        assert_eq!(migrator.current_version().unwrap(), 1);
+
+        assert_eq!(migrator.ensure_latest().unwrap(), (2..=latest_version()).collect::<Vec<_>>());
+        assert_eq!(migrator.current_version().unwrap(), latest_version());
+        assert!(migrator.ensure_latest().unwrap().is_empty());
+
+        // The v1 row survives, and new columns start out empty
+        let row: Option<(String, bool, Option<String>, Option<String>)> = conn.query_row(
+            "SELECT message_content, is_favorite, content_text, extra FROM conversations WHERE uuid = 'v1-uuid'",
+            &[], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        ).unwrap();
+        assert_eq!(row, Some(("written by v1".to_string(), true, None, None)));
+        let row: Option<(bool, Option<String>)> = conn.query_row(
+            "SELECT is_sidechain, message_extra FROM conversations WHERE uuid = 'v1-uuid'",
+            &[], |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!(row, Some((false, None)));
+        let favorites: Vec<String> = conn.query_all(
+            "SELECT target_id FROM favorites WHERE target_kind = 'message'", &[], |row| Ok(row.get(0)?),
+        ).unwrap();
+        assert_eq!(favorites, vec!["v1-uuid"]);
+        for table in ["content_blocks", "tool_calls", "cjk_bigrams", "import_state", "summaries", "favorites", "tags", "taggings", "notes", "raw_lines"] {
+            assert!(table_exists(&conn, table), "{} missing", table);
+        }
+
+        // New messages go in next to the old one
+        let message = crate::jsonl_parser::JsonlParser::new().parse_single_message(
+            r#"{"parentUuid":"v1-uuid","sessionId":"v1-session","userType":"external","cwd":"/v1","version":"1.0.0","type":"assistant","message":{"role":"assistant","content":"written after the upgrade"},"uuid":"v2-uuid","timestamp":"2025-01-01T00:01:00Z"}"#,
+        ).unwrap();
+        crate::test_support::import_messages(&conn, [&message], "/v1/project");
+        let count: Option<i64> = conn.query_row("SELECT count(*) FROM conversations", &[], |row| Ok(row.get(0)?)).unwrap();
+        assert_eq!(count, Some(2));
     }
 
     #[test]
-    fn test_migrate_down() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        mock_conn.expect_is_connected()
-            .times(1)
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .times(2)  // DROP_FTS_TABLE and DROP_CONVERSATIONS_TABLE
-            .returning(|_| Ok(()));
-        
-        let schema_manager = SchemaManager::new(&mock_conn);
-        let result = schema_manager.migrate_down();
-        
-        assert!(result.is_ok());
+    fn test_unversioned_vault_replays_all_migrations() {
+        let conn = v1_vault(false);
+        let migrator = Migrator::new(&conn);
+        assert_eq!(migrator.current_version().unwrap(), 0);
+
+        assert_eq!(migrator.ensure_latest().unwrap(), (1..=latest_version()).collect::<Vec<_>>());
+        assert!(migrator.status().unwrap().iter().all(|m| m.applied_at.is_some()));
     }
 
     #[test]
-    fn test_idempotent_schema_creation() {
-        let mut mock_conn = MockDatabaseConnection::new();
-        
-        // Set up expectations for two consecutive migrate_up calls
-        mock_conn.expect_is_connected()
-            .times(4)  // 2 calls per migrate_up, 2 migrate_up calls
-            .returning(|| true);
-            
-        mock_conn.expect_execute()
-            .times(14)  // 7 calls per migrate_up, 2 migrate_up calls
-            .returning(|_| Ok(()));
-        
-        let schema_manager = SchemaManager::new(&mock_conn);
-        
-        // First migration
-        let result1 = schema_manager.migrate_up();
-        assert!(result1.is_ok());
-        
-        // Second migration (should be idempotent)
-        let result2 = schema_manager.migrate_up();
-        assert!(result2.is_ok());
+    fn test_migrate_down_and_back_up() {
+        let conn = v1_vault(true);
+        let migrator = Migrator::new(&conn);
+        migrator.ensure_latest().unwrap();
+
+        assert_eq!(migrator.migrate_to(3).unwrap(), (4..=latest_version()).rev().collect::<Vec<_>>());
+        assert_eq!(migrator.current_version().unwrap(), 3);
+        assert!(table_exists(&conn, "tool_calls"));
+        assert!(!table_exists(&conn, "cjk_bigrams"));
+        assert!(!table_exists(&conn, "summaries"));
+        let pending: Vec<u32> = migrator.status().unwrap().iter()
+            .filter(|m| m.applied_at.is_none())
+            .map(|m| m.version)
+            .collect();
+        assert_eq!(pending, (4..=latest_version()).collect::<Vec<_>>());
+
+        migrator.migrate_to(latest_version()).unwrap();
+        assert!(table_exists(&conn, "summaries"));
+
+        // All the way back to v1 keeps the original rows
+        migrator.migrate_to(1).unwrap();
+        assert!(!table_exists(&conn, "content_blocks"));
+        let count: Option<i64> = conn.query_row("SELECT count(*) FROM conversations", &[], |row| Ok(row.get(0)?)).unwrap();
+        assert_eq!(count, Some(1));
+        migrator.migrate_to(latest_version()).unwrap();
+
+        let err = migrator.migrate_to(latest_version() + 1).unwrap_err();
+        assert!(err.to_string().contains("Unknown schema version"));
+    }
+
+    #[test]
+    fn test_newer_vault_is_refused() {
+        let conn = v1_vault(true);
+        conn.execute(&format!(
+            "INSERT INTO schema_version (version, description) VALUES ({}, 'from the future')", latest_version() + 1
+        )).unwrap();
+
+        let err = Migrator::new(&conn).ensure_latest().unwrap_err();
+        assert!(err.to_string().contains("newer than this cc-vault supports"));
     }
-}
\ No newline at end of file
+}
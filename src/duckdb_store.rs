@@ -0,0 +1,167 @@
+use anyhow::Result;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use crate::data_importer::{DataImporter, ImportStats};
+use crate::db_schema::SchemaManager;
+use crate::export::{ExportFormat, ExportRow, RowExporter};
+use crate::favorites::{Favorite, FavoriteStore};
+use crate::jsonl_parser::{ClaudeMessage, JsonlRecord};
+use crate::notes::{Note, NoteStore};
+use crate::raw_archive::{RawArchiveStore, RawSource};
+use crate::real_db_connection::ExtendedDatabaseConnection;
+use crate::search::{SearchEngine, SearchQuery, SearchResult};
+use crate::session::{SessionStore, SessionThread};
+use crate::store::{Target, VaultStats, VaultStore};
+use crate::sync::{ImportState, ImportStateStore};
+use crate::tags::{TagCount, TagStore};
+use crate::tool_calls::{ToolCall, ToolCallQuery, ToolCallSearch};
+
+/// The vault stored in DuckDB, the backend used by the CLI.
+#[allow(dead_code)]
+const VAULT_STATS: &str = r#"
+SELECT count(*),
+       count(DISTINCT session_id),
+       count(DISTINCT project_path),
+       (SELECT count(*) FROM favorites),
+       (SELECT count(*) FROM summaries)
+FROM conversations"#;
+
+pub struct DuckDBStore<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
+}
+
+impl<'a, C: ExtendedDatabaseConnection> DuckDBStore<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
+        Self { connection }
+    }
+}
+
+impl<C: ExtendedDatabaseConnection> VaultStore for DuckDBStore<'_, C> {
+    fn upsert_messages(
+        &self,
+        records: &mut dyn Iterator<Item = Result<JsonlRecord>>,
+        project_path: &str,
+        force: bool,
+    ) -> Result<ImportStats> {
+        DataImporter::new(self.connection).bulk_import(records, project_path, force)
+    }
+
+    /// The FTS index is a snapshot, so it is rebuilt to include new messages.
+    fn refresh_search_index(&self) -> Result<()> {
+        SchemaManager::new(self.connection).create_fts_indexes()
+    }
+
+    fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>> {
+        SearchEngine::new(self.connection).search(query)
+    }
+
+    fn export_rows(&self, query: &SearchQuery) -> Result<Vec<ExportRow>> {
+        RowExporter::new(self.connection).rows(query)
+    }
+
+    fn copy_rows(&self, query: &SearchQuery, format: ExportFormat, path: &Path) -> Result<()> {
+        RowExporter::new(self.connection).copy(query, format, path)
+    }
+
+    fn find_tool_calls(&self, query: &ToolCallQuery) -> Result<Vec<ToolCall>> {
+        ToolCallSearch::new(self.connection).find(query)
+    }
+
+    fn add_favorite(&self, target: &Target, note: Option<&str>) -> Result<()> {
+        FavoriteStore::new(self.connection).add(target, note)
+    }
+
+    fn remove_favorite(&self, target: &Target) -> Result<bool> {
+        FavoriteStore::new(self.connection).remove(target)
+    }
+
+    fn favorites(&self) -> Result<Vec<Favorite>> {
+        FavoriteStore::new(self.connection).list()
+    }
+
+    fn add_tag(&self, target: &Target, tag: &str) -> Result<()> {
+        TagStore::new(self.connection).add(target, tag)
+    }
+
+    fn remove_tag(&self, target: &Target, tag: &str) -> Result<bool> {
+        TagStore::new(self.connection).remove(target, tag)
+    }
+
+    fn tags_of(&self, target: &Target) -> Result<Vec<String>> {
+        TagStore::new(self.connection).tags_of(target)
+    }
+
+    fn tag_counts(&self) -> Result<Vec<TagCount>> {
+        TagStore::new(self.connection).counts()
+    }
+
+    fn note(&self, target: &Target) -> Result<Option<Note>> {
+        NoteStore::new(self.connection).get(target)
+    }
+
+    fn save_note(&self, target: &Target, body: &str) -> Result<()> {
+        NoteStore::new(self.connection).save(target, body)?;
+        // Without the fts extension, searches match notes as substrings
+        let _ = SchemaManager::new(self.connection).create_notes_fts_index();
+        Ok(())
+    }
+
+    fn remove_note(&self, target: &Target) -> Result<bool> {
+        let removed = NoteStore::new(self.connection).remove(target)?;
+        let _ = SchemaManager::new(self.connection).create_notes_fts_index();
+        Ok(removed)
+    }
+
+    fn notes(&self) -> Result<Vec<Note>> {
+        NoteStore::new(self.connection).list()
+    }
+
+    fn resolve_session(&self, id: &str) -> Result<Option<(String, Option<String>)>> {
+        SessionStore::new(self.connection).resolve(id)
+    }
+
+    fn load_session(&self, session_id: &str) -> Result<SessionThread> {
+        SessionStore::new(self.connection).load_thread(session_id)
+    }
+
+    fn session_messages(&self, session_id: &str) -> Result<Vec<ClaudeMessage>> {
+        SessionStore::new(self.connection).load_full_messages(session_id)
+    }
+
+    fn session_summaries(&self, session_id: &str) -> Result<Vec<String>> {
+        SessionStore::new(self.connection).summaries(session_id)
+    }
+
+    fn import_state(&self, source_path: &str) -> Result<Option<ImportState>> {
+        ImportStateStore::new(self.connection).get(source_path)
+    }
+
+    fn save_import_state(&self, state: &ImportState) -> Result<()> {
+        ImportStateStore::new(self.connection).save(state)
+    }
+
+    fn archived_len(&self, source_path: &str) -> Result<Option<u64>> {
+        RawArchiveStore::new(self.connection).archived_len(source_path)
+    }
+
+    fn archive_raw(&self, source: &RawSource, offset: u64, lines: &mut dyn BufRead) -> Result<()> {
+        RawArchiveStore::new(self.connection).append(source, offset, lines)
+    }
+
+    fn raw_source(&self, session_id: &str) -> Result<Option<RawSource>> {
+        RawArchiveStore::new(self.connection).source(session_id)
+    }
+
+    fn write_raw_file(&self, source_path: &str, out: &mut dyn Write) -> Result<u64> {
+        RawArchiveStore::new(self.connection).write_file(source_path, out)
+    }
+
+    fn stats(&self) -> Result<VaultStats> {
+        let counts: Option<[i64; 5]> = self.connection.query_row(VAULT_STATS, &[], |row| {
+            Ok([row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?])
+        })?;
+        let [messages, sessions, projects, favorites, summaries] = counts.unwrap_or_default().map(|n| n as usize);
+
+        Ok(VaultStats { messages, sessions, projects, favorites, summaries })
+    }
+}
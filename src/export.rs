@@ -0,0 +1,552 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+use crate::jsonl_parser::{ClaudeMessage, ContentBlock};
+use crate::real_db_connection::ExtendedDatabaseConnection;
+use crate::search::{SearchEngine, SearchQuery};
+use crate::store::VaultStore;
+
+/// Output formats of the `export` command. Markdown and Obsidian render whole
+/// sessions; the others write one `ExportRow` per matching message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    /// A JSON array of rows
+    Json,
+    /// One JSON row per line
+    Ndjson,
+    Csv,
+    Parquet,
+    /// Markdown notes with front matter and links, one per session, in an Obsidian vault
+    Obsidian,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "json" => Ok(ExportFormat::Json),
+            "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            "obsidian" => Ok(ExportFormat::Obsidian),
+            other => Err(anyhow!(
+                "Unknown export format '{}' (expected markdown, json, ndjson, csv, parquet or obsidian)",
+                other
+            )),
+        }
+    }
+
+    /// File extension used when writing one file per session.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown | ExportFormat::Obsidian => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    /// Options of DuckDB's `COPY ... TO` for the formats DuckDB writes itself.
+    pub fn copy_options(&self) -> Option<&'static str> {
+        match self {
+            ExportFormat::Csv => Some("FORMAT csv, HEADER"),
+            ExportFormat::Parquet => Some("FORMAT parquet"),
+            ExportFormat::Markdown | ExportFormat::Json | ExportFormat::Ndjson | ExportFormat::Obsidian => None,
+        }
+    }
+}
+
+/// One message as the json, ndjson, csv and parquet exports write it.
+///
+/// This is the documented export schema. Fields are only ever added, never
+/// renamed, retyped or removed, so scripts built on an export keep working when
+/// the vault's own tables change. CSV and Parquet have the same columns in the
+/// same order, except that `timestamp` is a UTC `TIMESTAMP` and `tags` is
+/// comma-separated (tags never contain commas; see `normalize_tag`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportRow {
+    pub uuid: String,
+    pub parent_uuid: Option<String>,
+    pub session_id: String,
+    pub project_path: String,
+    /// RFC 3339 in UTC
+    pub timestamp: DateTime<Utc>,
+    /// `user`, `assistant` or `system`
+    #[serde(rename = "type")]
+    pub message_type: String,
+    pub role: Option<String>,
+    /// The message's text, without tool calls, tool results or thinking
+    pub content: Option<String>,
+    pub git_branch: Option<String>,
+    /// Version of Claude Code that recorded the message
+    pub version: String,
+    /// Favorited directly or through its session
+    pub is_favorite: bool,
+    /// Tags on the message and on its session, in name order
+    pub tags: Vec<String>,
+    /// The note on the message
+    pub note: Option<String>,
+    /// The note on the message's session
+    pub session_note: Option<String>,
+}
+
+/// Writes `rows` as a JSON array or as NDJSON.
+pub fn write_json_rows(rows: &[ExportRow], format: ExportFormat, out: &mut dyn std::io::Write) -> Result<()> {
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, rows)?;
+            writeln!(out)?;
+        }
+        ExportFormat::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut *out, row)?;
+                writeln!(out)?;
+            }
+        }
+        other => return Err(anyhow!("{:?} exports are not written as JSON", other)),
+    }
+    Ok(())
+}
+
+/// The `ExportRow` columns, in order, for the messages of the search statement
+/// `{hits}`, oldest first. The search already computes favorites, tags and notes;
+/// the other fields come from joining back to `conversations`.
+const EXPORT_ROWS: &str = r#"
+SELECT c.uuid,
+       c.parent_uuid,
+       c.session_id,
+       c.project_path,
+       {timestamp} AS timestamp,
+       c.message_type AS type,
+       c.message_role AS role,
+       c.content_text AS content,
+       c.git_branch,
+       c.version,
+       hits.is_favorite,
+       hits.tags,
+       hits.message_note AS note,
+       hits.session_note
+FROM ({hits}) hits JOIN conversations c ON c.id = hits.id
+ORDER BY c.timestamp, c.id"#;
+
+/// Reads rows in the `ExportRow` schema, and has DuckDB write them to CSV or
+/// Parquet itself.
+pub struct RowExporter<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
+}
+
+impl<'a, C: ExtendedDatabaseConnection> RowExporter<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
+        Self { connection }
+    }
+
+    pub fn rows(&self, query: &SearchQuery) -> Result<Vec<ExportRow>> {
+        let (hits, params) = SearchEngine::new(self.connection).matching_sql(query)?;
+        let sql = EXPORT_ROWS.replace("{timestamp}", "epoch_us(c.timestamp)").replace("{hits}", &hits);
+        self.connection.query_all(&sql, params.values(), |row| {
+            let timestamp_us: i64 = row.get(4)?;
+            Ok(ExportRow {
+                uuid: row.get(0)?,
+                parent_uuid: row.get(1)?,
+                session_id: row.get(2)?,
+                project_path: row.get(3)?,
+                timestamp: DateTime::from_timestamp_micros(timestamp_us)
+                    .ok_or_else(|| anyhow!("Invalid timestamp: {}", timestamp_us))?,
+                message_type: row.get(5)?,
+                role: row.get(6)?,
+                content: row.get(7)?,
+                git_branch: row.get(8)?,
+                version: row.get(9)?,
+                is_favorite: row.get::<_, Option<bool>>(10)?.unwrap_or(false),
+                tags: row.get::<_, Option<String>>(11)?
+                    .map(|tags| tags.split(',').map(str::to_string).collect())
+                    .unwrap_or_default(),
+                note: row.get(12)?,
+                session_note: row.get(13)?,
+            })
+        })
+    }
+
+    /// Has DuckDB write the rows `rows` would return straight to `path`.
+    pub fn copy(&self, query: &SearchQuery, format: ExportFormat, path: &Path) -> Result<()> {
+        let options = format.copy_options()
+            .ok_or_else(|| anyhow!("{:?} exports are not written by DuckDB", format))?;
+        let path = path.to_str()
+            .ok_or_else(|| anyhow!("Export path {} is not valid UTF-8", path.display()))?;
+        let (hits, params) = SearchEngine::new(self.connection).matching_sql(query)?;
+        // COPY cannot take its target as a parameter
+        let sql = format!(
+            "COPY ({}) TO '{}' ({})",
+            EXPORT_ROWS.replace("{timestamp}", "c.timestamp").replace("{hits}", &hits),
+            path.replace('\'', "''"),
+            options
+        );
+        self.connection.execute_with_params(&sql, params.values())
+    }
+}
+
+/// One session prepared for export: the messages along one branch of its
+/// thread and what the header shows about them.
+#[derive(Debug, Clone)]
+pub struct SessionExport {
+    pub session_id: String,
+    pub project_path: String,
+    /// Newest first, as `VaultStore::session_summaries` returns them
+    pub summaries: Vec<String>,
+    /// Root to leaf along the exported branch
+    pub messages: Vec<ClaudeMessage>,
+    /// How many of the session's messages are on other branches and left out
+    pub skipped_messages: usize,
+}
+
+impl SessionExport {
+    /// Loads the branch through the message `through`, or through the session's
+    /// most recent message, which is the branch that was continued last.
+    pub fn load(store: &dyn VaultStore, session_id: &str, through: Option<&str>) -> Result<Self> {
+        let thread = store.load_session(session_id)?;
+        let leaf = through
+            .or_else(|| thread.messages().last().map(|m| m.uuid.as_str()))
+            .ok_or_else(|| anyhow!("Session {} has no messages", session_id))?;
+        let path = thread.path_through(leaf);
+
+        let mut by_uuid: HashMap<String, ClaudeMessage> = store.session_messages(session_id)?
+            .into_iter()
+            .map(|message| (message.uuid.clone(), message))
+            .collect();
+        let messages: Vec<ClaudeMessage> = path.iter()
+            .filter_map(|message| by_uuid.remove(&message.uuid))
+            .collect();
+
+        Ok(Self {
+            session_id: session_id.to_string(),
+            project_path: path.first().map(|m| m.project_path.clone()).unwrap_or_default(),
+            summaries: store.session_summaries(session_id)?,
+            skipped_messages: thread.len() - messages.len(),
+            messages,
+        })
+    }
+
+    /// The newest summary, or else the start of the first prompt.
+    pub fn title(&self) -> String {
+        if let Some(summary) = self.summaries.first() {
+            return summary.clone();
+        }
+        self.messages.iter()
+            .filter(|m| m.message_type == "user" && !is_meta(m))
+            .find_map(|m| m.message.plain_text())
+            .map(|text| {
+                let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                match line.char_indices().nth(TITLE_CHARS) {
+                    Some((end, _)) => format!("{}…", &line[..end]),
+                    None => line,
+                }
+            })
+            .unwrap_or_else(|| format!("Session {}", self.session_id))
+    }
+}
+
+/// How many characters of the first prompt title a session without a summary.
+const TITLE_CHARS: usize = 80;
+
+/// A single keyword naming a session or message selects that session (and,
+/// for a message, its branch). Otherwise `query` is searched for, selecting every
+/// session with a hit through its best hit. Returns `(session_id, message_uuid)`
+/// pairs for `SessionExport::load`.
+pub fn find_sessions(store: &dyn VaultStore, query: &SearchQuery) -> Result<Vec<(String, Option<String>)>> {
+    if let [id] = query.keywords.as_slice() {
+        if let Some(found) = store.resolve_session(id)? {
+            return Ok(vec![found]);
+        }
+    }
+
+    let hits = store.search(query)?;
+    let mut seen = HashSet::new();
+    let sessions: Vec<(String, Option<String>)> = hits.into_iter()
+        .filter(|hit| seen.insert(hit.session_id.clone()))
+        .map(|hit| (hit.session_id, Some(hit.uuid)))
+        .collect();
+    if sessions.is_empty() {
+        return Err(anyhow!("No session, message or search hit for '{}'", query.keywords.join(" ")));
+    }
+    Ok(sessions)
+}
+
+/// Renders the header, then every turn: prose as-is under a role heading, and
+/// thinking, tool calls and tool results as collapsed `<details>` sections.
+/// Messages Claude Code marks `isMeta` are left out.
+pub fn render_markdown(export: &SessionExport) -> String {
+    let mut out = String::new();
+    let title = export.summaries.first().cloned()
+        .unwrap_or_else(|| format!("Session {}", export.session_id));
+    let _ = writeln!(out, "# {}\n", title);
+    let _ = writeln!(out, "- **Project:** `{}`", export.project_path);
+    let _ = writeln!(out, "- **Session:** `{}`", export.session_id);
+    let branches = distinct(export.messages.iter().filter_map(|m| m.git_branch.as_deref()));
+    if !branches.is_empty() {
+        let _ = writeln!(out, "- **Branch:** {}", branches.join(", "));
+    }
+    let versions = distinct(export.messages.iter().map(|m| m.version.as_str()));
+    if !versions.is_empty() {
+        let _ = writeln!(out, "- **Claude Code:** {}", versions.join(", "));
+    }
+    if let (Some(first), Some(last)) = (export.messages.first(), export.messages.last()) {
+        let _ = writeln!(
+            out,
+            "- **Time:** {} – {} UTC",
+            first.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            last.timestamp.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    if export.skipped_messages > 0 {
+        let _ = writeln!(out, "\n_{} messages on other branches are not included._", export.skipped_messages);
+    }
+    out.push_str("\n---\n\n");
+    render_turns(&mut out, export, details);
+    out
+}
+
+/// Writes a collapsed section: `(out, summary, body)`.
+pub(crate) type Fold = fn(&mut String, &str, &str);
+
+/// Appends every turn of the export, folding thinking and tool traffic with
+/// `fold`, and ends `out` with a single newline.
+pub(crate) fn render_turns(out: &mut String, export: &SessionExport, fold: Fold) {
+    let tool_names = tool_names(&export.messages);
+    for message in export.messages.iter().filter(|m| !is_meta(m)) {
+        render_message(out, message, &tool_names, fold);
+    }
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+}
+
+fn render_message(out: &mut String, message: &ClaudeMessage, tool_names: &HashMap<String, String>, fold: Fold) {
+    let blocks = message.message.blocks();
+    // Tool traffic without prose reads as part of the turn before it
+    if has_text(message) {
+        let role = message.message.role.as_deref().unwrap_or(&message.message_type);
+        let _ = writeln!(out, "## {}\n", capitalize(role));
+        let _ = writeln!(out, "_{}_\n", message.timestamp.format("%Y-%m-%d %H:%M:%S"));
+    }
+
+    for block in blocks {
+        match block {
+            ContentBlock::Text { text } if text.trim().is_empty() => {}
+            ContentBlock::Text { text } => {
+                let _ = writeln!(out, "{}\n", text.trim());
+            }
+            ContentBlock::Thinking { thinking } => fold(out, "Thinking", thinking.trim()),
+            ContentBlock::ToolUse { name, input, .. } => {
+                let input = serde_json::to_string_pretty(&input).unwrap_or_default();
+                fold(out, &format!("Tool: {}", name), &fenced(&input, "json"));
+            }
+            ContentBlock::ToolResult { tool_use_id, content, is_error } => {
+                let name = tool_names.get(&tool_use_id).map(String::as_str).unwrap_or("tool");
+                let status = if is_error { " (error)" } else { "" };
+                fold(out, &format!("Result: {}{}", name, status), &fenced(&content, ""));
+            }
+            ContentBlock::Image { .. } => out.push_str("_[image]_\n\n"),
+            ContentBlock::Other { block_type, raw } => {
+                let raw = serde_json::to_string_pretty(&raw).unwrap_or_default();
+                fold(out, &block_type, &fenced(&raw, "json"));
+            }
+        }
+    }
+}
+
+/// Tool name of every tool call, by `tool_use_id`, for labelling results.
+pub(crate) fn tool_names(messages: &[ClaudeMessage]) -> HashMap<String, String> {
+    messages.iter()
+        .flat_map(|m| m.message.blocks())
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse { id, name, .. } => Some((id, name)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether Claude Code marked the message `isMeta`, e.g. injected command output.
+pub(crate) fn is_meta(message: &ClaudeMessage) -> bool {
+    message.extra.get("isMeta").and_then(Value::as_bool).unwrap_or(false)
+}
+
+/// Whether the message has prose; messages with only tool traffic get no heading.
+pub(crate) fn has_text(message: &ClaudeMessage) -> bool {
+    message.message.blocks().iter()
+        .any(|b| matches!(b, ContentBlock::Text { text } if !text.trim().is_empty()))
+}
+
+/// A collapsed section; the blank lines let GitHub render markdown inside it.
+fn details(out: &mut String, summary: &str, body: &str) {
+    let _ = writeln!(out, "<details>\n<summary>{}</summary>\n\n{}\n\n</details>\n", escape_html(summary), body);
+}
+
+/// Fences `text` with more backticks than any run inside it, so embedded
+/// code blocks cannot close the fence early.
+pub(crate) fn fenced(text: &str, language: &str) -> String {
+    let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!("{}{}\n{}\n{}", fence, language, text.trim_end_matches('\n'), fence)
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+pub(crate) fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Non-empty values in first-seen order.
+fn distinct<'a>(values: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    values.filter(|v| !v.is_empty() && seen.insert(*v)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{line, setup_db};
+    use crate::jsonl_parser::JsonlParser;
+    use crate::memory_store::MemoryStore;
+
+    fn store() -> MemoryStore {
+        let lines = [
+            line("u1", None, "s1", 0, "user", "Why does the vault lock?".into()),
+            line("a1", Some("u1"), "s1", 1, "assistant", serde_json::json!([
+                {"type": "text", "text": "Let me check.\n\n```rust\nlet x = 1;\n```"},
+                {"type": "tool_use", "id": "toolu_1", "name": "Bash", "input": {"command": "lsof vault.db"}},
+            ])),
+            line("r1", Some("a1"), "s1", 2, "user", serde_json::json!([
+                {"type": "tool_result", "tool_use_id": "toolu_1", "content": "```\nduckdb 123\n```", "is_error": true},
+            ])),
+            line("a2", Some("r1"), "s1", 3, "assistant", "Another process holds the lock.".into()),
+            // A retry of the first answer, on its own branch
+            line("a1b", Some("u1"), "s1", 4, "assistant", "Retried answer".into()),
+            r#"{"type":"summary","summary":"Vault lock","leafUuid":"a2"}"#.to_string(),
+        ];
+        let store = MemoryStore::new();
+        let parser = JsonlParser::new();
+        let mut records = lines.iter().map(|l| parser.parse_record(l));
+        store.upsert_messages(&mut records, "/work/app", false).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let store = store();
+        let export = SessionExport::load(&store, "s1", Some("a2")).unwrap();
+        assert_eq!(export.messages.iter().map(|m| m.uuid.as_str()).collect::<Vec<_>>(), vec!["u1", "a1", "r1", "a2"]);
+        assert_eq!(export.skipped_messages, 1);
+
+        let markdown = render_markdown(&export);
+        assert!(markdown.starts_with("# Vault lock\n\n- **Project:** `/work/app`\n- **Session:** `s1`\n"));
+        assert!(markdown.contains("- **Branch:** main\n- **Claude Code:** 1.0.51\n"));
+        assert!(markdown.contains("- **Time:** 2025-07-21 12:00:00 – 2025-07-21 12:03:00 UTC"));
+        assert!(markdown.contains("_1 messages on other branches are not included._"));
+        assert!(markdown.contains("## User\n\n_2025-07-21 12:00:00_\n\nWhy does the vault lock?\n"));
+        assert!(markdown.contains("Let me check.\n\n```rust\nlet x = 1;\n```\n"));
+        assert!(markdown.contains("<details>\n<summary>Tool: Bash</summary>\n\n```json\n{\n  \"command\": \"lsof vault.db\"\n}\n```\n\n</details>"));
+        // The result's own fence is wrapped in a longer one, and the tool-only message has no heading
+        assert!(markdown.contains("<summary>Result: Bash (error)</summary>\n\n````\n```\nduckdb 123\n```\n````"));
+        assert_eq!(markdown.matches("## ").count(), 3);
+        assert!(!markdown.contains("Retried answer"));
+        assert!(markdown.ends_with("Another process holds the lock.\n"));
+
+        // Without a message, the most recent branch is exported
+        let latest = SessionExport::load(&store, "s1", None).unwrap();
+        assert_eq!(latest.messages.iter().map(|m| m.uuid.as_str()).collect::<Vec<_>>(), vec!["u1", "a1b"]);
+    }
+
+    fn keywords(keywords: &[&str]) -> SearchQuery {
+        SearchQuery { keywords: keywords.iter().map(|k| k.to_string()).collect(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_find_sessions() {
+        let store = store();
+        assert_eq!(find_sessions(&store, &keywords(&["s1"])).unwrap(), vec![("s1".to_string(), None)]);
+        assert_eq!(find_sessions(&store, &keywords(&["a2"])).unwrap(), vec![("s1".to_string(), Some("a2".to_string()))]);
+        assert_eq!(
+            find_sessions(&store, &keywords(&["process", "lock"])).unwrap(),
+            vec![("s1".to_string(), Some("a2".to_string()))]
+        );
+        assert!(find_sessions(&store, &keywords(&["nothing"])).is_err());
+    }
+
+    #[test]
+    fn test_export_row_schema() {
+        let row = ExportRow {
+            uuid: "u1".to_string(),
+            parent_uuid: None,
+            session_id: "s1".to_string(),
+            project_path: "/work/app".to_string(),
+            timestamp: DateTime::from_timestamp_millis(1_753_099_200_250).unwrap(),
+            message_type: "user".to_string(),
+            role: Some("user".to_string()),
+            content: Some("Why does the vault lock?".to_string()),
+            git_branch: Some("fix-lock".to_string()),
+            version: "1.0.51".to_string(),
+            is_favorite: true,
+            tags: vec!["postmortem".to_string()],
+            note: None,
+            session_note: Some("lock issue".to_string()),
+        };
+        // Downstream scripts depend on these names, types and this order
+        assert_eq!(
+            serde_json::to_string(&row).unwrap(),
+            r#"{"uuid":"u1","parent_uuid":null,"session_id":"s1","project_path":"/work/app","timestamp":"2025-07-21T12:00:00.250Z","type":"user","role":"user","content":"Why does the vault lock?","git_branch":"fix-lock","version":"1.0.51","is_favorite":true,"tags":["postmortem"],"note":null,"session_note":"lock issue"}"#
+        );
+
+        let mut ndjson = Vec::new();
+        write_json_rows(&[row.clone(), row.clone()], ExportFormat::Ndjson, &mut ndjson).unwrap();
+        assert_eq!(String::from_utf8(ndjson).unwrap().lines().count(), 2);
+        let mut json = Vec::new();
+        write_json_rows(std::slice::from_ref(&row), ExportFormat::Json, &mut json).unwrap();
+        assert_eq!(serde_json::from_slice::<Vec<ExportRow>>(&json).unwrap(), vec![row]);
+    }
+
+    #[test]
+    fn test_copy_csv() {
+        use crate::duckdb_store::DuckDBStore;
+        use crate::store::Target;
+
+        let conn = setup_db();
+        let duckdb = DuckDBStore::new(&conn);
+        let parser = JsonlParser::new();
+        let lines = [
+            line("u1", None, "s1", 0, "user", "Why does the vault lock?".into()),
+            line("a1", Some("u1"), "s1", 1, "assistant", "Another process, \"probably\".".into()),
+        ];
+        let mut records = lines.iter().map(|l| parser.parse_record(l));
+        duckdb.upsert_messages(&mut records, "/work/app", false).unwrap();
+        duckdb.add_tag(&Target::Session("s1".to_string()), "postmortem").unwrap();
+        duckdb.add_tag(&Target::Message("a1".to_string()), "answer").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("it's.csv");
+        let query = SearchQuery { keywords: vec!["process".to_string()], limit: None, ..Default::default() };
+        duckdb.copy_rows(&query, ExportFormat::Csv, &path).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "uuid,parent_uuid,session_id,project_path,timestamp,type,role,content,git_branch,version,is_favorite,tags,note,session_note");
+        assert_eq!(lines[1], r#"a1,u1,s1,/work/app,2025-07-21 12:01:00,assistant,assistant,"Another process, ""probably"".",main,1.0.51,false,"answer,postmortem",,"#);
+        assert_eq!(lines.len(), 2);
+
+        assert!(duckdb.copy_rows(&query, ExportFormat::Json, &path).is_err());
+    }
+
+    #[test]
+    fn test_fenced() {
+        assert_eq!(fenced("plain\n", "json"), "```json\nplain\n```");
+        assert_eq!(fenced("a ````` b", ""), "``````\na ````` b\n``````");
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use crate::real_db_connection::ExtendedDatabaseConnection;
+use crate::store::Target;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Favorite {
+    pub target: Target,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Favoriting again keeps the original timestamp; a new note replaces the old one.
+const UPSERT_FAVORITE: &str = r#"
+INSERT INTO favorites (target_kind, target_id, note) VALUES (?, ?, ?)
+ON CONFLICT (target_kind, target_id) DO UPDATE SET note = coalesce(excluded.note, favorites.note)"#;
+
+const DELETE_FAVORITE: &str =
+    "DELETE FROM favorites WHERE target_kind = ? AND target_id = ? RETURNING target_id";
+
+const LIST_FAVORITES: &str = r#"
+SELECT target_kind, target_id, note, epoch_us(created_at)
+FROM favorites
+ORDER BY created_at DESC, target_kind, target_id"#;
+
+/// Favorite messages and sessions, each with an optional note.
+pub struct FavoriteStore<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
+}
+
+impl<'a, C: ExtendedDatabaseConnection> FavoriteStore<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
+        Self { connection }
+    }
+
+    fn connected(&self) -> Result<&C> {
+        if !self.connection.is_connected() {
+            return Err(anyhow!("Database not connected"));
+        }
+        Ok(self.connection)
+    }
+
+    pub fn add(&self, target: &Target, note: Option<&str>) -> Result<()> {
+        let [kind, id] = target.key_params();
+        self.connected()?.execute_with_params(UPSERT_FAVORITE, &[kind, id, note.map(str::to_string).into()])
+    }
+
+    /// Returns whether there was a favorite to remove.
+    pub fn remove(&self, target: &Target) -> Result<bool> {
+        let removed: Vec<String> = self.connected()?.query_all(
+            DELETE_FAVORITE,
+            &target.key_params(),
+            |row| Ok(row.get(0)?),
+        )?;
+        Ok(!removed.is_empty())
+    }
+
+    /// Every favorite, newest first.
+    pub fn list(&self) -> Result<Vec<Favorite>> {
+        self.connected()?.query_all(LIST_FAVORITES, &[], |row| {
+            let kind: String = row.get(0)?;
+            let created_us: i64 = row.get(3)?;
+            Ok(Favorite {
+                target: Target::from_parts(&kind, row.get(1)?)?,
+                note: row.get(2)?,
+                created_at: DateTime::from_timestamp_micros(created_us)
+                    .ok_or_else(|| anyhow!("Invalid timestamp: {}", created_us))?,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::setup_db;
+    use crate::real_db_connection::RealDuckDBConnection;
+    use crate::db_connection::{ConnectionConfig, DatabaseConnection};
+
+    #[test]
+    fn test_add_list_and_remove() {
+        let conn = setup_db();
+        let favorites = FavoriteStore::new(&conn);
+        let message = Target::Message("uuid-1".to_string());
+        let session = Target::Session("session-1".to_string());
+
+        favorites.add(&message, Some("the fix")).unwrap();
+        favorites.add(&session, None).unwrap();
+        // Favoriting again without a note keeps the note
+        favorites.add(&message, None).unwrap();
+
+        let listed = favorites.list().unwrap();
+        assert_eq!(listed.len(), 2);
+        let note = listed.iter().find(|f| f.target == message).unwrap().note.clone();
+        assert_eq!(note.as_deref(), Some("the fix"));
+
+        assert!(favorites.remove(&session).unwrap());
+        assert!(!favorites.remove(&session).unwrap());
+        assert_eq!(favorites.list().unwrap().iter().map(|f| f.target.clone()).collect::<Vec<_>>(), vec![message]);
+    }
+
+    #[test]
+    fn test_favorites_survive_reimport() {
+        let conn = setup_db();
+        let favorites = FavoriteStore::new(&conn);
+        favorites.add(&Target::Message("uuid-1".to_string()), None).unwrap();
+
+        // Wiping and rebuilding the conversations does not touch favorites
+        conn.execute("DELETE FROM conversations").unwrap();
+        assert_eq!(favorites.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_when_not_connected() {
+        let conn = RealDuckDBConnection::new(ConnectionConfig::default());
+        let result = FavoriteStore::new(&conn).add(&Target::Session("s".to_string()), None);
+        assert!(result.unwrap_err().to_string().contains("Database not connected"));
+    }
+}
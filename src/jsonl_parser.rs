@@ -1,26 +1,46 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::io::BufRead;
 
+/// Lines longer than this are skipped instead of buffered. Tool results can
+/// embed whole files, so the limit is generous.
+pub const DEFAULT_MAX_LINE_BYTES: usize = 64 * 1024 * 1024;
+
+/// A `user`, `assistant` or `system` record. Only `type`, `uuid` and
+/// `timestamp` are required; fields this struct does not model are kept in
+/// `extra` (e.g. `requestId`, `toolUseResult`, `isMeta`).
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ClaudeMessage {
+    #[serde(default)]
     pub parent_uuid: Option<String>,
+    #[serde(default)]
     pub is_sidechain: bool,
+    #[serde(default)]
     pub user_type: String,
+    #[serde(default)]
     pub cwd: String,
+    #[serde(default)]
     pub session_id: String,
+    #[serde(default)]
     pub version: String,
+    #[serde(default)]
     pub git_branch: Option<String>,
     #[serde(rename = "type")]
     pub message_type: String,
+    #[serde(default)]
     pub message: MessageContent,
     pub uuid: String,
     pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// The API message of a record. Fields this struct does not model are kept
+/// in `extra` (e.g. `usage`, `stop_reason`, `stop_sequence`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct MessageContent {
     pub role: Option<String>,
     pub content: Option<Value>,
@@ -28,13 +48,223 @@ pub struct MessageContent {
     #[serde(rename = "type")]
     pub content_type: Option<String>,
     pub model: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `{"type":"summary","summary":...,"leafUuid":...}`: a title Claude Code
+/// generates for the conversation ending at `leaf_uuid`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryRecord {
+    pub summary: String,
+    #[serde(default)]
+    pub leaf_uuid: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// One line of a transcript, by its `type` field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonlRecord {
+    /// A `user` or `assistant` turn
+    Message(ClaudeMessage),
+    /// A `system` notice; its top-level `content` is moved into `message`
+    System(ClaudeMessage),
+    Summary(SummaryRecord),
+    /// Record types this parser does not know about, or system records
+    /// without a uuid or timestamp, kept as raw JSON
+    Unknown { record_type: Option<String>, raw: Value },
+}
+
+impl JsonlRecord {
+    /// The record's own `uuid`, if it has one.
+    pub fn uuid(&self) -> Option<&str> {
+        match self {
+            JsonlRecord::Message(message) | JsonlRecord::System(message) => Some(&message.uuid),
+            JsonlRecord::Summary(_) => None,
+            JsonlRecord::Unknown { raw, .. } => raw.get("uuid").and_then(Value::as_str),
+        }
+    }
+}
+
+impl MessageContent {
+    /// Rebuilds a message from its role, content and `metadata_json`.
+    pub fn from_parts(role: Option<String>, content: Option<Value>, metadata: Option<&str>) -> Result<Self> {
+        let mut fields: Map<String, Value> = metadata.map(serde_json::from_str).transpose()?.unwrap_or_default();
+        fields.insert("role".to_string(), role.into());
+        fields.insert("content".to_string(), content.unwrap_or(Value::Null));
+        Ok(serde_json::from_value(Value::Object(fields))?)
+    }
+
+    /// Every field except `role` and `content` as a JSON object, or `None` when
+    /// the message has no other fields.
+    pub fn metadata_json(&self) -> Option<String> {
+        let mut fields = self.extra.clone();
+        for (key, value) in [("id", &self.id), ("type", &self.content_type), ("model", &self.model)] {
+            if let Some(value) = value {
+                fields.insert(key.to_string(), Value::String(value.clone()));
+            }
+        }
+        (!fields.is_empty()).then(|| Value::Object(fields).to_string())
+    }
+
+    /// Normalizes `content` into typed blocks. A plain string becomes a single
+    /// text block; a missing or null content has no blocks.
+    pub fn blocks(&self) -> Vec<ContentBlock> {
+        match &self.content {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(items)) => items.iter().map(ContentBlock::from_value).collect(),
+            Some(value) => vec![ContentBlock::from_value(value)],
+        }
+    }
+
+    /// The prose of the message: text blocks only, without thinking or tool traffic.
+    pub fn plain_text(&self) -> Option<String> {
+        let text = self.blocks()
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } if !text.trim().is_empty() => Some(text.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        if text.is_empty() { None } else { Some(text) }
+    }
+}
+
+/// A typed view of one entry in a message's `content`.
+///
+/// User prompts usually carry a plain string, while assistant messages carry an
+/// array of blocks; both normalize to a list of `ContentBlock`s.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentBlock {
+    Text { text: String },
+    Thinking { thinking: String },
+    ToolUse { id: String, name: String, input: Value },
+    ToolResult { tool_use_id: String, content: String, is_error: bool },
+    Image { media_type: Option<String> },
+    /// Block types this parser does not know about yet, kept as raw JSON
+    Other { block_type: String, raw: Value },
+}
+
+impl ContentBlock {
+    pub fn from_value(value: &Value) -> Self {
+        let str_field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_string);
+
+        match value {
+            Value::String(text) => return ContentBlock::Text { text: text.clone() },
+            Value::Object(_) => {}
+            other => {
+                return ContentBlock::Other { block_type: "unknown".to_string(), raw: other.clone() };
+            }
+        }
+
+        match value.get("type").and_then(Value::as_str).unwrap_or("unknown") {
+            "text" => ContentBlock::Text { text: str_field("text").unwrap_or_default() },
+            "thinking" => ContentBlock::Thinking { thinking: str_field("thinking").unwrap_or_default() },
+            "tool_use" => ContentBlock::ToolUse {
+                id: str_field("id").unwrap_or_default(),
+                name: str_field("name").unwrap_or_default(),
+                input: value.get("input").cloned().unwrap_or(Value::Null),
+            },
+            "tool_result" => ContentBlock::ToolResult {
+                tool_use_id: str_field("tool_use_id").unwrap_or_default(),
+                content: value.get("content").map(Self::flatten_text).unwrap_or_default(),
+                is_error: value.get("is_error").and_then(Value::as_bool).unwrap_or(false),
+            },
+            "image" => ContentBlock::Image {
+                media_type: value.get("source")
+                    .and_then(|s| s.get("media_type"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            },
+            other => ContentBlock::Other { block_type: other.to_string(), raw: value.clone() },
+        }
+    }
+
+    /// Tool results hold either a string or a list of text/image blocks.
+    fn flatten_text(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            Value::Array(items) => items.iter()
+                .filter_map(|item| match item {
+                    Value::String(s) => Some(s.clone()),
+                    _ => item.get("text").and_then(Value::as_str).map(str::to_string),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Value::Null => String::new(),
+            other => other.to_string(),
+        }
+    }
+
+    pub fn block_type(&self) -> &str {
+        match self {
+            ContentBlock::Text { .. } => "text",
+            ContentBlock::Thinking { .. } => "thinking",
+            ContentBlock::ToolUse { .. } => "tool_use",
+            ContentBlock::ToolResult { .. } => "tool_result",
+            ContentBlock::Image { .. } => "image",
+            ContentBlock::Other { block_type, .. } => block_type,
+        }
+    }
+
+    /// Searchable text of the block; tool inputs are rendered as compact JSON.
+    pub fn text(&self) -> Option<String> {
+        match self {
+            ContentBlock::Text { text } => Some(text.clone()),
+            ContentBlock::Thinking { thinking } => Some(thinking.clone()),
+            ContentBlock::ToolUse { input, .. } => Some(input.to_string()),
+            ContentBlock::ToolResult { content, .. } => Some(content.clone()),
+            ContentBlock::Image { .. } | ContentBlock::Other { .. } => None,
+        }
+    }
+
+    pub fn tool_name(&self) -> Option<&str> {
+        match self {
+            ContentBlock::ToolUse { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn tool_use_id(&self) -> Option<&str> {
+        match self {
+            ContentBlock::ToolUse { id, .. } => Some(id),
+            ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id),
+            _ => None,
+        }
+    }
 }
 
-pub struct JsonlParser;
+pub struct JsonlParser {
+    max_line_bytes: usize,
+}
 
 impl JsonlParser {
     pub fn new() -> Self {
-        Self
+        Self { max_line_bytes: DEFAULT_MAX_LINE_BYTES }
+    }
+
+    /// Sets the longest line `stream` will buffer.
+    #[allow(dead_code)]
+    pub fn with_max_line_bytes(mut self, max_line_bytes: usize) -> Self {
+        self.max_line_bytes = max_line_bytes;
+        self
+    }
+
+    /// Parses records one line at a time from `reader`, holding at most one
+    /// line in memory.
+    pub fn stream<R: BufRead>(&self, reader: R) -> RecordStream<'_, R> {
+        RecordStream {
+            parser: self,
+            reader,
+            position: 0,
+            complete_offset: 0,
+            line_number: 0,
+            done: false,
+        }
     }
 
     pub fn parse_single_message(&self, json_str: &str) -> Result<ClaudeMessage> {
@@ -42,6 +272,40 @@ impl JsonlParser {
             .context("Failed to parse JSON message")
     }
 
+    /// Parses any kind of transcript line. Only malformed JSON, or a user or
+    /// assistant turn missing its `uuid`/`timestamp`, is an error.
+    pub fn parse_record(&self, json_str: &str) -> Result<JsonlRecord> {
+        let value: Value = serde_json::from_str(json_str)
+            .context("Failed to parse JSON message")?;
+        let record_type = value.get("type").and_then(Value::as_str).map(str::to_string);
+
+        match record_type.as_deref() {
+            Some("user") | Some("assistant") => serde_json::from_value(value)
+                .map(JsonlRecord::Message)
+                .context("Failed to parse JSON message"),
+            Some("summary") => match serde_json::from_value::<SummaryRecord>(value.clone()) {
+                Ok(mut summary) => {
+                    // The record kind is already encoded in the variant
+                    summary.extra.remove("type");
+                    Ok(JsonlRecord::Summary(summary))
+                }
+                Err(_) => Ok(JsonlRecord::Unknown { record_type, raw: value }),
+            },
+            Some("system") => match serde_json::from_value::<ClaudeMessage>(value.clone()) {
+                Ok(mut message) => {
+                    if message.message.content.is_none() {
+                        message.message.content = message.extra.remove("content");
+                        message.message.role.get_or_insert_with(|| "system".to_string());
+                    }
+                    Ok(JsonlRecord::System(message))
+                }
+                Err(_) => Ok(JsonlRecord::Unknown { record_type, raw: value }),
+            },
+            _ => Ok(JsonlRecord::Unknown { record_type, raw: value }),
+        }
+    }
+
+    #[allow(dead_code)]
     pub fn parse_multiple_messages(&self, jsonl_content: &str) -> Result<Vec<ClaudeMessage>> {
         let mut messages = Vec::new();
         
@@ -56,6 +320,7 @@ impl JsonlParser {
         Ok(messages)
     }
 
+    #[allow(dead_code)]
     pub fn parse_multiple_messages_skip_errors(&self, jsonl_content: &str) -> Vec<(usize, Result<ClaudeMessage>)> {
         let mut results = Vec::new();
         
@@ -69,6 +334,145 @@ impl JsonlParser {
         
         results
     }
+
+    /// Like `parse_multiple_messages_skip_errors`, for every kind of record.
+    #[allow(dead_code)]
+    pub fn parse_records_skip_errors(&self, jsonl_content: &str) -> Vec<(usize, Result<JsonlRecord>)> {
+        jsonl_content.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(line_num, line)| (line_num + 1, self.parse_record(line.trim())))
+            .collect()
+    }
+}
+
+/// One non-blank line read by `RecordStream`.
+#[derive(Debug)]
+pub struct StreamedRecord {
+    /// 1-based line number
+    pub line_number: usize,
+    /// Byte offset of the start of the line, relative to where reading began
+    pub offset: u64,
+    /// Byte offset just past the line and its newline
+    #[allow(dead_code)]
+    pub end_offset: u64,
+    /// Whether the line ended with a newline. Only the last line of the
+    /// input can be unterminated.
+    pub terminated: bool,
+    pub record: Result<JsonlRecord>,
+}
+
+/// Iterator over the records of a JSONL reader; see `JsonlParser::stream`.
+///
+/// Blank lines are skipped. A line over the parser's length limit is yielded
+/// as an error without being buffered. A final line without a newline that
+/// does not parse is taken to be still being written and is not yielded at
+/// all; `complete_offset` stays before it. `Err` items are I/O errors, after
+/// which the stream ends.
+pub struct RecordStream<'p, R> {
+    parser: &'p JsonlParser,
+    reader: R,
+    position: u64,
+    complete_offset: u64,
+    line_number: usize,
+    done: bool,
+}
+
+impl<R: BufRead> RecordStream<'_, R> {
+    /// Bytes read so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Offset just past the last newline read, i.e. where reading can safely
+    /// resume later.
+    pub fn complete_offset(&self) -> u64 {
+        self.complete_offset
+    }
+
+    /// Reads the next line into `line`, up to the length limit. Returns the
+    /// line's full length and whether it ended in a newline, or `None` at EOF.
+    fn read_line(&mut self, line: &mut Vec<u8>) -> std::io::Result<Option<(usize, bool)>> {
+        let mut length = 0;
+        loop {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                return Ok((length > 0).then_some((length, false)));
+            }
+
+            let (chunk, terminated) = match available.iter().position(|&b| b == b'\n') {
+                Some(i) => (&available[..=i], true),
+                None => (available, false),
+            };
+            if length + chunk.len() <= self.parser.max_line_bytes + 1 {
+                line.extend_from_slice(chunk);
+            }
+            let consumed = chunk.len();
+            length += consumed;
+            self.reader.consume(consumed);
+
+            if terminated {
+                return Ok(Some((length, true)));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for RecordStream<'_, R> {
+    type Item = Result<StreamedRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = Vec::new();
+        while !self.done {
+            line.clear();
+            let (length, terminated) = match self.read_line(&mut line) {
+                Ok(Some(read)) => read,
+                Ok(None) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            };
+
+            self.line_number += 1;
+            let offset = self.position;
+            self.position += length as u64;
+            if terminated {
+                self.complete_offset = self.position;
+            }
+
+            // Lines over the limit are only partly buffered; the newline is
+            // counted in `length`
+            let content_length = if terminated { length - 1 } else { length };
+            let record = if content_length > self.parser.max_line_bytes {
+                Err(anyhow!("Line is {} bytes, over the {} byte limit", content_length, self.parser.max_line_bytes))
+            } else {
+                match std::str::from_utf8(&line) {
+                    Ok(text) if text.trim().is_empty() => continue,
+                    Ok(text) => self.parser.parse_record(text.trim()),
+                    Err(e) => Err(anyhow!("Line is not valid UTF-8: {}", e)),
+                }
+            };
+
+            if !terminated && record.is_err() {
+                // A partial write; it will be complete on the next read
+                self.done = true;
+                return None;
+            }
+
+            return Some(Ok(StreamedRecord {
+                line_number: self.line_number,
+                offset,
+                end_offset: self.position,
+                terminated,
+                record,
+            }));
+        }
+        None
+    }
 }
 
 #[cfg(test)]
@@ -86,7 +490,7 @@ mod tests {
         let message = result.unwrap();
         
         assert_eq!(message.parent_uuid, None);
-        assert_eq!(message.is_sidechain, false);
+        assert!(!message.is_sidechain);
         assert_eq!(message.user_type, "external");
         assert_eq!(message.cwd, "/Users/honda/dev/cc-vault");
         assert_eq!(message.session_id, "28035087-fe7b-4b2e-843a-ce802161e23c");
@@ -192,4 +596,206 @@ mod tests {
         assert_eq!(messages[0].uuid, "uuid1");
         assert_eq!(messages[1].uuid, "uuid2");
     }
-}
\ No newline at end of file
+
+    fn assistant_content(value: Value) -> MessageContent {
+        MessageContent {
+            role: Some("assistant".to_string()),
+            content: Some(value),
+            id: None,
+            content_type: None,
+            model: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_string_content_is_single_text_block() {
+        let content = assistant_content(serde_json::json!("Hello"));
+
+        assert_eq!(content.blocks(), vec![ContentBlock::Text { text: "Hello".to_string() }]);
+        assert_eq!(content.plain_text(), Some("Hello".to_string()));
+    }
+
+    #[test]
+    fn test_parse_typed_content_blocks() {
+        let content = assistant_content(serde_json::json!([
+            {"type": "thinking", "thinking": "Let me check the tests"},
+            {"type": "text", "text": "Running the tests now."},
+            {"type": "tool_use", "id": "toolu_01", "name": "Bash", "input": {"command": "cargo test"}},
+            {"type": "tool_result", "tool_use_id": "toolu_01", "content": [{"type": "text", "text": "ok"}], "is_error": true},
+            {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "..."}},
+            {"type": "server_tool_use", "id": "srv_01"}
+        ]));
+
+        let blocks = content.blocks();
+        assert_eq!(blocks.len(), 6);
+        assert_eq!(blocks[0], ContentBlock::Thinking { thinking: "Let me check the tests".to_string() });
+        assert_eq!(blocks[2].block_type(), "tool_use");
+        assert_eq!(blocks[2].tool_name(), Some("Bash"));
+        assert_eq!(blocks[2].tool_use_id(), Some("toolu_01"));
+        assert_eq!(blocks[2].text(), Some(r#"{"command":"cargo test"}"#.to_string()));
+        assert_eq!(blocks[3], ContentBlock::ToolResult {
+            tool_use_id: "toolu_01".to_string(),
+            content: "ok".to_string(),
+            is_error: true,
+        });
+        assert_eq!(blocks[4], ContentBlock::Image { media_type: Some("image/png".to_string()) });
+        assert_eq!(blocks[5].block_type(), "server_tool_use");
+        assert_eq!(blocks[5].text(), None);
+    }
+
+    #[test]
+    fn test_plain_text_skips_tool_noise() {
+        let content = assistant_content(serde_json::json!([
+            {"type": "text", "text": "First paragraph"},
+            {"type": "tool_use", "id": "toolu_01", "name": "Read", "input": {"file_path": "src/main.rs"}},
+            {"type": "text", "text": "Second paragraph"}
+        ]));
+        assert_eq!(content.plain_text(), Some("First paragraph\n\nSecond paragraph".to_string()));
+
+        let tool_only = assistant_content(serde_json::json!([
+            {"type": "tool_result", "tool_use_id": "toolu_01", "content": "fn main() {}"}
+        ]));
+        assert_eq!(tool_only.plain_text(), None);
+
+        let mut empty = tool_only.clone();
+        empty.content = None;
+        assert!(empty.blocks().is_empty());
+    }
+
+    #[test]
+    fn test_parse_record_kinds() {
+        let parser = JsonlParser::new();
+
+        let summary = parser.parse_record(r#"{"type":"summary","summary":"Refactor the importer","leafUuid":"uuid3"}"#).unwrap();
+        assert_eq!(summary, JsonlRecord::Summary(SummaryRecord {
+            summary: "Refactor the importer".to_string(),
+            leaf_uuid: Some("uuid3".to_string()),
+            extra: Map::new(),
+        }));
+
+        let system = parser.parse_record(r#"{"type":"system","subtype":"compact_boundary","content":"Conversation compacted","level":"info","uuid":"sys1","timestamp":"2025-07-21T12:48:30.283Z"}"#).unwrap();
+        let JsonlRecord::System(message) = system else { panic!("expected a system record") };
+        assert_eq!(message.message.role.as_deref(), Some("system"));
+        assert_eq!(message.message.plain_text().as_deref(), Some("Conversation compacted"));
+        assert_eq!(message.extra.get("subtype"), Some(&Value::from("compact_boundary")));
+        assert!(!message.extra.contains_key("content"));
+
+        let snapshot = parser.parse_record(r#"{"type":"file-history-snapshot","messageId":"m1","snapshot":{}}"#).unwrap();
+        assert!(matches!(snapshot, JsonlRecord::Unknown { record_type: Some(ref t), .. } if t == "file-history-snapshot"));
+
+        // A system notice without a uuid has nothing to key it on
+        let untracked = parser.parse_record(r#"{"type":"system","content":"hook output"}"#).unwrap();
+        assert!(matches!(untracked, JsonlRecord::Unknown { .. }));
+
+        assert!(parser.parse_record(r#"{"type":"user","message":{"role":"user","content":"no uuid"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_missing_fields_default_and_unknown_fields_are_kept() {
+        let json_str = r#"{"type":"assistant","message":{"role":"assistant","content":"ok"},"uuid":"uuid1","timestamp":"2025-07-21T12:48:30.283Z","requestId":"req_01","isMeta":true}"#;
+
+        let parser = JsonlParser::new();
+        let message = parser.parse_single_message(json_str).unwrap();
+
+        assert_eq!(message.session_id, "");
+        assert_eq!(message.cwd, "");
+        assert!(!message.is_sidechain);
+        assert_eq!(message.extra.len(), 2);
+        assert_eq!(message.extra.get("requestId"), Some(&Value::from("req_01")));
+        assert_eq!(message.extra.get("isMeta"), Some(&Value::Bool(true)));
+
+        let records = parser.parse_records_skip_errors(&format!("{}\n\n{{bad\n", json_str));
+        assert_eq!(records.len(), 2);
+        assert!(matches!(records[0], (1, Ok(JsonlRecord::Message(_)))));
+        assert_eq!(records[1].0, 3);
+        assert!(records[1].1.is_err());
+    }
+
+    #[test]
+    fn test_message_fields_are_kept() {
+        let json_str = r#"{"type":"assistant","message":{"id":"msg_01","type":"message","role":"assistant","model":"model-1","content":"ok","stop_reason":"end_turn","stop_sequence":null,"usage":{"input_tokens":3,"output_tokens":5}},"uuid":"uuid1","timestamp":"2025-07-21T12:48:30.283Z"}"#;
+        let message = JsonlParser::new().parse_single_message(json_str).unwrap().message;
+
+        assert_eq!(message.model.as_deref(), Some("model-1"));
+        assert_eq!(message.extra.get("stop_reason"), Some(&Value::from("end_turn")));
+        assert_eq!(message.extra.get("stop_sequence"), Some(&Value::Null));
+        assert_eq!(message.extra["usage"]["output_tokens"], 5);
+
+        let metadata = message.metadata_json().unwrap();
+        assert!(!metadata.contains("\"content\""));
+        let rebuilt = MessageContent::from_parts(message.role.clone(), message.content.clone(), Some(&metadata)).unwrap();
+        assert_eq!(rebuilt, message);
+        assert_eq!(MessageContent::from_parts(None, None, None).unwrap(), MessageContent::default());
+        assert_eq!(MessageContent::default().metadata_json(), None);
+    }
+
+    fn user_line(uuid: &str, content: &str) -> String {
+        format!(
+            r#"{{"type":"user","sessionId":"session1","message":{{"role":"user","content":"{}"}},"uuid":"{}","timestamp":"2025-07-21T12:48:30.283Z"}}"#,
+            content, uuid
+        )
+    }
+
+    #[test]
+    fn test_stream_reports_offsets_and_skips_blank_lines() {
+        let first = user_line("uuid1", "First");
+        let second = user_line("uuid2", "Second");
+        let input = format!("{}\n\n{{bad\n{}\n", first, second);
+
+        let parser = JsonlParser::new();
+        let mut stream = parser.stream(input.as_bytes());
+        let lines: Vec<StreamedRecord> = stream.by_ref().map(|line| line.unwrap()).collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!((lines[0].line_number, lines[0].offset), (1, 0));
+        assert_eq!(lines[0].end_offset, first.len() as u64 + 1);
+        assert_eq!(lines[0].record.as_ref().unwrap().uuid(), Some("uuid1"));
+        assert_eq!((lines[1].line_number, lines[1].offset), (3, first.len() as u64 + 2));
+        assert!(lines[1].record.is_err());
+        assert_eq!(lines[2].line_number, 4);
+        assert!(lines[2].terminated);
+        assert_eq!(&input[lines[2].offset as usize..lines[2].end_offset as usize - 1], second);
+        assert_eq!(stream.position(), input.len() as u64);
+        assert_eq!(stream.complete_offset(), input.len() as u64);
+    }
+
+    #[test]
+    fn test_stream_tolerates_truncated_final_line() {
+        let first = user_line("uuid1", "First");
+        let partial = &user_line("uuid2", "Second")[..40];
+        let input = format!("{}\n{}", first, partial);
+
+        let parser = JsonlParser::new();
+        let mut stream = parser.stream(input.as_bytes());
+        let lines: Vec<StreamedRecord> = stream.by_ref().map(|line| line.unwrap()).collect();
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(stream.complete_offset(), first.len() as u64 + 1);
+        assert_eq!(stream.position(), input.len() as u64);
+
+        // A complete record that merely lacks its newline is still yielded
+        let unterminated = user_line("uuid3", "Third");
+        let lines: Vec<StreamedRecord> = parser.stream(unterminated.as_bytes()).map(|line| line.unwrap()).collect();
+        assert_eq!(lines.len(), 1);
+        assert!(!lines[0].terminated);
+        assert!(lines[0].record.is_ok());
+    }
+
+    #[test]
+    fn test_stream_skips_lines_over_the_limit() {
+        let long = user_line("uuid1", &"x".repeat(500));
+        let short = user_line("uuid2", "ok");
+        let input = format!("{}\n{}\n", long, short);
+
+        // A tiny read buffer makes the long line span many reads
+        let parser = JsonlParser::new().with_max_line_bytes(200);
+        let reader = std::io::BufReader::with_capacity(16, input.as_bytes());
+        let lines: Vec<StreamedRecord> = parser.stream(reader).map(|line| line.unwrap()).collect();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].record.as_ref().unwrap_err().to_string().contains("over the 200 byte limit"));
+        assert_eq!(lines[1].offset, long.len() as u64 + 1);
+        assert_eq!(lines[1].record.as_ref().unwrap().uuid(), Some("uuid2"));
+    }
+}
@@ -1,10 +1,30 @@
+mod cjk;
 mod claude_reader;
 mod jsonl_parser;
 mod db_connection;
 mod real_db_connection;
 mod db_schema;
 mod data_importer;
+mod date_filter;
 mod search;
+mod snippet;
+mod session;
+mod sync;
+mod tool_calls;
+mod favorites;
+mod tags;
+mod notes;
+mod raw_archive;
+mod export;
+mod obsidian;
+mod site;
+mod watch;
+mod store;
+mod duckdb_store;
+#[cfg(test)]
+mod memory_store;
+#[cfg(test)]
+mod test_support;
 mod cli;
 
 #[cfg(feature = "tui")]
@@ -12,16 +32,17 @@ mod tui;
 
 use anyhow::Result;
 use cli::Cli;
-use std::path::PathBuf;
-use dirs::home_dir;
-
-#[cfg(test)]
-use db_connection::MockDatabaseConnection;
 
 #[cfg(not(test))]
 use crate::real_db_connection::RealDuckDBConnection;
 #[cfg(not(test))]
 use crate::db_connection::DatabaseConnection;
+#[cfg(not(test))]
+use crate::duckdb_store::DuckDBStore;
+#[cfg(not(test))]
+use crate::db_schema::{latest_version, Migrator, SchemaManager};
+#[cfg(not(test))]
+use cli::Commands;
 
 fn main() -> Result<()> {
     // Parse command line arguments
@@ -29,10 +50,8 @@ fn main() -> Result<()> {
     
     #[cfg(test)]
     {
-        let mut mock_conn = MockDatabaseConnection::new();
-        mock_conn.expect_is_connected()
-            .returning(|| true);
-        cli.execute(&mock_conn)?;
+        let store = memory_store::MemoryStore::new();
+        cli.execute(&store)?;
     }
     
     #[cfg(not(test))]
@@ -44,14 +63,23 @@ fn main() -> Result<()> {
         // Connect to database
         conn.connect()?;
         
-        // Initialize schema if needed
-        let schema_manager = crate::db_schema::SchemaManager::new(&conn);
-        schema_manager.create_schema()?;
-        // Create FTS indexes for search functionality
-        schema_manager.create_fts_indexes()?;
-        
-        // Execute command
-        cli.execute(&conn)?;
+        let migrator = Migrator::new(&conn);
+        if let Commands::Migrate { .. } = cli.command {
+            cli.execute_migrate(&migrator)?;
+        } else {
+            // Bring older vaults up to date before touching them
+            let from = migrator.current_version()?;
+            if !migrator.ensure_latest()?.is_empty() && from > 0 {
+                eprintln!("Upgraded vault schema from version {} to {}", from, latest_version());
+            }
+            // Load the FTS extension for BM25 search; without it search falls back
+            // to substring matching, so a failure here is not fatal
+            let _ = SchemaManager::new(&conn).load_fts_extension();
+            
+            // Execute command
+            let store = DuckDBStore::new(&conn);
+            cli.execute(&store)?;
+        }
         
         // Disconnect
         conn.disconnect()?;
@@ -61,8 +89,8 @@ fn main() -> Result<()> {
 }
 
 #[cfg(not(test))]
-fn get_database_path() -> Result<PathBuf> {
-    let home = home_dir()
+fn get_database_path() -> Result<std::path::PathBuf> {
+    let home = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Failed to get home directory"))?;
     
     let cc_vault_dir = home.join(".cc-vault");
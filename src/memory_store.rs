@@ -0,0 +1,592 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use crate::data_importer::ImportStats;
+use crate::export::{ExportFormat, ExportRow};
+use crate::favorites::Favorite;
+use crate::jsonl_parser::{ClaudeMessage, ContentBlock, JsonlRecord};
+use crate::notes::Note;
+use crate::raw_archive::{line_session_id, RawSource};
+use crate::search::{attach_snippets, keyword_terms, validate_regex_patterns, SearchMode, SearchQuery, SearchResult};
+use crate::session::{SessionMessage, SessionThread};
+use crate::sync::ImportState;
+use crate::tags::{TagCount, TagMode};
+use crate::tool_calls::{ToolCall, ToolCallQuery};
+use crate::store::{Target, VaultStats, VaultStore};
+
+struct StoredMessage {
+    id: i64,
+    project_path: String,
+    message: ClaudeMessage,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    /// In import order, so `id` is the position plus one
+    messages: Vec<StoredMessage>,
+    by_uuid: HashMap<String, usize>,
+    /// `(leaf_uuid, summary)` pairs
+    summaries: BTreeSet<(String, String)>,
+    import_states: HashMap<String, ImportState>,
+    /// Note and creation time of each favorite
+    favorites: BTreeMap<Target, (Option<String>, DateTime<Utc>)>,
+    /// `(tag, target)` pairs
+    taggings: BTreeSet<(String, Target)>,
+    notes: BTreeMap<Target, Note>,
+    /// Archived files by source path, with the order they were last archived in
+    raw_files: HashMap<String, (usize, RawSource, Vec<u8>)>,
+    raw_archivals: usize,
+}
+
+impl MemoryState {
+    fn is_favorite(&self, message: &ClaudeMessage) -> bool {
+        self.favorites.contains_key(&Target::Message(message.uuid.clone()))
+            || self.favorites.contains_key(&Target::Session(message.session_id.clone()))
+    }
+
+    /// Tags on the message and on its session, in name order.
+    fn tags_of_message(&self, message: &ClaudeMessage) -> Vec<String> {
+        let targets = [Target::Message(message.uuid.clone()), Target::Session(message.session_id.clone())];
+        let tags: BTreeSet<&String> = self.taggings.iter()
+            .filter(|(_, target)| targets.contains(target))
+            .map(|(tag, _)| tag)
+            .collect();
+        tags.into_iter().cloned().collect()
+    }
+
+    /// The note on the message, then the note on its session.
+    fn notes_of_message(&self, message: &ClaudeMessage) -> Vec<String> {
+        [Target::Message(message.uuid.clone()), Target::Session(message.session_id.clone())].iter()
+            .filter_map(|target| self.notes.get(target))
+            .map(|note| note.body.clone())
+            .collect()
+    }
+
+    fn has_tags(&self, message: &ClaudeMessage, query: &SearchQuery) -> bool {
+        let tags = self.tags_of_message(message);
+        match query.tag_mode {
+            TagMode::All => query.tags.iter().all(|tag| tags.contains(tag)),
+            TagMode::Any => query.tags.is_empty() || query.tags.iter().any(|tag| tags.contains(tag)),
+        }
+    }
+}
+
+/// A vault kept entirely in memory, for tests.
+///
+/// Keywords are always matched as case-insensitive substrings and ranked by
+/// their number of occurrences, which is what DuckDB does without the FTS
+/// index, minus the bigram weighting of CJK terms.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, MemoryState>> {
+        self.state.lock().map_err(|e| anyhow!("Lock poisoned: {}", e))
+    }
+
+    /// Whether an import would leave the stored copy as it is. As with the
+    /// DuckDB merge, `session_id` and `timestamp` are not compared because an
+    /// update never changes them.
+    fn unchanged(stored: &ClaudeMessage, message: &ClaudeMessage) -> bool {
+        stored.parent_uuid == message.parent_uuid
+            && stored.user_type == message.user_type
+            && stored.message_type == message.message_type
+            && stored.is_sidechain == message.is_sidechain
+            && stored.message == message.message
+            && stored.cwd == message.cwd
+            && stored.git_branch == message.git_branch
+            && stored.version == message.version
+            && stored.extra == message.extra
+    }
+
+    /// Rank of `content` for `query`, or `None` when it does not match.
+    fn rank(content: &str, query: &SearchQuery, patterns: &[Regex]) -> Option<f64> {
+        let counts: Vec<usize> = match query.mode {
+            SearchMode::Regex => patterns.iter().map(|re| re.find_iter(content).count()).collect(),
+            SearchMode::And | SearchMode::Or => {
+                let content = content.to_lowercase();
+                keyword_terms(&query.keywords).iter()
+                    .map(|term| content.matches(&term.to_lowercase()).count())
+                    .collect()
+            }
+        };
+
+        let matched = match query.mode {
+            SearchMode::Or => counts.iter().any(|&n| n > 0),
+            SearchMode::And | SearchMode::Regex => !counts.is_empty() && counts.iter().all(|&n| n > 0),
+        };
+        matched.then(|| counts.iter().sum::<usize>() as f64)
+    }
+
+    /// What `search` would return, but matching every message within the
+    /// filters when there are no keywords or tags, and without snippets.
+    fn matches(&self, query: &SearchQuery) -> Result<Vec<SearchResult>> {
+        let patterns = match query.mode {
+            SearchMode::Regex => {
+                validate_regex_patterns(&query.keywords)?;
+                query.keywords.iter().map(|p| Regex::new(p)).collect::<Result<Vec<_>, _>>()?
+            }
+            SearchMode::And | SearchMode::Or => Vec::new(),
+        };
+
+        let projects: Option<HashSet<&str>> = match (&query.project_filters, &query.project_filter) {
+            (Some(projects), _) if !projects.is_empty() => Some(projects.iter().map(String::as_str).collect()),
+            (Some(_), _) => None,
+            (None, Some(project)) => Some(HashSet::from([project.as_str()])),
+            (None, None) => None,
+        };
+
+        let state = self.lock()?;
+        let mut results: Vec<SearchResult> = state.messages.iter()
+            .filter(|stored| projects.as_ref().is_none_or(|p| p.contains(stored.project_path.as_str())))
+            .filter(|stored| query.date_from.is_none_or(|from| stored.message.timestamp >= from))
+            .filter(|stored| query.date_to.is_none_or(|to| stored.message.timestamp < to))
+            .filter(|stored| query.favorites_only != Some(true) || state.is_favorite(&stored.message))
+            .filter(|stored| state.has_tags(&stored.message, query))
+            .filter_map(|stored| {
+                let content = stored.message.message.plain_text();
+                let notes = state.notes_of_message(&stored.message);
+                let rank = if query.keywords.is_empty() {
+                    0.0
+                } else {
+                    // A note on the message or its session can match instead of the content
+                    let content_rank = Self::rank(content.as_deref().unwrap_or_default(), query, &patterns);
+                    let note_ranks: Vec<f64> = notes.iter()
+                        .filter_map(|body| Self::rank(body, query, &patterns))
+                        .collect();
+                    if content_rank.is_none() && note_ranks.is_empty() {
+                        return None;
+                    }
+                    content_rank.unwrap_or(0.0) + note_ranks.iter().sum::<f64>()
+                };
+                Some(SearchResult {
+                    id: stored.id,
+                    uuid: stored.message.uuid.clone(),
+                    session_id: stored.message.session_id.clone(),
+                    message_content: content,
+                    message_role: stored.message.message.role.clone(),
+                    project_path: stored.project_path.clone(),
+                    timestamp: stored.message.timestamp,
+                    rank,
+                    is_favorite: state.is_favorite(&stored.message),
+                    tags: state.tags_of_message(&stored.message),
+                    notes,
+                    snippet: None,
+                })
+            })
+            .collect();
+
+        results.sort_by(|a, b| {
+            b.rank.total_cmp(&a.rank)
+                .then_with(|| b.timestamp.cmp(&a.timestamp))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        if let Some(limit) = query.limit {
+            results.truncate(limit);
+        }
+        Ok(results)
+    }
+
+    fn session_message(message: &ClaudeMessage, project_path: &str) -> SessionMessage {
+        let block_summary = message.message.blocks().iter()
+            .filter(|block| block.block_type() != "text")
+            .map(|block| match block.tool_name() {
+                Some(name) => format!("{}: {}", block.block_type(), name),
+                None => block.block_type().to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        SessionMessage {
+            uuid: message.uuid.clone(),
+            parent_uuid: message.parent_uuid.clone(),
+            session_id: message.session_id.clone(),
+            message_type: message.message_type.clone(),
+            message_role: message.message.role.clone(),
+            content_text: message.message.plain_text(),
+            block_summary: (!block_summary.is_empty()).then(|| block_summary.join(", ")),
+            project_path: project_path.to_string(),
+            timestamp: message.timestamp,
+        }
+    }
+}
+
+impl VaultStore for MemoryStore {
+    fn upsert_messages(
+        &self,
+        records: &mut dyn Iterator<Item = Result<JsonlRecord>>,
+        project_path: &str,
+        force: bool,
+    ) -> Result<ImportStats> {
+        let mut stats = ImportStats::new();
+        let mut messages = Vec::new();
+        let mut summaries = Vec::new();
+
+        for record in records {
+            match record {
+                Ok(JsonlRecord::Message(message)) | Ok(JsonlRecord::System(message)) => messages.push(message),
+                Ok(JsonlRecord::Summary(summary)) => match summary.leaf_uuid {
+                    Some(leaf_uuid) => summaries.push((leaf_uuid, summary.summary)),
+                    None => stats.skipped += 1,
+                },
+                Ok(JsonlRecord::Unknown { .. }) => stats.skipped += 1,
+                Err(_) => stats.errors += 1,
+            }
+        }
+
+        let last_copy: HashMap<&str, usize> = messages.iter()
+            .enumerate()
+            .map(|(i, message)| (message.uuid.as_str(), i))
+            .collect();
+
+        let mut state = self.lock()?;
+        for (i, message) in messages.iter().enumerate() {
+            if last_copy[message.uuid.as_str()] != i {
+                stats.skipped += 1;
+                continue;
+            }
+
+            match state.by_uuid.get(&message.uuid).copied() {
+                None => {
+                    let index = state.messages.len();
+                    let id = index as i64 + 1;
+                    state.by_uuid.insert(message.uuid.clone(), index);
+                    state.messages.push(StoredMessage {
+                        id,
+                        project_path: project_path.to_string(),
+                        message: message.clone(),
+                    });
+                    stats.inserted += 1;
+                }
+                Some(index) => {
+                    let stored = &mut state.messages[index].message;
+                    if !force && Self::unchanged(stored, message) {
+                        stats.skipped += 1;
+                        continue;
+                    }
+                    *stored = ClaudeMessage {
+                        session_id: stored.session_id.clone(),
+                        timestamp: stored.timestamp,
+                        ..message.clone()
+                    };
+                    stats.updated += 1;
+                }
+            }
+        }
+
+        for summary in summaries {
+            if state.summaries.insert(summary) {
+                stats.inserted += 1;
+            } else {
+                stats.skipped += 1;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn refresh_search_index(&self) -> Result<()> {
+        // Every search scans the messages directly
+        Ok(())
+    }
+
+    fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>> {
+        if query.keywords.is_empty() && query.tags.is_empty() && query.favorites_only != Some(true) {
+            return Ok(Vec::new());
+        }
+
+        let mut results = self.matches(query)?;
+        attach_snippets(&mut results, query);
+        Ok(results)
+    }
+
+    fn export_rows(&self, query: &SearchQuery) -> Result<Vec<ExportRow>> {
+        let mut hits = self.matches(query)?;
+        hits.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+
+        let state = self.lock()?;
+        Ok(hits.into_iter()
+            .map(|hit| {
+                let message = &state.messages[state.by_uuid[&hit.uuid]].message;
+                let note = |target: Target| state.notes.get(&target).map(|note| note.body.clone());
+                ExportRow {
+                    parent_uuid: message.parent_uuid.clone(),
+                    message_type: message.message_type.clone(),
+                    git_branch: message.git_branch.clone(),
+                    version: message.version.clone(),
+                    note: note(Target::Message(hit.uuid.clone())),
+                    session_note: note(Target::Session(hit.session_id.clone())),
+                    uuid: hit.uuid,
+                    session_id: hit.session_id,
+                    project_path: hit.project_path,
+                    timestamp: hit.timestamp,
+                    role: hit.message_role,
+                    content: hit.message_content,
+                    is_favorite: hit.is_favorite,
+                    tags: hit.tags,
+                }
+            })
+            .collect())
+    }
+
+    fn copy_rows(&self, _query: &SearchQuery, format: ExportFormat, _path: &Path) -> Result<()> {
+        Err(anyhow!("{} exports are written by DuckDB's COPY, which the memory store lacks", format.extension()))
+    }
+
+    fn find_tool_calls(&self, query: &ToolCallQuery) -> Result<Vec<ToolCall>> {
+        let state = self.lock()?;
+        let mut calls: HashMap<String, ToolCall> = HashMap::new();
+        let mut results: HashMap<String, (String, bool)> = HashMap::new();
+
+        // Later messages win, like the upserts into `tool_calls`
+        for stored in &state.messages {
+            let message = &stored.message;
+            for block in message.message.blocks() {
+                match block {
+                    ContentBlock::ToolUse { id, name, input } if !id.is_empty() => {
+                        calls.insert(id.clone(), ToolCall {
+                            tool_use_id: id,
+                            tool_name: name,
+                            input_json: Some(input.to_string()),
+                            result_text: None,
+                            is_error: None,
+                            session_id: message.session_id.clone(),
+                            message_uuid: message.uuid.clone(),
+                            timestamp: message.timestamp,
+                        });
+                    }
+                    ContentBlock::ToolResult { tool_use_id, content, is_error } if !tool_use_id.is_empty() => {
+                        results.insert(tool_use_id, (content, is_error));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let needle = query.input_contains.as_ref().map(|n| n.to_lowercase());
+        let mut calls: Vec<ToolCall> = calls.into_values()
+            .filter(|call| query.tool_name.as_ref().is_none_or(|name| &call.tool_name == name))
+            .filter(|call| needle.as_ref().is_none_or(|needle| {
+                call.input_json.as_deref().unwrap_or_default().to_lowercase().contains(needle)
+            }))
+            .filter(|call| query.session_id.as_ref().is_none_or(|session| &call.session_id == session))
+            .map(|mut call| {
+                if let Some((text, is_error)) = results.remove(&call.tool_use_id) {
+                    call.result_text = Some(text);
+                    call.is_error = Some(is_error);
+                }
+                call
+            })
+            .collect();
+
+        calls.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| a.tool_use_id.cmp(&b.tool_use_id)));
+        calls.truncate(query.limit.unwrap_or(100));
+        Ok(calls)
+    }
+
+    fn add_favorite(&self, target: &Target, note: Option<&str>) -> Result<()> {
+        let mut state = self.lock()?;
+        let entry = state.favorites.entry(target.clone()).or_insert_with(|| (None, Utc::now()));
+        if let Some(note) = note {
+            entry.0 = Some(note.to_string());
+        }
+        Ok(())
+    }
+
+    fn remove_favorite(&self, target: &Target) -> Result<bool> {
+        Ok(self.lock()?.favorites.remove(target).is_some())
+    }
+
+    fn favorites(&self) -> Result<Vec<Favorite>> {
+        let state = self.lock()?;
+        let mut favorites: Vec<Favorite> = state.favorites.iter()
+            .map(|(target, (note, created_at))| Favorite {
+                target: target.clone(),
+                note: note.clone(),
+                created_at: *created_at,
+            })
+            .collect();
+        favorites.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.target.cmp(&b.target)));
+        Ok(favorites)
+    }
+
+    fn add_tag(&self, target: &Target, tag: &str) -> Result<()> {
+        self.lock()?.taggings.insert((tag.to_string(), target.clone()));
+        Ok(())
+    }
+
+    fn remove_tag(&self, target: &Target, tag: &str) -> Result<bool> {
+        Ok(self.lock()?.taggings.remove(&(tag.to_string(), target.clone())))
+    }
+
+    fn tags_of(&self, target: &Target) -> Result<Vec<String>> {
+        Ok(self.lock()?.taggings.iter()
+            .filter(|(_, tagged)| tagged == target)
+            .map(|(tag, _)| tag.clone())
+            .collect())
+    }
+
+    fn tag_counts(&self) -> Result<Vec<TagCount>> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        let state = self.lock()?;
+        for (tag, _) in &state.taggings {
+            *counts.entry(tag).or_default() += 1;
+        }
+        Ok(counts.into_iter().map(|(tag, count)| TagCount { tag: tag.to_string(), count }).collect())
+    }
+
+    fn note(&self, target: &Target) -> Result<Option<Note>> {
+        Ok(self.lock()?.notes.get(target).cloned())
+    }
+
+    fn save_note(&self, target: &Target, body: &str) -> Result<()> {
+        let now = Utc::now();
+        let mut state = self.lock()?;
+        let note = state.notes.entry(target.clone()).or_insert_with(|| Note {
+            target: target.clone(),
+            body: String::new(),
+            created_at: now,
+            updated_at: now,
+        });
+        note.body = body.to_string();
+        note.updated_at = now;
+        Ok(())
+    }
+
+    fn remove_note(&self, target: &Target) -> Result<bool> {
+        Ok(self.lock()?.notes.remove(target).is_some())
+    }
+
+    fn notes(&self) -> Result<Vec<Note>> {
+        let mut notes: Vec<Note> = self.lock()?.notes.values().cloned().collect();
+        notes.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then_with(|| a.target.cmp(&b.target)));
+        Ok(notes)
+    }
+
+    fn resolve_session(&self, id: &str) -> Result<Option<(String, Option<String>)>> {
+        let state = self.lock()?;
+        if state.messages.iter().any(|stored| stored.message.session_id == id) {
+            return Ok(Some((id.to_string(), None)));
+        }
+
+        let index = state.by_uuid.get(id).copied().or_else(|| {
+            let conversation_id = id.parse::<i64>().ok()?;
+            state.messages.iter().position(|stored| stored.id == conversation_id)
+        });
+        Ok(index.map(|index| {
+            let message = &state.messages[index].message;
+            (message.session_id.clone(), Some(message.uuid.clone()))
+        }))
+    }
+
+    fn load_session(&self, session_id: &str) -> Result<SessionThread> {
+        let state = self.lock()?;
+        let messages = state.messages.iter()
+            .filter(|stored| stored.message.session_id == session_id)
+            .map(|stored| Self::session_message(&stored.message, &stored.project_path))
+            .collect();
+        Ok(SessionThread::new(messages))
+    }
+
+    fn session_messages(&self, session_id: &str) -> Result<Vec<ClaudeMessage>> {
+        let state = self.lock()?;
+        let mut messages: Vec<ClaudeMessage> = state.messages.iter()
+            .filter(|stored| stored.message.session_id == session_id)
+            .map(|stored| stored.message.clone())
+            .collect();
+        // Stable, so messages with the same timestamp stay in import order like `ORDER BY timestamp, id`
+        messages.sort_by_key(|message| message.timestamp);
+        Ok(messages)
+    }
+
+    fn session_summaries(&self, session_id: &str) -> Result<Vec<String>> {
+        let state = self.lock()?;
+        let mut summaries: Vec<(chrono::DateTime<chrono::Utc>, String)> = state.summaries.iter()
+            .filter_map(|(leaf_uuid, summary)| {
+                let leaf = &state.messages[*state.by_uuid.get(leaf_uuid)?].message;
+                (leaf.session_id == session_id).then(|| (leaf.timestamp, summary.clone()))
+            })
+            .collect();
+
+        summaries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        Ok(summaries.into_iter().map(|(_, summary)| summary).collect())
+    }
+
+    fn import_state(&self, source_path: &str) -> Result<Option<ImportState>> {
+        Ok(self.lock()?.import_states.get(source_path).cloned())
+    }
+
+    fn save_import_state(&self, state: &ImportState) -> Result<()> {
+        self.lock()?.import_states.insert(state.source_path.clone(), state.clone());
+        Ok(())
+    }
+
+    fn archived_len(&self, source_path: &str) -> Result<Option<u64>> {
+        Ok(self.lock()?.raw_files.get(source_path)
+            .map(|(_, _, content)| content.len() as u64)
+            .filter(|&len| len > 0))
+    }
+
+    fn archive_raw(&self, source: &RawSource, offset: u64, lines: &mut dyn BufRead) -> Result<()> {
+        let mut state = self.lock()?;
+        let archived = state.raw_files.get(&source.source_path).map_or(0, |(_, _, content)| content.len());
+        if offset > archived as u64 {
+            return Err(anyhow!(
+                "Cannot archive {} from byte {}: only {} bytes are archived",
+                source.source_path, offset, archived
+            ));
+        }
+        let inside_line = state.raw_files.get(&source.source_path)
+            .is_some_and(|(_, _, content)| offset > 0 && content.get(offset as usize - 1) != Some(&b'\n'));
+        if inside_line {
+            return Err(anyhow!("Cannot archive {} from byte {}: it is inside an archived line", source.source_path, offset));
+        }
+        let mut content = state.raw_files.remove(&source.source_path)
+            .map(|(_, _, content)| content)
+            .unwrap_or_default();
+        content.truncate(offset as usize);
+        lines.read_to_end(&mut content)?;
+        state.raw_archivals += 1;
+        let order = state.raw_archivals;
+        state.raw_files.insert(source.source_path.clone(), (order, source.clone(), content));
+        Ok(())
+    }
+
+    fn raw_source(&self, session_id: &str) -> Result<Option<RawSource>> {
+        Ok(self.lock()?.raw_files.values()
+            .filter(|(_, _, content)| content.split_inclusive(|&b| b == b'\n')
+                .any(|line| line_session_id(line).as_deref() == Some(session_id)))
+            .max_by_key(|(order, _, _)| *order)
+            .map(|(_, source, _)| source.clone()))
+    }
+
+    fn write_raw_file(&self, source_path: &str, out: &mut dyn Write) -> Result<u64> {
+        let state = self.lock()?;
+        let content = state.raw_files.get(source_path).map_or(&[][..], |(_, _, content)| content);
+        out.write_all(content)?;
+        Ok(content.len() as u64)
+    }
+
+    fn stats(&self) -> Result<VaultStats> {
+        let state = self.lock()?;
+        let sessions: HashSet<&str> = state.messages.iter().map(|s| s.message.session_id.as_str()).collect();
+        let projects: HashSet<&str> = state.messages.iter().map(|s| s.project_path.as_str()).collect();
+
+        Ok(VaultStats {
+            messages: state.messages.len(),
+            sessions: sessions.len(),
+            projects: projects.len(),
+            favorites: state.favorites.len(),
+            summaries: state.summaries.len(),
+        })
+    }
+}
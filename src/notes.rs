@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use crate::db_connection::SqlValue;
+use crate::real_db_connection::ExtendedDatabaseConnection;
+use crate::store::Target;
+
+/// Free text attached to a message or a whole session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub target: Target,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const UPSERT_NOTE: &str = r#"
+INSERT INTO notes (target_kind, target_id, body) VALUES (?, ?, ?)
+ON CONFLICT (target_kind, target_id) DO UPDATE SET body = excluded.body, updated_at = now()"#;
+
+const DELETE_NOTE: &str =
+    "DELETE FROM notes WHERE target_kind = ? AND target_id = ? RETURNING id";
+
+const NOTE_COLUMNS: &str = "target_kind, target_id, body, epoch_us(created_at), epoch_us(updated_at)";
+
+/// At most one note per message or session. Keeping the notes index current is
+/// left to the caller.
+pub struct NoteStore<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
+}
+
+impl<'a, C: ExtendedDatabaseConnection> NoteStore<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
+        Self { connection }
+    }
+
+    fn connected(&self) -> Result<&C> {
+        if !self.connection.is_connected() {
+            return Err(anyhow!("Database not connected"));
+        }
+        Ok(self.connection)
+    }
+
+    /// Creates the note or replaces its text, keeping the creation time.
+    pub fn save(&self, target: &Target, body: &str) -> Result<()> {
+        let [kind, id] = target.key_params();
+        self.connected()?.execute_with_params(UPSERT_NOTE, &[kind, id, SqlValue::Text(body.to_string())])
+    }
+
+    /// Returns whether there was a note to remove.
+    pub fn remove(&self, target: &Target) -> Result<bool> {
+        let removed: Vec<i64> = self.connected()?.query_all(
+            DELETE_NOTE,
+            &target.key_params(),
+            |row| Ok(row.get(0)?),
+        )?;
+        Ok(!removed.is_empty())
+    }
+
+    pub fn get(&self, target: &Target) -> Result<Option<Note>> {
+        let sql = format!("SELECT {} FROM notes WHERE target_kind = ? AND target_id = ?", NOTE_COLUMNS);
+        Ok(self.connected()?.query_all(&sql, &target.key_params(), Self::map_note)?.pop())
+    }
+
+    /// Every note, most recently updated first.
+    pub fn list(&self) -> Result<Vec<Note>> {
+        let sql = format!(
+            "SELECT {} FROM notes ORDER BY updated_at DESC, target_kind, target_id",
+            NOTE_COLUMNS
+        );
+        self.connected()?.query_all(&sql, &[], Self::map_note)
+    }
+
+    fn map_note(row: &duckdb::Row) -> Result<Note> {
+        let kind: String = row.get(0)?;
+        let timestamp = |index: usize| -> Result<DateTime<Utc>> {
+            let us: i64 = row.get(index)?;
+            DateTime::from_timestamp_micros(us).ok_or_else(|| anyhow!("Invalid timestamp: {}", us))
+        };
+        Ok(Note {
+            target: Target::from_parts(&kind, row.get(1)?)?,
+            body: row.get(2)?,
+            created_at: timestamp(3)?,
+            updated_at: timestamp(4)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::setup_db;
+    use crate::real_db_connection::RealDuckDBConnection;
+    use crate::db_connection::ConnectionConfig;
+
+    #[test]
+    fn test_save_get_and_remove() {
+        let conn = setup_db();
+        let notes = NoteStore::new(&conn);
+        let session = Target::Session("session-1".to_string());
+
+        assert_eq!(notes.get(&session).unwrap(), None);
+        notes.save(&session, "how we fixed the lock issue").unwrap();
+        let created = notes.get(&session).unwrap().unwrap();
+        notes.save(&session, "how we fixed the DuckDB lock issue").unwrap();
+        let edited = notes.get(&session).unwrap().unwrap();
+        assert_eq!(edited.body, "how we fixed the DuckDB lock issue");
+        assert_eq!(edited.created_at, created.created_at);
+        assert!(edited.updated_at >= created.updated_at);
+
+        notes.save(&Target::Message("uuid-1".to_string()), "the fix").unwrap();
+        assert_eq!(notes.list().unwrap().len(), 2);
+        assert!(notes.remove(&session).unwrap());
+        assert!(!notes.remove(&session).unwrap());
+        assert_eq!(notes.list().unwrap().iter().map(|n| n.body.as_str()).collect::<Vec<_>>(), vec!["the fix"]);
+    }
+
+    #[test]
+    fn test_when_not_connected() {
+        let conn = RealDuckDBConnection::new(ConnectionConfig::default());
+        let result = NoteStore::new(&conn).list();
+        assert!(result.unwrap_err().to_string().contains("Database not connected"));
+    }
+}
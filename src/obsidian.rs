@@ -0,0 +1,361 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+use crate::export::{find_sessions, render_turns, ExportRow, SessionExport};
+use crate::jsonl_parser::ContentBlock;
+use crate::search::SearchQuery;
+use crate::store::VaultStore;
+
+/// Tool inputs that name a file the tool read or changed.
+const FILE_INPUTS: [&str; 2] = ["file_path", "notebook_path"];
+
+/// Longest title kept in a new note's file name.
+const FILE_TITLE_CHARS: usize = 60;
+
+/// What `export_obsidian` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObsidianStats {
+    pub written: usize,
+    pub unchanged: usize,
+}
+
+/// A session on its way to becoming a note.
+struct SessionNote {
+    export: SessionExport,
+    title: String,
+    /// Relative to the vault folder
+    path: PathBuf,
+    rows: Vec<ExportRow>,
+    tags: BTreeSet<String>,
+    /// Files the session's tool calls read or changed
+    files: BTreeSet<String>,
+}
+
+impl SessionNote {
+    /// The name Obsidian links to: the file name without `.md`.
+    fn link_name(&self) -> String {
+        self.path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+    }
+}
+
+/// Writes one note per session into `out`, in folders named after the
+/// sessions' project paths. With keywords or tags, `query` selects sessions
+/// like the markdown export; otherwise every session within its filters.
+///
+/// Notes are keyed by the `session_id` in their front matter, so exporting
+/// again rewrites each session's note wherever it is in the vault, even after
+/// it was moved or renamed in Obsidian, and notes whose content is unchanged
+/// are left untouched.
+pub fn export_obsidian(store: &dyn VaultStore, query: &SearchQuery, out: &Path) -> Result<ObsidianStats> {
+    let session_ids: Vec<String> = if query.keywords.is_empty() && query.tags.is_empty() {
+        let mut seen = BTreeSet::new();
+        store.export_rows(query)?.into_iter()
+            .map(|row| row.session_id)
+            .filter(|id| seen.insert(id.clone()))
+            .collect()
+    } else {
+        find_sessions(store, query)?.into_iter().map(|(id, _)| id).collect()
+    };
+
+    // Front matter covers the whole session, not just the rows the query matched
+    let mut rows_by_session: HashMap<String, Vec<ExportRow>> = HashMap::new();
+    for row in store.export_rows(&SearchQuery { limit: None, ..Default::default() })? {
+        rows_by_session.entry(row.session_id.clone()).or_default().push(row);
+    }
+    let existing = existing_notes(out)?;
+
+    let mut notes = Vec::new();
+    for session_id in session_ids {
+        let export = SessionExport::load(store, &session_id, None)?;
+        let rows = rows_by_session.remove(&session_id).unwrap_or_default();
+        let title = export.title();
+        let path = existing.get(&session_id).cloned().unwrap_or_else(|| {
+            project_folder(&export.project_path).join(file_name(&title, &session_id))
+        });
+        let files = store.session_messages(&session_id)?.iter()
+            .flat_map(|message| message.message.blocks())
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { input, .. } => Some(input),
+                _ => None,
+            })
+            .flat_map(|input| FILE_INPUTS.iter().filter_map(|key| input.get(key)?.as_str().map(str::to_string)).collect::<Vec<_>>())
+            .collect();
+        notes.push(SessionNote {
+            tags: rows.iter().flat_map(|row| row.tags.iter().cloned()).collect(),
+            title,
+            path,
+            rows,
+            files,
+            export,
+        });
+    }
+
+    let mut stats = ObsidianStats { written: 0, unchanged: 0 };
+    for note in &notes {
+        let contents = render_note(note, &notes);
+        let path = out.join(&note.path);
+        if std::fs::read_to_string(&path).is_ok_and(|current| current == contents) {
+            stats.unchanged += 1;
+            continue;
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        std::fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+        stats.written += 1;
+    }
+    Ok(stats)
+}
+
+/// Notes already in the vault, by the `session_id` in their front matter.
+fn existing_notes(out: &Path) -> Result<HashMap<String, PathBuf>> {
+    let mut notes = HashMap::new();
+    if !out.exists() {
+        return Ok(notes);
+    }
+    for entry in WalkDir::new(out).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() || entry.path().extension().is_none_or(|ext| ext != "md") {
+            continue;
+        }
+        // Files that are not UTF-8 are not ours
+        let Ok(text) = std::fs::read_to_string(entry.path()) else { continue };
+        if let Some(session_id) = front_matter_session_id(&text) {
+            let relative = entry.path().strip_prefix(out)?.to_path_buf();
+            notes.entry(session_id).or_insert(relative);
+        }
+    }
+    Ok(notes)
+}
+
+fn front_matter_session_id(text: &str) -> Option<String> {
+    let front_matter = text.strip_prefix("---\n")?;
+    let front_matter = &front_matter[..front_matter.find("\n---")?];
+    let value = front_matter.lines().find_map(|line| line.strip_prefix("session_id:"))?.trim();
+    // Written as a JSON string, but accept a plain or single-quoted YAML value after hand edits
+    Some(serde_json::from_str::<String>(value).unwrap_or_else(|_| value.trim_matches('\'').to_string()))
+}
+
+/// `/home/me/work/app` becomes `home/me/work/app`.
+fn project_folder(project_path: &str) -> PathBuf {
+    let folder: PathBuf = Path::new(project_path).components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(file_safe(&name.to_string_lossy())),
+            _ => None,
+        })
+        .filter(|name| !name.is_empty())
+        .collect();
+    if folder.as_os_str().is_empty() { PathBuf::from("unknown-project") } else { folder }
+}
+
+/// The title plus the start of the session ID, which keeps names unique.
+fn file_name(title: &str, session_id: &str) -> String {
+    let title: String = file_safe(title).chars().take(FILE_TITLE_CHARS).collect();
+    let id: String = file_safe(session_id).chars().take(8).collect();
+    format!("{} ({}).md", title.trim(), id)
+}
+
+/// Drops what file systems or Obsidian links cannot hold.
+fn file_safe(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c.is_control() || "\\/:*?\"<>|#^[]".contains(c) { ' ' } else { c })
+        .collect();
+    name.split_whitespace().collect::<Vec<_>>().join(" ").trim_start_matches('.').to_string()
+}
+
+fn render_note(note: &SessionNote, notes: &[SessionNote]) -> String {
+    let export = &note.export;
+    let mut out = String::from("---\n");
+    let _ = writeln!(out, "session_id: {}", yaml_string(&export.session_id));
+    let _ = writeln!(out, "project: {}", yaml_string(&export.project_path));
+    let branch = export.messages.iter().rev().find_map(|m| m.git_branch.as_deref());
+    let _ = writeln!(out, "git_branch: {}", branch.map(yaml_string).unwrap_or_else(|| "null".to_string()));
+    if note.tags.is_empty() {
+        out.push_str("tags: []\n");
+    } else {
+        out.push_str("tags:\n");
+        for tag in &note.tags {
+            let _ = writeln!(out, "  - {}", yaml_string(tag));
+        }
+    }
+    let _ = writeln!(out, "favorite: {}", note.rows.iter().any(|row| row.is_favorite));
+    if let (Some(first), Some(last)) = (note.rows.first(), note.rows.last()) {
+        let _ = writeln!(out, "created: {}", timestamp(first.timestamp));
+        let _ = writeln!(out, "updated: {}", timestamp(last.timestamp));
+    }
+    out.push_str("---\n\n");
+
+    let _ = writeln!(out, "# {}\n", note.title);
+    if let Some(body) = note.rows.iter().find_map(|row| row.session_note.as_deref()) {
+        callout(&mut out, "note", "Note", body, false);
+    }
+    let related = related(note, notes);
+    if !related.is_empty() {
+        out.push_str("## Related\n\n");
+        for line in related {
+            let _ = writeln!(out, "- {}", line);
+        }
+        out.push('\n');
+    }
+    if export.skipped_messages > 0 {
+        let _ = writeln!(out, "_{} messages on other branches are not included._\n", export.skipped_messages);
+    }
+    out.push_str("---\n\n");
+
+    render_turns(&mut out, export, |out, summary, body| callout(out, "example", summary, body, true));
+    out
+}
+
+/// Links to the other sessions that share a file or a tag with `note`, the
+/// ones sharing the most first.
+fn related(note: &SessionNote, notes: &[SessionNote]) -> Vec<String> {
+    let mut related: Vec<(usize, String, String)> = notes.iter()
+        .filter(|other| other.export.session_id != note.export.session_id)
+        .filter_map(|other| {
+            let files: Vec<String> = note.files.intersection(&other.files)
+                .map(|file| format!("`{}`", relative_file(file, &note.export.project_path)))
+                .collect();
+            let tags: Vec<&str> = note.tags.intersection(&other.tags).map(String::as_str).collect();
+            if files.is_empty() && tags.is_empty() {
+                return None;
+            }
+            let shared = [
+                (!files.is_empty()).then(|| format!("files {}", files.join(", "))),
+                (!tags.is_empty()).then(|| format!("tags {}", tags.join(", "))),
+            ];
+            let link = format!(
+                "[[{}|{}]] (shares {})",
+                other.link_name(),
+                other.title.replace(['|', '[', ']'], " ").split_whitespace().collect::<Vec<_>>().join(" "),
+                shared.into_iter().flatten().collect::<Vec<_>>().join("; ")
+            );
+            Some((files.len() + tags.len(), other.title.clone(), link))
+        })
+        .collect();
+    related.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    related.into_iter().map(|(_, _, link)| link).collect()
+}
+
+fn relative_file<'a>(file: &'a str, project_path: &str) -> &'a str {
+    file.strip_prefix(project_path)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .unwrap_or(file)
+}
+
+/// An Obsidian callout; folded ones start collapsed.
+fn callout(out: &mut String, kind: &str, summary: &str, body: &str, folded: bool) {
+    let _ = writeln!(out, "> [!{}]{} {}", kind, if folded { "-" } else { "" }, summary);
+    for line in body.lines() {
+        out.push_str(if line.is_empty() { ">\n" } else { "> " });
+        if !line.is_empty() {
+            let _ = writeln!(out, "{}", line);
+        }
+    }
+    out.push('\n');
+}
+
+/// JSON strings are valid double-quoted YAML scalars.
+fn yaml_string(value: &str) -> String {
+    Value::String(value.to_string()).to_string()
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonl_parser::JsonlParser;
+    use crate::memory_store::MemoryStore;
+    use crate::store::Target;
+    use crate::test_support::line;
+
+    fn edit(id: &str, file: &str) -> Value {
+        serde_json::json!([{"type": "tool_use", "id": id, "name": "Edit", "input": {"file_path": file, "old_string": "a"}}])
+    }
+
+    fn store() -> MemoryStore {
+        let lines = [
+            line("u1", None, "session-one", 0, "user", "Fix the lock".into()),
+            line("a1", Some("u1"), "session-one", 1, "assistant", edit("t1", "/work/app/src/lock.rs")),
+            line("u2", None, "session-two", 5, "user", "Lock again?".into()),
+            line("a2", Some("u2"), "session-two", 6, "assistant", edit("t2", "/work/app/src/lock.rs")),
+            line("u3", None, "session-three", 9, "user", "Unrelated".into()),
+            r#"{"type":"summary","summary":"Vault: lock [fix]","leafUuid":"a1"}"#.to_string(),
+        ];
+        let store = MemoryStore::new();
+        let parser = JsonlParser::new();
+        let mut records = lines.iter().map(|l| parser.parse_record(l));
+        store.upsert_messages(&mut records, "/work/app", false).unwrap();
+        store.add_tag(&Target::Session("session-one".to_string()), "postmortem").unwrap();
+        store.add_tag(&Target::Message("u3".to_string()), "postmortem").unwrap();
+        store.add_favorite(&Target::Session("session-one".to_string()), None).unwrap();
+        store.save_note(&Target::Session("session-one".to_string()), "Root cause was\nthe watcher").unwrap();
+        store
+    }
+
+    #[test]
+    fn test_export_obsidian() {
+        let store = store();
+        let dir = tempfile::tempdir().unwrap();
+        let everything = SearchQuery { limit: None, ..Default::default() };
+        let stats = export_obsidian(&store, &everything, dir.path()).unwrap();
+        assert_eq!(stats, ObsidianStats { written: 3, unchanged: 0 });
+
+        let one = dir.path().join("work/app/Vault lock fix (session-).md");
+        let note = std::fs::read_to_string(&one).unwrap();
+        assert!(note.starts_with(concat!(
+            "---\nsession_id: \"session-one\"\nproject: \"/work/app\"\ngit_branch: \"main\"\n",
+            "tags:\n  - \"postmortem\"\nfavorite: true\n",
+            "created: 2025-07-21T12:00:00Z\nupdated: 2025-07-21T12:01:00Z\n---\n\n",
+            "# Vault: lock [fix]\n\n",
+            "> [!note] Note\n> Root cause was\n> the watcher\n\n",
+            "## Related\n\n",
+        )));
+        assert!(note.contains("[[Lock again (session-)|Lock again?]] (shares files `src/lock.rs`)\n"));
+        assert!(note.contains("[[Unrelated (session-)|Unrelated]] (shares tags postmortem)\n"));
+        assert!(note.contains("> [!example]- Tool: Edit\n> ```json\n"));
+
+        let two = std::fs::read_to_string(dir.path().join("work/app/Lock again (session-).md")).unwrap();
+        assert!(two.contains("tags: []\nfavorite: false\n"));
+        assert!(two.contains("[[Vault lock fix (session-)|Vault: lock fix]] (shares files `src/lock.rs`)"));
+        assert!(!two.contains("Unrelated"));
+
+        // Exporting again finds each note by its session_id, even after it moved
+        let moved = dir.path().join("Archive/renamed.md");
+        std::fs::create_dir_all(moved.parent().unwrap()).unwrap();
+        std::fs::rename(&one, &moved).unwrap();
+        store.add_tag(&Target::Session("session-one".to_string()), "watcher").unwrap();
+        let stats = export_obsidian(&store, &everything, dir.path()).unwrap();
+        assert_eq!(stats, ObsidianStats { written: 3, unchanged: 0 });
+        assert!(!one.exists());
+        assert!(std::fs::read_to_string(&moved).unwrap().contains("  - \"watcher\"\n"));
+        let two = std::fs::read_to_string(dir.path().join("work/app/Lock again (session-).md")).unwrap();
+        assert!(two.contains("[[renamed|"));
+
+        let stats = export_obsidian(&store, &everything, dir.path()).unwrap();
+        assert_eq!(stats, ObsidianStats { written: 0, unchanged: 3 });
+        let count = WalkDir::new(dir.path()).into_iter().filter(|e| e.as_ref().unwrap().file_type().is_file()).count();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_front_matter_session_id() {
+        assert_eq!(front_matter_session_id("---\nsession_id: \"abc\"\n---\n# x"), Some("abc".to_string()));
+        assert_eq!(front_matter_session_id("---\ntitle: x\nsession_id: abc\n---\n"), Some("abc".to_string()));
+        assert_eq!(front_matter_session_id("# no front matter\nsession_id: abc\n"), None);
+        assert_eq!(front_matter_session_id("---\ntitle: x\n---\nsession_id: abc\n"), None);
+    }
+
+    #[test]
+    fn test_file_names() {
+        assert_eq!(project_folder("/home/me/work/app"), PathBuf::from("home/me/work/app"));
+        assert_eq!(project_folder("/"), PathBuf::from("unknown-project"));
+        assert_eq!(file_name("Fix: a|b [draft]", "0123456789"), "Fix a b draft (01234567).md");
+    }
+}
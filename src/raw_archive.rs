@@ -0,0 +1,324 @@
+use anyhow::{anyhow, Context, Result};
+use duckdb::params;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::db_connection::SqlValue;
+use crate::real_db_connection::ExtendedDatabaseConnection;
+use crate::store::VaultStore;
+
+/// Lines fetched per query when writing an archived file back out.
+const RESTORE_PAGE_LINES: i64 = 1024;
+
+/// The file an archived transcript was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawSource {
+    pub source_path: String,
+    pub project_path: String,
+}
+
+impl RawSource {
+    pub fn new(path: &Path, project_path: &str) -> Self {
+        Self {
+            source_path: path.to_string_lossy().to_string(),
+            project_path: project_path.to_string(),
+        }
+    }
+}
+
+/// The `sessionId` of the record on `line`, if it parses and has one. A file
+/// belongs to every session its records name, whatever the file is called.
+pub fn line_session_id(line: &[u8]) -> Option<String> {
+    #[derive(Deserialize)]
+    struct SessionOf {
+        #[serde(rename = "sessionId")]
+        session_id: Option<String>,
+    }
+
+    serde_json::from_slice::<SessionOf>(line).ok()?.session_id
+}
+
+/// Calls `f` with every line of `lines` and its offset from where reading
+/// started. Only one line is held in memory at a time.
+pub fn for_each_line(lines: &mut dyn BufRead, mut f: impl FnMut(u64, &[u8]) -> Result<()>) -> Result<()> {
+    let mut line = Vec::new();
+    let mut offset = 0;
+    loop {
+        line.clear();
+        let read = lines.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            return Ok(());
+        }
+        f(offset, &line)?;
+        offset += read as u64;
+    }
+}
+
+/// Archives bytes `from..to` of `path`, which must start at or before the end
+/// of what is already archived for it.
+pub fn archive_range(store: &dyn VaultStore, path: &Path, project_path: &str, from: u64, to: u64) -> Result<()> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    file.seek(SeekFrom::Start(from))?;
+    let mut lines = BufReader::new(file.take(to.saturating_sub(from)));
+    store.archive_raw(&RawSource::new(path, project_path), from, &mut lines)
+}
+
+/// Whether the two files hold the same bytes, read a buffer at a time.
+pub fn same_content(a: &Path, b: &Path) -> Result<bool> {
+    if std::fs::metadata(a)?.len() != std::fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    let (mut a, mut b) = (BufReader::new(File::open(a)?), BufReader::new(File::open(b)?));
+    loop {
+        let chunk = a.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(true);
+        }
+        let len = chunk.len();
+        let mut other = vec![0u8; len];
+        b.read_exact(&mut other)?;
+        if chunk != other.as_slice() {
+            return Ok(false);
+        }
+        a.consume(len);
+    }
+}
+
+const ARCHIVED_LEN: &str =
+    "SELECT max(byte_offset + octet_length(raw_json)) FROM raw_lines WHERE source_path = ?";
+
+const STRADDLED_LINES: &str = r#"
+SELECT count(*) FROM raw_lines
+WHERE source_path = ? AND byte_offset < ? AND byte_offset + octet_length(raw_json) > ?"#;
+
+/// Lines of one `append`, filled through the Appender before they replace
+/// the archived ones.
+const CREATE_STAGED_RAW_LINES: &str =
+    "CREATE OR REPLACE TEMP TABLE staged_raw_lines (byte_offset BIGINT, session_id TEXT, raw_json BLOB)";
+
+const MERGE_STAGED_RAW_LINES: &str = r#"
+INSERT INTO raw_lines (source_path, byte_offset, project_path, session_id, raw_json)
+SELECT CAST(? AS TEXT), byte_offset, CAST(? AS TEXT), session_id, raw_json
+FROM staged_raw_lines
+ORDER BY byte_offset"#;
+
+// A file that moved is archived under both paths; the one archived last wins
+const LATEST_RAW_FILE: &str = r#"
+SELECT source_path, arg_max(project_path, byte_offset) FROM raw_lines
+WHERE source_path IN (SELECT source_path FROM raw_lines WHERE session_id = ?)
+GROUP BY source_path
+ORDER BY max(archived_at) DESC, source_path
+LIMIT 1"#;
+
+const RAW_LINES_PAGE: &str = r#"
+SELECT byte_offset, raw_json FROM raw_lines
+WHERE source_path = ? AND byte_offset >= ?
+ORDER BY byte_offset
+LIMIT ?"#;
+
+/// Appends archived lines and writes a session's file back out from them.
+pub struct RawArchiveStore<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
+}
+
+impl<'a, C: ExtendedDatabaseConnection> RawArchiveStore<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
+        Self { connection }
+    }
+
+    fn connected(&self) -> Result<&C> {
+        if !self.connection.is_connected() {
+            return Err(anyhow!("Database not connected"));
+        }
+        Ok(self.connection)
+    }
+
+    /// How many bytes of the file are archived, `None` if none ever were.
+    pub fn archived_len(&self, source_path: &str) -> Result<Option<u64>> {
+        let len: Option<i64> = self.connected()?
+            .query_row(ARCHIVED_LEN, &[SqlValue::Text(source_path.to_string())], |row| Ok(row.get(0)?))?
+            .flatten();
+        Ok(len.map(|n| n as u64))
+    }
+
+    /// Stores the lines read from `lines` from `offset` on and drops anything
+    /// archived after them, in one transaction. `offset` has to be where an
+    /// archived line starts or where the archive ends.
+    pub fn append(&self, source: &RawSource, offset: u64, lines: &mut dyn BufRead) -> Result<()> {
+        let path = source.source_path.as_str();
+        self.connected()?.transaction(|conn| {
+            let archived: Option<i64> = conn.query_row(ARCHIVED_LEN, params![path], |row| row.get(0))?;
+            let archived = archived.unwrap_or(0) as u64;
+            if offset > archived {
+                return Err(anyhow!(
+                    "Cannot archive {} from byte {}: only {} bytes are archived",
+                    path, offset, archived
+                ));
+            }
+            let straddled: i64 = conn.query_row(
+                STRADDLED_LINES, params![path, offset as i64, offset as i64], |row| row.get(0),
+            )?;
+            if straddled > 0 {
+                return Err(anyhow!("Cannot archive {} from byte {}: it is inside an archived line", path, offset));
+            }
+
+            conn.execute_batch(CREATE_STAGED_RAW_LINES)?;
+            let mut staged = conn.appender("staged_raw_lines")?;
+            for_each_line(lines, |start, line| {
+                staged.append_row(params![(offset + start) as i64, line_session_id(line), line])?;
+                Ok(())
+            })?;
+            staged.flush()?;
+            drop(staged);
+
+            conn.execute("DELETE FROM raw_lines WHERE source_path = ? AND byte_offset >= ?", params![path, offset as i64])?;
+            conn.execute(MERGE_STAGED_RAW_LINES, params![path, source.project_path])?;
+            conn.execute_batch("DROP TABLE IF EXISTS staged_raw_lines")?;
+            Ok(())
+        })
+    }
+
+    /// The most recently archived file with lines of the session.
+    pub fn source(&self, session_id: &str) -> Result<Option<RawSource>> {
+        self.connected()?.query_row(LATEST_RAW_FILE, &[SqlValue::Text(session_id.to_string())], |row| {
+            Ok(RawSource {
+                source_path: row.get(0)?,
+                project_path: row.get(1)?,
+            })
+        })
+    }
+
+    /// Writes the archived lines of the file to `out` in order, a page at a
+    /// time, and returns how many bytes that was.
+    pub fn write_file(&self, source_path: &str, out: &mut dyn Write) -> Result<u64> {
+        // One transaction, so a concurrent sync cannot change the file between pages
+        self.connected()?.transaction(|conn| {
+            let mut page = conn.prepare(RAW_LINES_PAGE)?;
+            let mut next_offset = 0i64;
+            let mut written = 0u64;
+            loop {
+                let mut rows = page.query(params![source_path, next_offset, RESTORE_PAGE_LINES])?;
+                let mut fetched = 0;
+                while let Some(row) = rows.next()? {
+                    let offset: i64 = row.get(0)?;
+                    let line: Vec<u8> = row.get(1)?;
+                    out.write_all(&line)?;
+                    written += line.len() as u64;
+                    next_offset = offset + 1;
+                    fetched += 1;
+                }
+                if fetched < RESTORE_PAGE_LINES {
+                    return Ok(written);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::setup_db;
+    use crate::real_db_connection::RealDuckDBConnection;
+    use crate::db_connection::ConnectionConfig;
+
+    fn source(path: &str) -> RawSource {
+        RawSource::new(Path::new(path), "/test/project")
+    }
+
+    #[test]
+    fn test_line_session_id() {
+        assert_eq!(line_session_id(b"{\"sessionId\":\"s1\",\"uuid\":\"m1\"}\r\n").as_deref(), Some("s1"));
+        assert_eq!(line_session_id(b"{\"type\":\"summary\"}\n"), None);
+        assert_eq!(line_session_id(b"not json\n"), None);
+        assert_eq!(line_session_id(b"\n"), None);
+    }
+
+    /// The session's file as `write_file` restores it.
+    fn restored(archive: &RawArchiveStore<RealDuckDBConnection>, session_id: &str) -> Option<(RawSource, Vec<u8>)> {
+        let source = archive.source(session_id).unwrap()?;
+        let mut content = Vec::new();
+        let written = archive.write_file(&source.source_path, &mut content).unwrap();
+        assert_eq!(written, content.len() as u64);
+        Some((source, content))
+    }
+
+    #[test]
+    fn test_for_each_line() {
+        let mut lines = Vec::new();
+        for_each_line(&mut &b"a\n\r\nbc\nunfinished"[..], |offset, line| {
+            lines.push((offset, line.to_vec()));
+            Ok(())
+        }).unwrap();
+        assert_eq!(lines, vec![
+            (0, b"a\n".to_vec()),
+            (2, b"\r\n".to_vec()),
+            (4, b"bc\n".to_vec()),
+            (7, b"unfinished".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn test_append_and_restore() {
+        let conn = setup_db();
+        let archive = RawArchiveStore::new(&conn);
+        // The session comes from the records, not the file name
+        let renamed = source("/p/renamed.jsonl");
+        let first = b"{\"sessionId\":\"s1\",\"uuid\":\"m1\"}\n";
+
+        assert_eq!(archive.archived_len(&renamed.source_path).unwrap(), None);
+        assert_eq!(restored(&archive, "s1"), None);
+
+        archive.append(&renamed, 0, &mut &first[..]).unwrap();
+        archive.append(&renamed, 31, &mut &b"\nline \xff two\n"[..]).unwrap();
+        assert_eq!(archive.archived_len(&renamed.source_path).unwrap(), Some(43));
+        assert_eq!(restored(&archive, "renamed"), None);
+        assert_eq!(restored(&archive, "s1"), Some((renamed.clone(), [&first[..], b"\nline \xff two\n"].concat())));
+
+        // Re-archiving from a line keeps what comes before it, but not from inside one
+        archive.append(&renamed, 32, &mut &b"line 2\n"[..]).unwrap();
+        assert_eq!(restored(&archive, "s1").unwrap().1, [&first[..], b"\nline 2\n"].concat());
+        let result = archive.append(&renamed, 34, &mut &b"ne 2\n"[..]);
+        assert!(result.unwrap_err().to_string().contains("inside an archived line"));
+
+        // A rewritten file replaces the whole archive
+        archive.append(&renamed, 0, &mut &b""[..]).unwrap();
+        assert_eq!(archive.archived_len(&renamed.source_path).unwrap(), None);
+        assert_eq!(restored(&archive, "s1"), None);
+
+        let result = archive.append(&renamed, 5, &mut &b"gap\n"[..]);
+        assert!(result.unwrap_err().to_string().contains("only 0 bytes are archived"));
+    }
+
+    #[test]
+    fn test_restore_spans_pages() {
+        let conn = setup_db();
+        let archive = RawArchiveStore::new(&conn);
+        let content: Vec<u8> = (0..RESTORE_PAGE_LINES * 2 + 1)
+            .flat_map(|i| format!("{{\"sessionId\":\"s1\",\"n\":{}}}\n", i).into_bytes())
+            .collect();
+        archive.append(&source("/p/s1.jsonl"), 0, &mut &content[..]).unwrap();
+        assert_eq!(restored(&archive, "s1").unwrap().1, content);
+    }
+
+    #[test]
+    fn test_same_content() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (a, b, c) = (dir.path().join("a"), dir.path().join("b"), dir.path().join("c"));
+        std::fs::write(&a, "same\n").unwrap();
+        std::fs::write(&b, "same\n").unwrap();
+        std::fs::write(&c, "diff\n").unwrap();
+        assert!(same_content(&a, &b).unwrap());
+        assert!(!same_content(&a, &c).unwrap());
+    }
+
+    #[test]
+    fn test_when_not_connected() {
+        let conn = RealDuckDBConnection::new(ConnectionConfig::default());
+        let result = RawArchiveStore::new(&conn).source("s1");
+        assert!(result.unwrap_err().to_string().contains("Database not connected"));
+    }
+}
@@ -1,8 +1,8 @@
 use anyhow::{anyhow, Result};
-use duckdb::{Connection, params};
+use duckdb::{Connection, params, params_from_iter};
 use std::sync::{Arc, Mutex};
 use std::path::Path;
-use crate::db_connection::{DatabaseConnection, ConnectionConfig};
+use crate::db_connection::{DatabaseConnection, ConnectionConfig, SqlValue};
 
 pub struct RealDuckDBConnection {
     connection: Arc<Mutex<Option<Connection>>>,
@@ -36,8 +36,17 @@ impl DatabaseConnection for RealDuckDBConnection {
             Connection::open_in_memory()
                 .map_err(|e| anyhow!("Failed to create in-memory DuckDB: {}", e))?
         } else {
-            Connection::open(&self.config.database)
-                .map_err(|e| anyhow!("Failed to connect to DuckDB: {}", e))?
+            Connection::open(&self.config.database).map_err(|e| {
+                // DuckDB lets one process at a time open a database file for writing
+                if e.to_string().contains("Could not set lock") {
+                    anyhow!(
+                        "{} is in use by another process, such as a running `cc-vault watch`; stop it and try again",
+                        self.config.database
+                    )
+                } else {
+                    anyhow!("Failed to connect to DuckDB: {}", e)
+                }
+            })?
         };
         
         *conn_guard = Some(conn);
@@ -71,26 +80,42 @@ impl DatabaseConnection for RealDuckDBConnection {
         Ok(())
     }
     
-    fn as_any(&self) -> &dyn std::any::Any {
-        self
+    fn execute_with_params(&self, query: &str, params: &[SqlValue]) -> Result<()> {
+        let conn_guard = self.connection.lock()
+            .map_err(|e| anyhow!("Lock poisoned: {}", e))?;
+        
+        let conn = conn_guard.as_ref()
+            .ok_or_else(|| anyhow!("Not connected to database"))?;
+        
+        conn.execute(query, params_from_iter(params))
+            .map_err(|e| anyhow!("Failed to execute query: {}", e))?;
+        
+        Ok(())
     }
 }
 
 // Extended connection with query support
 pub trait ExtendedDatabaseConnection: DatabaseConnection {
-    fn query_row<T, F>(&self, query: &str, mapper: F) -> Result<Option<T>>
+    fn query_row<T, F>(&self, query: &str, params: &[SqlValue], mapper: F) -> Result<Option<T>>
     where
         F: FnOnce(&duckdb::Row) -> Result<T>;
         
-    fn query_all<T, F>(&self, query: &str, mapper: F) -> Result<Vec<T>>
+    fn query_all<T, F>(&self, query: &str, params: &[SqlValue], mapper: F) -> Result<Vec<T>>
     where
         F: Fn(&duckdb::Row) -> Result<T>;
         
+    #[allow(dead_code)]
     fn execute_batch(&self, queries: &[&str]) -> Result<()>;
+
+    /// Runs `f` against the underlying connection inside a transaction,
+    /// committing if it succeeds and rolling back if it fails.
+    fn transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> Result<T>;
 }
 
 impl ExtendedDatabaseConnection for RealDuckDBConnection {
-    fn query_row<T, F>(&self, query: &str, mapper: F) -> Result<Option<T>>
+    fn query_row<T, F>(&self, query: &str, params: &[SqlValue], mapper: F) -> Result<Option<T>>
     where
         F: FnOnce(&duckdb::Row) -> Result<T>
     {
@@ -103,16 +128,16 @@ impl ExtendedDatabaseConnection for RealDuckDBConnection {
         let mut stmt = conn.prepare(query)
             .map_err(|e| anyhow!("Failed to prepare query: {}", e))?;
         
-        let mut rows = stmt.query(params![])
+        let mut rows = stmt.query(params_from_iter(params))
             .map_err(|e| anyhow!("Failed to execute query: {}", e))?;
         
         match rows.next()? {
-            Some(row) => Ok(Some(mapper(&row)?)),
+            Some(row) => Ok(Some(mapper(row)?)),
             None => Ok(None),
         }
     }
     
-    fn query_all<T, F>(&self, query: &str, mapper: F) -> Result<Vec<T>>
+    fn query_all<T, F>(&self, query: &str, params: &[SqlValue], mapper: F) -> Result<Vec<T>>
     where
         F: Fn(&duckdb::Row) -> Result<T>
     {
@@ -125,12 +150,12 @@ impl ExtendedDatabaseConnection for RealDuckDBConnection {
         let mut stmt = conn.prepare(query)
             .map_err(|e| anyhow!("Failed to prepare query: {}", e))?;
         
-        let mut rows = stmt.query(params![])
+        let mut rows = stmt.query(params_from_iter(params))
             .map_err(|e| anyhow!("Failed to execute query: {}", e))?;
         
         let mut results = Vec::new();
         while let Some(row) = rows.next()? {
-            results.push(mapper(&row)?);
+            results.push(mapper(row)?);
         }
         
         Ok(results)
@@ -142,6 +167,33 @@ impl ExtendedDatabaseConnection for RealDuckDBConnection {
         }
         Ok(())
     }
+
+    fn transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> Result<T>
+    {
+        let conn_guard = self.connection.lock()
+            .map_err(|e| anyhow!("Lock poisoned: {}", e))?;
+        
+        let conn = conn_guard.as_ref()
+            .ok_or_else(|| anyhow!("Not connected to database"))?;
+        
+        conn.execute_batch("BEGIN TRANSACTION")
+            .map_err(|e| anyhow!("Failed to begin transaction: {}", e))?;
+        
+        match f(conn) {
+            Ok(value) => {
+                conn.execute_batch("COMMIT")
+                    .map_err(|e| anyhow!("Failed to commit transaction: {}", e))?;
+                Ok(value)
+            }
+            Err(e) => {
+                // The original error matters more than a failed rollback
+                let _ = conn.execute_batch("ROLLBACK");
+                Err(e)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -176,7 +228,10 @@ mod tests {
     
     #[test]
     fn test_in_memory_connection() {
-        let config = ConnectionConfig::default();
+        let config = ConnectionConfig {
+            database: ":memory:".to_string(),
+            ..Default::default()
+        };
         let conn = RealDuckDBConnection::new(config);
         
         assert!(conn.connect().is_ok());
@@ -188,10 +243,31 @@ mod tests {
         
         // Verify data
         let result: Option<String> = conn.query_row(
-            "SELECT value FROM test WHERE id = 1",
+            "SELECT value FROM test WHERE id = 1", &[],
             |row| Ok(row.get(0)?)
         ).unwrap();
         
         assert_eq!(result, Some("hello".to_string()));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_transaction_rolls_back_on_error() {
+        let conn = RealDuckDBConnection::new(ConnectionConfig {
+            database: ":memory:".to_string(),
+            ..Default::default()
+        });
+        conn.connect().unwrap();
+        conn.execute("CREATE TABLE test (id INTEGER)").unwrap();
+        
+        let result: Result<()> = conn.transaction(|tx| {
+            tx.execute("INSERT INTO test VALUES (1)", params![])?;
+            Err(anyhow!("abort"))
+        });
+        assert!(result.is_err());
+        
+        conn.transaction(|tx| Ok(tx.execute("INSERT INTO test VALUES (2)", params![])?)).unwrap();
+        
+        let ids: Vec<i32> = conn.query_all("SELECT id FROM test", &[], |row| Ok(row.get(0)?)).unwrap();
+        assert_eq!(ids, vec![2]);
+    }
+}
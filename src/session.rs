@@ -0,0 +1,533 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use crate::db_connection::SqlValue;
+use crate::jsonl_parser::{ClaudeMessage, MessageContent};
+use crate::real_db_connection::ExtendedDatabaseConnection;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionMessage {
+    pub uuid: String,
+    pub parent_uuid: Option<String>,
+    pub session_id: String,
+    pub message_type: String,
+    pub message_role: Option<String>,
+    pub content_text: Option<String>,
+    /// Comma-separated block types (and tool names) for messages without prose
+    pub block_summary: Option<String>,
+    pub project_path: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// One message in a flattened thread, with enough layout information to draw it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreadEntry<'a> {
+    pub message: &'a SessionMessage,
+    /// Number of forks between the root and this message
+    pub depth: usize,
+    /// Position among its siblings (0 for the first), and how many there are
+    pub branch_index: usize,
+    pub branch_count: usize,
+}
+
+/// The `parent_uuid` graph of one session.
+///
+/// Editing a prompt or retrying a response gives a message more than one child;
+/// each child starts a branch. Siblings are ordered by timestamp, so the last
+/// child of every fork is the branch that was continued most recently.
+#[derive(Debug, Clone)]
+pub struct SessionThread {
+    messages: Vec<SessionMessage>,
+    index: HashMap<String, usize>,
+    children: HashMap<String, Vec<usize>>,
+    roots: Vec<usize>,
+}
+
+impl SessionThread {
+    /// Builds the graph. Messages whose parent is missing from the set (for example
+    /// the first message of a resumed session) become roots.
+    pub fn new(mut messages: Vec<SessionMessage>) -> Self {
+        messages.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.uuid.cmp(&b.uuid)));
+
+        let index: HashMap<String, usize> = messages.iter()
+            .enumerate()
+            .map(|(i, m)| (m.uuid.clone(), i))
+            .collect();
+
+        let mut children: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut roots = Vec::new();
+        for (i, message) in messages.iter().enumerate() {
+            match &message.parent_uuid {
+                Some(parent) if index.contains_key(parent) && parent != &message.uuid => {
+                    children.entry(parent.clone()).or_default().push(i);
+                }
+                _ => roots.push(i),
+            }
+        }
+
+        Self { messages, index, children, roots }
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, uuid: &str) -> Option<&SessionMessage> {
+        self.index.get(uuid).map(|&i| &self.messages[i])
+    }
+
+    /// Messages in timestamp order, ignoring the graph.
+    pub fn messages(&self) -> &[SessionMessage] {
+        &self.messages
+    }
+
+    #[allow(dead_code)]
+    pub fn children_of(&self, uuid: &str) -> Vec<&SessionMessage> {
+        self.children.get(uuid)
+            .map(|ids| ids.iter().map(|&i| &self.messages[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Whole session in depth-first order: each branch is printed in full before its
+    /// later siblings. Messages caught in a `parent_uuid` cycle are appended at depth 0.
+    pub fn flatten(&self) -> Vec<ThreadEntry<'_>> {
+        let mut entries = Vec::with_capacity(self.messages.len());
+        let mut visited = HashSet::new();
+
+        // Explicit stack of (message, depth, branch_index, branch_count)
+        let mut stack: Vec<(usize, usize, usize, usize)> = self.roots.iter()
+            .enumerate()
+            .rev()
+            .map(|(i, &root)| (root, 0, i, self.roots.len()))
+            .collect();
+
+        while let Some((i, depth, branch_index, branch_count)) = stack.pop() {
+            if !visited.insert(i) {
+                continue;
+            }
+            let message = &self.messages[i];
+            entries.push(ThreadEntry { message, depth, branch_index, branch_count });
+
+            if let Some(kids) = self.children.get(&message.uuid) {
+                let child_depth = if kids.len() > 1 { depth + 1 } else { depth };
+                for (k, &child) in kids.iter().enumerate().rev() {
+                    stack.push((child, child_depth, k, kids.len()));
+                }
+            }
+        }
+
+        for (i, message) in self.messages.iter().enumerate() {
+            if !visited.contains(&i) {
+                entries.push(ThreadEntry { message, depth: 0, branch_index: 0, branch_count: 1 });
+            }
+        }
+
+        entries
+    }
+
+    /// Ancestors of `uuid` from the root down to the message itself.
+    pub fn ancestors(&self, uuid: &str) -> Vec<&SessionMessage> {
+        let mut chain = Vec::new();
+        let mut seen = HashSet::new();
+        let mut current = self.get(uuid);
+
+        while let Some(message) = current {
+            if !seen.insert(message.uuid.as_str()) {
+                break;
+            }
+            chain.push(message);
+            current = message.parent_uuid.as_deref().and_then(|p| self.get(p));
+        }
+
+        chain.reverse();
+        chain
+    }
+
+    /// Descendants of `uuid` along the most recent branch at every fork.
+    pub fn continuation(&self, uuid: &str) -> Vec<&SessionMessage> {
+        let mut chain = Vec::new();
+        let mut seen = HashSet::from([uuid.to_string()]);
+        let mut current = uuid.to_string();
+
+        while let Some(&last) = self.children.get(&current).and_then(|kids| kids.last()) {
+            let message = &self.messages[last];
+            if !seen.insert(message.uuid.clone()) {
+                break;
+            }
+            chain.push(message);
+            current = message.uuid.clone();
+        }
+
+        chain
+    }
+
+    /// Up to `before` messages leading to `uuid` and `after` messages following it.
+    ///
+    /// Neighbours follow the parent chain (and the most recent branch forward).
+    /// Messages with no links at all, such as rows imported without `parent_uuid`,
+    /// fall back to timestamp order within the session.
+    pub fn neighbors(&self, uuid: &str, before: usize, after: usize) -> (Vec<&SessionMessage>, Vec<&SessionMessage>) {
+        let Some(&position) = self.index.get(uuid) else {
+            return (Vec::new(), Vec::new());
+        };
+
+        let message = &self.messages[position];
+        let linked = self.children.contains_key(uuid)
+            || message.parent_uuid.as_deref().is_some_and(|p| self.index.contains_key(p));
+
+        if linked {
+            let mut ancestors = self.ancestors(uuid);
+            ancestors.pop();
+            let skip = ancestors.len().saturating_sub(before);
+            let preceding = ancestors.into_iter().skip(skip).collect();
+            let following = self.continuation(uuid).into_iter().take(after).collect();
+            (preceding, following)
+        } else {
+            let preceding = self.messages[position.saturating_sub(before)..position].iter().collect();
+            let following = self.messages[position + 1..].iter().take(after).collect();
+            (preceding, following)
+        }
+    }
+
+    /// The conversation as the user experienced it around `uuid`: its ancestors,
+    /// the message itself, and the most recent continuation.
+    pub fn path_through(&self, uuid: &str) -> Vec<&SessionMessage> {
+        let mut path = self.ancestors(uuid);
+        if !path.is_empty() {
+            path.extend(self.continuation(uuid));
+        }
+        path
+    }
+}
+
+const SESSION_MESSAGE_COLUMNS: &str = r#"
+    c.uuid,
+    c.parent_uuid,
+    c.session_id,
+    c.message_type,
+    c.message_role,
+    c.content_text,
+    (SELECT string_agg(b.block_type || coalesce(': ' || b.tool_name, ''), ', ' ORDER BY b.block_index)
+       FROM content_blocks b
+      WHERE b.message_uuid = c.uuid AND b.block_type <> 'text') AS block_summary,
+    c.project_path,
+    epoch_us(c.timestamp) AS timestamp_us"#;
+
+/// Every stored field of a message, in the order `map_full_message_row` expects.
+const FULL_MESSAGE_COLUMNS: &str = r#"
+    uuid, parent_uuid, session_id, user_type, message_type, message_role, message_content,
+    cwd, git_branch, version, epoch_us(timestamp), extra, is_sidechain, message_extra"#;
+
+/// Resolves the ids users type and loads whole sessions for `show`, `export`
+/// and the TUI.
+pub struct SessionStore<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
+}
+
+impl<'a, C: ExtendedDatabaseConnection> SessionStore<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
+        Self { connection }
+    }
+
+    fn connected(&self) -> Result<&C> {
+        if !self.connection.is_connected() {
+            return Err(anyhow!("Database not connected"));
+        }
+        Ok(self.connection)
+    }
+
+    /// Resolves an identifier that is a session ID, a message UUID, or the
+    /// numeric conversation ID shown in search results. Returns the session ID
+    /// and, for messages, the message to highlight.
+    pub fn resolve(&self, id: &str) -> Result<Option<(String, Option<String>)>> {
+        let conn = self.connected()?;
+
+        let text_id = [SqlValue::Text(id.to_string())];
+        let session: Option<String> = conn.query_row(
+            "SELECT session_id FROM conversations WHERE session_id = ? LIMIT 1",
+            &text_id,
+            |row| Ok(row.get(0)?),
+        )?;
+        if let Some(session) = session {
+            return Ok(Some((session, None)));
+        }
+
+        let message = conn.query_row(
+            "SELECT session_id, uuid FROM conversations WHERE uuid = ?",
+            &text_id,
+            |row| Ok((row.get(0)?, Some(row.get(1)?))),
+        )?;
+        match (message, id.parse::<i64>()) {
+            (None, Ok(conversation_id)) => conn.query_row(
+                "SELECT session_id, uuid FROM conversations WHERE id = ?",
+                &[conversation_id.into()],
+                |row| Ok((row.get(0)?, Some(row.get(1)?))),
+            ),
+            (message, _) => Ok(message),
+        }
+    }
+
+    pub fn load_messages(&self, session_id: &str) -> Result<Vec<SessionMessage>> {
+        let conn = self.connected()?;
+
+        let sql = format!(
+            "SELECT {} FROM conversations c WHERE c.session_id = ? ORDER BY c.timestamp, c.id",
+            SESSION_MESSAGE_COLUMNS
+        );
+        conn.query_all(&sql, &[SqlValue::Text(session_id.to_string())], Self::map_session_row)
+    }
+
+    /// The session's messages rebuilt from `conversations` as they were imported,
+    /// oldest first.
+    pub fn load_full_messages(&self, session_id: &str) -> Result<Vec<ClaudeMessage>> {
+        let conn = self.connected()?;
+
+        let sql = format!(
+            "SELECT {} FROM conversations WHERE session_id = ? ORDER BY timestamp, id",
+            FULL_MESSAGE_COLUMNS
+        );
+        conn.query_all(&sql, &[SqlValue::Text(session_id.to_string())], Self::map_full_message_row)
+    }
+
+    /// Summary titles of the session, newest leaf first. A summary belongs to
+    /// the session containing its leaf message, wherever the summary line itself
+    /// was written.
+    pub fn summaries(&self, session_id: &str) -> Result<Vec<String>> {
+        let conn = self.connected()?;
+
+        conn.query_all(
+            "SELECT s.summary FROM summaries s JOIN conversations c ON c.uuid = s.leaf_uuid \
+             WHERE c.session_id = ? ORDER BY c.timestamp DESC, s.summary",
+            &[SqlValue::Text(session_id.to_string())],
+            |row| Ok(row.get(0)?),
+        )
+    }
+
+    pub fn load_thread(&self, session_id: &str) -> Result<SessionThread> {
+        Ok(SessionThread::new(self.load_messages(session_id)?))
+    }
+
+    fn map_full_message_row(row: &duckdb::Row) -> Result<ClaudeMessage> {
+        let content: Option<String> = row.get(6)?;
+        let extra: Option<String> = row.get(11)?;
+        let message_extra: Option<String> = row.get(13)?;
+        let timestamp_us: i64 = row.get(10)?;
+        Ok(ClaudeMessage {
+            uuid: row.get(0)?,
+            parent_uuid: row.get(1)?,
+            is_sidechain: row.get::<_, Option<bool>>(12)?.unwrap_or_default(),
+            session_id: row.get(2)?,
+            user_type: row.get(3)?,
+            message_type: row.get(4)?,
+            message: MessageContent::from_parts(
+                row.get(5)?,
+                content.map(|json| serde_json::from_str(&json)).transpose()?,
+                message_extra.as_deref(),
+            )?,
+            cwd: row.get(7)?,
+            git_branch: row.get(8)?,
+            version: row.get(9)?,
+            timestamp: DateTime::from_timestamp_micros(timestamp_us)
+                .ok_or_else(|| anyhow!("Invalid timestamp: {}", timestamp_us))?,
+            extra: extra.map(|json| serde_json::from_str(&json)).transpose()?.unwrap_or_default(),
+        })
+    }
+
+    fn map_session_row(row: &duckdb::Row) -> Result<SessionMessage> {
+        let timestamp_us: i64 = row.get(8)?;
+        Ok(SessionMessage {
+            uuid: row.get(0)?,
+            parent_uuid: row.get(1)?,
+            session_id: row.get(2)?,
+            message_type: row.get(3)?,
+            message_role: row.get(4)?,
+            content_text: row.get(5)?,
+            block_summary: row.get(6)?,
+            project_path: row.get(7)?,
+            timestamp: DateTime::from_timestamp_micros(timestamp_us)
+                .ok_or_else(|| anyhow!("Invalid timestamp: {}", timestamp_us))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::real_db_connection::RealDuckDBConnection;
+    use crate::data_importer::DataImporter;
+    use crate::db_connection::ConnectionConfig;
+    use crate::jsonl_parser::{ClaudeMessage, JsonlRecord, SummaryRecord};
+    use crate::test_support::{self, import_messages, setup_db};
+
+    fn message(uuid: &str, parent: Option<&str>, minute: i64) -> SessionMessage {
+        SessionMessage {
+            uuid: uuid.to_string(),
+            parent_uuid: parent.map(str::to_string),
+            session_id: "session-1".to_string(),
+            message_type: "user".to_string(),
+            message_role: Some("user".to_string()),
+            content_text: Some(format!("message {}", uuid)),
+            block_summary: None,
+            project_path: "/test/project".to_string(),
+            timestamp: DateTime::from_timestamp(1_700_000_000 + minute * 60, 0).unwrap(),
+        }
+    }
+
+    /// a -> b -> c -> d, with b retried as b2 -> e
+    fn branched_thread() -> SessionThread {
+        SessionThread::new(vec![
+            message("d", Some("c"), 3),
+            message("a", None, 0),
+            message("b", Some("a"), 1),
+            message("c", Some("b"), 2),
+            message("b2", Some("a"), 4),
+            message("e", Some("b2"), 5),
+        ])
+    }
+
+    fn uuids(messages: &[&SessionMessage]) -> Vec<String> {
+        messages.iter().map(|m| m.uuid.clone()).collect()
+    }
+
+    #[test]
+    fn test_flatten_orders_branches_depth_first() {
+        let thread = branched_thread();
+        let entries = thread.flatten();
+
+        let order: Vec<(&str, usize, usize)> = entries.iter()
+            .map(|e| (e.message.uuid.as_str(), e.depth, e.branch_index))
+            .collect();
+        assert_eq!(order, vec![
+            ("a", 0, 0),
+            ("b", 1, 0),
+            ("c", 1, 0),
+            ("d", 1, 0),
+            ("b2", 1, 1),
+            ("e", 1, 0),
+        ]);
+        assert_eq!(entries[1].branch_count, 2);
+    }
+
+    #[test]
+    fn test_path_through_follows_latest_branch() {
+        let thread = branched_thread();
+
+        assert_eq!(uuids(&thread.path_through("c")), vec!["a", "b", "c", "d"]);
+        assert_eq!(uuids(&thread.path_through("a")), vec!["a", "b2", "e"]);
+        assert!(thread.path_through("missing").is_empty());
+        assert_eq!(uuids(&thread.children_of("a")), vec!["b", "b2"]);
+    }
+
+    #[test]
+    fn test_neighbors_follow_parent_chain() {
+        let thread = branched_thread();
+
+        let (before, after) = thread.neighbors("c", 1, 5);
+        assert_eq!(uuids(&before), vec!["b"]);
+        assert_eq!(uuids(&after), vec!["d"]);
+
+        // "b2" comes after "d" by time, but its parent is "a"
+        let (before, after) = thread.neighbors("b2", 3, 1);
+        assert_eq!(uuids(&before), vec!["a"]);
+        assert_eq!(uuids(&after), vec!["e"]);
+
+        let (before, after) = thread.neighbors("missing", 1, 1);
+        assert!(before.is_empty() && after.is_empty());
+    }
+
+    #[test]
+    fn test_neighbors_fall_back_to_timestamps() {
+        let thread = SessionThread::new(vec![
+            message("x", None, 0),
+            message("y", None, 1),
+            message("z", None, 2),
+        ]);
+
+        let (before, after) = thread.neighbors("y", 2, 2);
+        assert_eq!(uuids(&before), vec!["x"]);
+        assert_eq!(uuids(&after), vec!["z"]);
+    }
+
+    #[test]
+    fn test_orphans_and_cycles_are_kept() {
+        let thread = SessionThread::new(vec![
+            message("a", Some("from-previous-session"), 0),
+            message("x", Some("y"), 1),
+            message("y", Some("x"), 2),
+        ]);
+
+        let entries = thread.flatten();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].message.uuid, "a");
+        assert_eq!(uuids(&thread.ancestors("y")), vec!["x", "y"]);
+    }
+
+    fn claude_message(uuid: &str, parent: Option<&str>, content: serde_json::Value) -> ClaudeMessage {
+        test_support::message(uuid, parent, "session-db", "assistant", content, chrono::Duration::zero())
+    }
+
+    #[test]
+    fn test_load_thread_from_database() {
+        let conn = setup_db();
+
+        import_messages(&conn, [&claude_message("m1", None, serde_json::json!("Question"))], "/test/project");
+        import_messages(&conn, [&claude_message("m2", Some("m1"), serde_json::json!([
+            {"type": "tool_use", "id": "toolu_1", "name": "Bash", "input": {"command": "ls"}}
+        ]))], "/test/project");
+
+        let store = SessionStore::new(&conn);
+        assert_eq!(store.resolve("session-db").unwrap(), Some(("session-db".to_string(), None)));
+        assert_eq!(store.resolve("m2").unwrap(), Some(("session-db".to_string(), Some("m2".to_string()))));
+        assert_eq!(store.resolve("nope").unwrap(), None);
+
+        let thread = store.load_thread("session-db").unwrap();
+        assert_eq!(thread.len(), 2);
+        assert_eq!(uuids(&thread.path_through("m1")), vec!["m1", "m2"]);
+        assert_eq!(thread.get("m2").unwrap().block_summary.as_deref(), Some("tool_use: Bash"));
+        assert_eq!(thread.get("m1").unwrap().content_text.as_deref(), Some("Question"));
+        assert!(store.summaries("session-db").unwrap().is_empty());
+        let summary = SummaryRecord {
+            summary: "Listing files".to_string(),
+            leaf_uuid: Some("m2".to_string()),
+            extra: Default::default(),
+        };
+        DataImporter::new(&conn).bulk_import([Ok(JsonlRecord::Summary(summary))], "/test/project", false).unwrap();
+        assert_eq!(store.summaries("session-db").unwrap(), vec!["Listing files".to_string()]);
+    }
+
+    #[test]
+    fn test_load_full_messages_round_trip() {
+        let conn = setup_db();
+
+        let mut message = claude_message("m1", None, serde_json::json!([
+            {"type": "text", "text": "Listing"},
+            {"type": "tool_use", "id": "toolu_1", "name": "Bash", "input": {"command": "ls"}}
+        ]));
+        message.git_branch = Some("main".to_string());
+        message.timestamp = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        message.extra.insert("requestId".to_string(), serde_json::json!("req_1"));
+        message.is_sidechain = true;
+        message.message.id = Some("msg_1".to_string());
+        message.message.model = Some("model-1".to_string());
+        message.message.extra.insert("stop_reason".to_string(), serde_json::json!("tool_use"));
+        message.message.extra.insert("stop_sequence".to_string(), serde_json::Value::Null);
+        message.message.extra.insert("usage".to_string(), serde_json::json!({"input_tokens": 3, "output_tokens": 5}));
+        import_messages(&conn, [&message], "/test/project");
+
+        assert_eq!(SessionStore::new(&conn).load_full_messages("session-db").unwrap(), vec![message]);
+    }
+
+    #[test]
+    fn test_load_when_not_connected() {
+        let conn = RealDuckDBConnection::new(ConnectionConfig::default());
+
+        let store = SessionStore::new(&conn);
+        assert!(store.load_thread("session-1").is_err());
+    }
+}
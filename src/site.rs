@@ -0,0 +1,405 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use crate::export::{capitalize, escape_html, has_text, is_meta, tool_names, ExportRow, SessionExport};
+use crate::jsonl_parser::{ClaudeMessage, ContentBlock};
+use crate::search::SearchQuery;
+use crate::store::VaultStore;
+
+// Bundled into the binary so the site needs nothing from the network
+const STYLE_CSS: &str = include_str!("site/style.css");
+const HIGHLIGHT_JS: &str = include_str!("site/highlight.js");
+const SEARCH_JS: &str = include_str!("site/search.js");
+
+/// What `build_site` wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SiteStats {
+    pub projects: usize,
+    pub sessions: usize,
+    pub messages: usize,
+}
+
+/// The file `search.js` loads: every message shown on a session page.
+#[derive(Debug, Serialize)]
+struct SearchIndex {
+    sessions: Vec<IndexedSession>,
+    messages: Vec<IndexedMessage>,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexedSession {
+    id: String,
+    title: String,
+    project: String,
+    /// Relative to the site root
+    page: String,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexedMessage {
+    /// Position in `SearchIndex::sessions`
+    session: usize,
+    uuid: String,
+    role: String,
+    timestamp: String,
+    text: String,
+}
+
+/// A session as listed on the index page.
+struct SessionEntry {
+    export: SessionExport,
+    title: String,
+    page: String,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+    messages: usize,
+}
+
+/// Writes a self-contained static site into `out`: `index.html` listing every
+/// project's sessions, one page per session under `sessions/`, the stylesheet and
+/// scripts under `assets/`, and `search-index.json` for client-side search.
+/// Session pages show the branch that was continued last, like the markdown export.
+/// Building again overwrites the files in place.
+pub fn build_site(store: &dyn VaultStore, out: &Path) -> Result<SiteStats> {
+    let rows = store.export_rows(&SearchQuery { limit: None, ..Default::default() })?;
+    let mut by_session: BTreeMap<&str, Vec<&ExportRow>> = BTreeMap::new();
+    for row in &rows {
+        by_session.entry(row.session_id.as_str()).or_default().push(row);
+    }
+
+    for dir in [out.to_path_buf(), out.join("sessions"), out.join("assets")] {
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    write_file(&out.join("assets/style.css"), STYLE_CSS)?;
+    write_file(&out.join("assets/highlight.js"), HIGHLIGHT_JS)?;
+    write_file(&out.join("assets/search.js"), SEARCH_JS)?;
+
+    // Rows are oldest first, so each session's rows are too
+    let mut projects: BTreeMap<String, Vec<SessionEntry>> = BTreeMap::new();
+    for (session_id, session_rows) in &by_session {
+        let export = SessionExport::load(store, session_id, None)?;
+        let entry = SessionEntry {
+            title: export.title(),
+            page: format!("sessions/{}.html", page_name(session_id)),
+            first: session_rows[0].timestamp,
+            last: session_rows[session_rows.len() - 1].timestamp,
+            messages: session_rows.len(),
+            export,
+        };
+        write_file(&out.join(&entry.page), &render_session_page(&entry))?;
+        projects.entry(session_rows[0].project_path.clone()).or_default().push(entry);
+    }
+    for sessions in projects.values_mut() {
+        sessions.sort_by(|a, b| b.last.cmp(&a.last).then_with(|| a.page.cmp(&b.page)));
+    }
+
+    write_file(&out.join("index.html"), &render_index_page(&projects))?;
+    let index = search_index(&projects);
+    write_file(&out.join("search-index.json"), &serde_json::to_string(&index)?)?;
+
+    Ok(SiteStats {
+        projects: projects.len(),
+        sessions: by_session.len(),
+        messages: rows.len(),
+    })
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Session IDs are UUIDs, but anything else is kept out of file names.
+fn page_name(session_id: &str) -> String {
+    session_id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+fn search_index(projects: &BTreeMap<String, Vec<SessionEntry>>) -> SearchIndex {
+    let mut index = SearchIndex { sessions: Vec::new(), messages: Vec::new() };
+    for (project, sessions) in projects {
+        for entry in sessions {
+            let session = index.sessions.len();
+            index.sessions.push(IndexedSession {
+                id: entry.export.session_id.clone(),
+                title: entry.title.clone(),
+                project: project.clone(),
+                page: entry.page.clone(),
+            });
+            let messages = entry.export.messages.iter()
+                .filter(|m| !is_meta(m))
+                .filter_map(|m| Some((m, m.message.plain_text()?)))
+                .map(|(m, text)| IndexedMessage {
+                    session,
+                    uuid: m.uuid.clone(),
+                    role: role(m).to_string(),
+                    timestamp: m.timestamp.format("%Y-%m-%d %H:%M").to_string(),
+                    text,
+                });
+            index.messages.extend(messages);
+        }
+    }
+    index
+}
+
+fn role(message: &ClaudeMessage) -> &str {
+    message.message.role.as_deref().unwrap_or(&message.message_type)
+}
+
+/// The shared page skeleton; `root` is the relative path back to the site root.
+fn page(title: &str, root: &str, body: &str, scripts: &[&str]) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<link rel=\"stylesheet\" href=\"{}assets/style.css\">\n</head>\n<body>\n\
+         <header><a href=\"{}index.html\">cc-vault</a></header>\n<main>\n{}</main>\n",
+        escape_html(title), root, root, body
+    );
+    for script in scripts {
+        let _ = writeln!(html, "<script src=\"{}assets/{}\"></script>", root, script);
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn render_index_page(projects: &BTreeMap<String, Vec<SessionEntry>>) -> String {
+    let mut body = String::from(
+        "<h1>Claude Code vault</h1>\n\
+         <input id=\"search\" type=\"search\" placeholder=\"Search messages…\" disabled>\n\
+         <div id=\"results\"></div>\n",
+    );
+    for (project, sessions) in projects {
+        let _ = writeln!(body, "<section>\n<h2>{}</h2>\n<ul class=\"sessions\">", escape_html(project));
+        for entry in sessions {
+            let _ = writeln!(
+                body,
+                "<li><a href=\"{}\">{}</a> <span class=\"meta\">{} – {} · {} messages</span></li>",
+                entry.page,
+                escape_html(&entry.title),
+                entry.first.format("%Y-%m-%d %H:%M"),
+                entry.last.format("%Y-%m-%d %H:%M"),
+                entry.messages
+            );
+        }
+        body.push_str("</ul>\n</section>\n");
+    }
+    page("Claude Code vault", "", &body, &["search.js"])
+}
+
+fn render_session_page(entry: &SessionEntry) -> String {
+    let export = &entry.export;
+    let mut body = String::new();
+    let _ = writeln!(body, "<h1>{}</h1>\n<p class=\"meta\">", escape_html(&entry.title));
+    let _ = writeln!(body, "Project <code>{}</code><br>", escape_html(&export.project_path));
+    let _ = writeln!(body, "Session <code>{}</code><br>", escape_html(&export.session_id));
+    if let Some(branch) = export.messages.iter().rev().find_map(|m| m.git_branch.as_deref()) {
+        let _ = writeln!(body, "Branch <code>{}</code><br>", escape_html(branch));
+    }
+    let _ = writeln!(
+        body,
+        "{} – {} UTC",
+        entry.first.format("%Y-%m-%d %H:%M:%S"),
+        entry.last.format("%Y-%m-%d %H:%M:%S")
+    );
+    if export.skipped_messages > 0 {
+        let _ = write!(body, "<br>{} messages on other branches are not shown.", export.skipped_messages);
+    }
+    body.push_str("</p>\n");
+
+    let tool_names = tool_names(&export.messages);
+    for message in export.messages.iter().filter(|m| !is_meta(m)) {
+        render_message(&mut body, message, &tool_names);
+    }
+    page(&entry.title, "../", &body, &["highlight.js"])
+}
+
+fn render_message(out: &mut String, message: &ClaudeMessage, tool_names: &std::collections::HashMap<String, String>) {
+    let role = role(message);
+    // Tool traffic without prose is shown compactly, without the role line
+    let prose = has_text(message);
+    let _ = writeln!(
+        out,
+        "<article class=\"message {}{}\" id=\"{}\">",
+        page_name(role),
+        if prose { "" } else { " tools" },
+        page_name(&message.uuid)
+    );
+    if prose {
+        let _ = writeln!(
+            out,
+            "<div class=\"meta\">{} · {}</div>",
+            escape_html(&capitalize(role)),
+            message.timestamp.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+
+    for block in message.message.blocks() {
+        match block {
+            ContentBlock::Text { text } if text.trim().is_empty() => {}
+            ContentBlock::Text { text } => out.push_str(&markdown_html(&text)),
+            ContentBlock::Thinking { thinking } => details(out, "thinking", "Thinking", &markdown_html(&thinking)),
+            ContentBlock::ToolUse { name, input, .. } => {
+                let input = serde_json::to_string_pretty(&input).unwrap_or_default();
+                details(out, "tool", &format!("Tool: {}", name), &code_block(&input, "json"));
+            }
+            ContentBlock::ToolResult { tool_use_id, content, is_error } => {
+                let name = tool_names.get(&tool_use_id).map(String::as_str).unwrap_or("tool");
+                let (class, status) = if is_error { ("result error", " (error)") } else { ("result", "") };
+                details(out, class, &format!("Result: {}{}", name, status), &code_block(&content, ""));
+            }
+            ContentBlock::Image { .. } => out.push_str("<p class=\"meta\">[image]</p>\n"),
+            ContentBlock::Other { block_type, raw } => {
+                let raw = serde_json::to_string_pretty(&raw).unwrap_or_default();
+                details(out, "other", &block_type, &code_block(&raw, "json"));
+            }
+        }
+    }
+    out.push_str("</article>\n");
+}
+
+fn details(out: &mut String, class: &str, summary: &str, body: &str) {
+    let _ = writeln!(
+        out,
+        "<details class=\"{}\"><summary>{}</summary>\n{}</details>",
+        class,
+        escape_html(summary),
+        body
+    );
+}
+
+fn code_block(code: &str, language: &str) -> String {
+    // The language ends up in a class attribute, so only plain names are kept
+    let language: String = language.chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '#'))
+        .collect();
+    let class = if language.is_empty() { String::new() } else { format!(" class=\"language-{}\"", language) };
+    format!("<pre><code{}>{}</code></pre>\n", class, escape_html(code.trim_end_matches('\n')))
+}
+
+/// Just enough Markdown for chat messages: fenced code blocks, paragraphs,
+/// line breaks and inline code. Everything else is shown as written.
+fn markdown_html(text: &str) -> String {
+    let mut out = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        let fence = trimmed.chars().take_while(|&c| c == '`').count();
+        if fence >= 3 {
+            flush_paragraph(&mut out, &mut paragraph);
+            let language = trimmed[fence..].split_whitespace().next().unwrap_or_default();
+            // A closing fence is a line of at least as many backticks
+            let code: Vec<&str> = lines.by_ref()
+                .take_while(|line| {
+                    let line = line.trim();
+                    !(line.len() >= fence && line.chars().all(|c| c == '`'))
+                })
+                .collect();
+            out.push_str(&code_block(&code.join("\n"), language));
+        } else if trimmed.is_empty() {
+            flush_paragraph(&mut out, &mut paragraph);
+        } else {
+            paragraph.push(line);
+        }
+    }
+    flush_paragraph(&mut out, &mut paragraph);
+    out
+}
+
+fn flush_paragraph(out: &mut String, paragraph: &mut Vec<&str>) {
+    if paragraph.is_empty() {
+        return;
+    }
+    let text = paragraph.join("\n");
+    paragraph.clear();
+
+    // Inline code needs a closing backtick; an odd one out is shown as is
+    let parts: Vec<&str> = text.split('`').collect();
+    let html = if parts.len() % 2 == 1 {
+        parts.iter()
+            .enumerate()
+            .map(|(i, part)| if i % 2 == 1 { format!("<code>{}</code>", escape_html(part)) } else { escape_html(part) })
+            .collect::<String>()
+    } else {
+        escape_html(&text)
+    };
+    let _ = writeln!(out, "<p>{}</p>", html.replace('\n', "<br>\n"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonl_parser::JsonlParser;
+    use crate::memory_store::MemoryStore;
+    use crate::test_support::line;
+
+    #[test]
+    fn test_markdown_html() {
+        assert_eq!(markdown_html("Use `Result<T>`\nand ?.\n\nDone"), "<p>Use <code>Result&lt;T&gt;</code><br>\nand ?.</p>\n<p>Done</p>\n");
+        assert_eq!(markdown_html("odd ` tick"), "<p>odd ` tick</p>\n");
+        assert_eq!(
+            markdown_html("Run:\n````rust extra\nlet x = \"<a>\";\n```\n````\nafter"),
+            "<p>Run:</p>\n<pre><code class=\"language-rust\">let x = \"&lt;a&gt;\";\n```</code></pre>\n<p>after</p>\n"
+        );
+        assert_eq!(code_block("x", "js\" onload=\"alert(1)"), "<pre><code class=\"language-js\">x</code></pre>\n");
+    }
+
+    #[test]
+    fn test_build_site() {
+        let store = MemoryStore::new();
+        let parser = JsonlParser::new();
+        let lines = [
+            line("u1", None, "s1", 0, "user", "Why does <the> vault lock?".into()),
+            line("a1", Some("u1"), "s1", 1, "assistant", serde_json::json!([
+                {"type": "text", "text": "Let me check."},
+                {"type": "tool_use", "id": "toolu_1", "name": "Bash", "input": {"command": "lsof vault.db"}},
+            ])),
+            line("r1", Some("a1"), "s1", 2, "user", serde_json::json!([
+                {"type": "tool_result", "tool_use_id": "toolu_1", "content": "duckdb 123", "is_error": true},
+            ])),
+            line("u2", None, "s2", 5, "user", "Second session".into()),
+        ];
+        let mut records = lines.iter().map(|l| parser.parse_record(l));
+        store.upsert_messages(&mut records, "/work/app", false).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let stats = build_site(&store, dir.path()).unwrap();
+        assert_eq!(stats, SiteStats { projects: 1, sessions: 2, messages: 4 });
+
+        let index = std::fs::read_to_string(dir.path().join("index.html")).unwrap();
+        assert!(index.contains("<h2>/work/app</h2>"));
+        // Newest session first, titled by its first prompt
+        let s2 = index.find("<a href=\"sessions/s2.html\">Second session</a>").unwrap();
+        let s1 = index.find("<a href=\"sessions/s1.html\">Why does &lt;the&gt; vault lock?</a>").unwrap();
+        assert!(s2 < s1);
+        assert!(index.contains("<script src=\"assets/search.js\"></script>"));
+
+        let session = std::fs::read_to_string(dir.path().join("sessions/s1.html")).unwrap();
+        assert!(session.contains("<link rel=\"stylesheet\" href=\"../assets/style.css\">"));
+        assert!(session.contains("<article class=\"message user\" id=\"u1\">"));
+        assert!(session.contains("<details class=\"tool\"><summary>Tool: Bash</summary>"));
+        assert!(session.contains("<article class=\"message user tools\" id=\"r1\">\n<details class=\"result error\"><summary>Result: Bash (error)</summary>"));
+
+        let search: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(dir.path().join("search-index.json")).unwrap()
+        ).unwrap();
+        assert_eq!(search["sessions"][1]["page"], "sessions/s1.html");
+        // Tool traffic has no prose to index
+        assert_eq!(search["messages"].as_array().unwrap().len(), 3);
+        assert_eq!(search["messages"][1]["text"], "Why does <the> vault lock?");
+
+        // Everything the pages load is local
+        for asset in ["style.css", "highlight.js", "search.js"] {
+            let contents = std::fs::read_to_string(dir.path().join("assets").join(asset)).unwrap();
+            assert!(!contents.contains("http://") && !contents.contains("https://"), "{} loads remote content", asset);
+        }
+        assert!(!index.contains("http") && !session.contains("http"));
+
+        // Building again overwrites in place
+        build_site(&store, dir.path()).unwrap();
+        assert_eq!(std::fs::read_dir(dir.path().join("sessions")).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,53 @@
+// Syntax highlighting for the code blocks of `cc-vault site` pages: comments,
+// strings, numbers and common keywords, the same rules for every language.
+(function () {
+  var KEYWORDS = [
+    'as', 'async', 'await', 'break', 'case', 'catch', 'class', 'const', 'continue',
+    'def', 'do', 'elif', 'else', 'enum', 'export', 'false', 'fi', 'fn', 'for',
+    'from', 'func', 'function', 'if', 'impl', 'import', 'in', 'let', 'match', 'mod',
+    'mut', 'new', 'None', 'null', 'pub', 'return', 'self', 'static', 'struct',
+    'switch', 'then', 'this', 'throw', 'trait', 'True', 'False', 'true', 'try',
+    'type', 'use', 'var', 'while', 'with', 'yield'
+  ];
+  // Languages where `#` starts a comment rather than an attribute or heading
+  var HASH_COMMENTS = ['bash', 'sh', 'shell', 'zsh', 'python', 'py', 'ruby', 'rb', 'yaml', 'yml', 'toml', 'dockerfile', 'makefile'];
+
+  function pattern(language) {
+    var comment = '\\/\\/[^\\n]*|\\/\\*[\\s\\S]*?\\*\\/';
+    if (HASH_COMMENTS.indexOf(language) !== -1) {
+      comment += '|#[^\\n]*';
+    }
+    var string = '"(?:[^"\\\\\\n]|\\\\.)*"|`(?:[^`\\\\]|\\\\.)*`';
+    // In Rust a single quote usually starts a lifetime
+    if (language !== 'rust' && language !== 'rs') {
+      string += "|'(?:[^'\\\\\\n]|\\\\.)*'";
+    }
+    return new RegExp(
+      '(' + comment + ')|(' + string + ')|(\\b\\d[\\d_]*(?:\\.\\d+)?\\b)|(\\b(?:' + KEYWORDS.join('|') + ')\\b)',
+      'g'
+    );
+  }
+
+  function escapeHtml(text) {
+    return text.replace(/&/g, '&amp;').replace(/</g, '&lt;').replace(/>/g, '&gt;');
+  }
+
+  function highlight(code, language) {
+    var token = pattern(language);
+    var out = '';
+    var last = 0;
+    var match;
+    while ((match = token.exec(code)) !== null) {
+      var kind = match[1] ? 'comment' : match[2] ? 'string' : match[3] ? 'number' : 'keyword';
+      out += escapeHtml(code.slice(last, match.index));
+      out += '<span class="hl-' + kind + '">' + escapeHtml(match[0]) + '</span>';
+      last = token.lastIndex;
+    }
+    return out + escapeHtml(code.slice(last));
+  }
+
+  document.querySelectorAll('pre code').forEach(function (block) {
+    var language = /language-(\S+)/.exec(block.className);
+    block.innerHTML = highlight(block.textContent, language ? language[1].toLowerCase() : '');
+  });
+})();
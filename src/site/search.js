@@ -0,0 +1,66 @@
+// Searches search-index.json, written next to index.html by `cc-vault site`.
+// A message matches when it contains every term, ignoring case.
+(function () {
+  var MAX_RESULTS = 50;
+  var input = document.getElementById('search');
+  var results = document.getElementById('results');
+  var index = null;
+
+  function excerpt(message, term) {
+    var at = Math.max(0, message.lower.indexOf(term));
+    var start = Math.max(0, at - 60);
+    var text = message.text.substr(start, 240).replace(/\s+/g, ' ');
+    return (start > 0 ? '… ' : '') + text + (start + 240 < message.text.length ? ' …' : '');
+  }
+
+  function search() {
+    var terms = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+    results.textContent = '';
+    if (!index || terms.length === 0) {
+      return;
+    }
+
+    var hits = index.messages.filter(function (message) {
+      return terms.every(function (term) { return message.lower.indexOf(term) !== -1; });
+    });
+    var count = document.createElement('p');
+    count.className = 'meta';
+    count.textContent = hits.length === 1 ? '1 message' : hits.length + ' messages';
+    if (hits.length > MAX_RESULTS) {
+      count.textContent += ', showing the first ' + MAX_RESULTS;
+    }
+
+    var list = document.createElement('ol');
+    hits.slice(0, MAX_RESULTS).forEach(function (message) {
+      var session = index.sessions[message.session];
+      var link = document.createElement('a');
+      link.href = session.page + '#' + message.uuid;
+      link.textContent = session.title;
+      var meta = document.createElement('span');
+      meta.className = 'meta';
+      meta.textContent = ' ' + message.role + ' · ' + message.timestamp + ' · ' + session.project;
+      var snippet = document.createElement('p');
+      snippet.textContent = excerpt(message, terms[0]);
+      var item = document.createElement('li');
+      item.append(link, meta, snippet);
+      list.appendChild(item);
+    });
+    results.append(count, list);
+  }
+
+  fetch('search-index.json')
+    .then(function (response) { return response.json(); })
+    .then(function (data) {
+      data.messages.forEach(function (message) { message.lower = message.text.toLowerCase(); });
+      index = data;
+      input.disabled = false;
+      input.focus();
+      search();
+    })
+    .catch(function () {
+      // Browsers refuse to fetch files from file:// pages
+      results.textContent = 'Search needs the site to be served over HTTP, e.g. with `python3 -m http.server` in this folder.';
+    });
+
+  input.addEventListener('input', search);
+})();
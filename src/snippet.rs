@@ -0,0 +1,230 @@
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+use crate::search::SearchMode;
+
+/// Characters of context kept around the best match.
+pub const DEFAULT_SNIPPET_CHARS: usize = 100;
+
+/// A short excerpt of a message around its best match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    /// Excerpt with line breaks folded into spaces
+    pub text: String,
+    /// Byte ranges of every match inside `text`, sorted and non-overlapping.
+    /// They always fall on UTF-8 character boundaries.
+    pub matches: Vec<Range<usize>>,
+    /// Whether content was cut before or after the excerpt
+    pub truncated_start: bool,
+    pub truncated_end: bool,
+}
+
+impl Snippet {
+    /// Builds a snippet of about `max_chars` characters around the part of
+    /// `content` with the most matches. Keywords match case-insensitively;
+    /// in regex mode every pattern is used as given.
+    pub fn generate(content: &str, keywords: &[String], mode: &SearchMode, max_chars: usize) -> Self {
+        let matches = find_matches(content, keywords, mode);
+        let chars: Vec<(usize, char)> = content.char_indices().collect();
+        let max_chars = max_chars.max(1);
+
+        if chars.len() <= max_chars {
+            return Self::from_window(content, 0, content.len(), &matches, false, false);
+        }
+
+        // Start a quarter of the window before the densest cluster of matches
+        let start_char = match best_match(&matches, content, max_chars) {
+            Some(byte) => {
+                let match_char = chars.partition_point(|&(i, _)| i < byte);
+                match_char.saturating_sub(max_chars / 4).min(chars.len() - max_chars)
+            }
+            None => 0,
+        };
+        let end_char = start_char + max_chars;
+
+        let start = chars[start_char].0;
+        let end = chars.get(end_char).map(|&(i, _)| i).unwrap_or(content.len());
+        Self::from_window(content, start, end, &matches, start > 0, end < content.len())
+    }
+
+    fn from_window(
+        content: &str,
+        start: usize,
+        end: usize,
+        matches: &[Range<usize>],
+        truncated_start: bool,
+        truncated_end: bool,
+    ) -> Self {
+        // Folding '\n'/'\r'/'\t' into ' ' keeps byte offsets unchanged
+        let text = content[start..end].replace(['\n', '\r', '\t'], " ");
+        let matches = matches.iter()
+            .filter(|m| m.start < end && m.end > start)
+            .map(|m| m.start.max(start) - start..m.end.min(end) - start)
+            .collect();
+
+        Self { text, matches, truncated_start, truncated_end }
+    }
+
+    /// Splits the excerpt into `(segment, is_match)` pieces for renderers.
+    pub fn segments(&self) -> Vec<(&str, bool)> {
+        let mut segments = Vec::new();
+        let mut position = 0;
+
+        for m in &self.matches {
+            if m.start > position {
+                segments.push((&self.text[position..m.start], false));
+            }
+            segments.push((&self.text[m.clone()], true));
+            position = m.end;
+        }
+        if position < self.text.len() {
+            segments.push((&self.text[position..], false));
+        }
+
+        segments
+    }
+
+    /// Renders the excerpt with ellipses, wrapping matches in `open`/`close`.
+    pub fn render(&self, open: &str, close: &str) -> String {
+        let mut out = String::new();
+        if self.truncated_start {
+            out.push('…');
+        }
+        for (segment, is_match) in self.segments() {
+            if is_match {
+                out.push_str(open);
+                out.push_str(segment);
+                out.push_str(close);
+            } else {
+                out.push_str(segment);
+            }
+        }
+        if self.truncated_end {
+            out.push('…');
+        }
+        out
+    }
+}
+
+fn build_patterns(keywords: &[String], mode: &SearchMode) -> Vec<Regex> {
+    keywords.iter()
+        .filter(|k| !k.is_empty())
+        .filter_map(|k| match mode {
+            SearchMode::Regex => Regex::new(k).ok(),
+            SearchMode::And | SearchMode::Or => RegexBuilder::new(&regex::escape(k))
+                .case_insensitive(true)
+                .build()
+                .ok(),
+        })
+        .collect()
+}
+
+/// All matches of all keywords, merged into sorted, non-overlapping byte ranges.
+pub fn find_matches(content: &str, keywords: &[String], mode: &SearchMode) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = build_patterns(keywords, mode)
+        .iter()
+        .flat_map(|re| re.find_iter(content).map(|m| m.range()).collect::<Vec<_>>())
+        .filter(|r| !r.is_empty())
+        .collect();
+    ranges.sort_by_key(|r| (r.start, r.end));
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Byte offset of the match that starts the window containing the most matches.
+fn best_match(matches: &[Range<usize>], content: &str, max_chars: usize) -> Option<usize> {
+    // Approximate the window in bytes from the average character width
+    let bytes_per_char = content.len().div_ceil(content.chars().count().max(1)).max(1);
+    let window = max_chars * bytes_per_char / 2;
+
+    matches.iter()
+        .enumerate()
+        .max_by_key(|&(i, m)| {
+            let covered = matches[i..].iter().take_while(|n| n.start < m.start + window).count();
+            // Prefer earlier matches on ties
+            (covered, std::cmp::Reverse(m.start))
+        })
+        .map(|(_, m)| m.start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keywords(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_short_content_is_kept_whole() {
+        let snippet = Snippet::generate("Rust error handling", &keywords(&["error"]), &SearchMode::And, 100);
+
+        assert_eq!(snippet.text, "Rust error handling");
+        assert_eq!(snippet.matches, vec![5..10]);
+        assert!(!snippet.truncated_start && !snippet.truncated_end);
+        assert_eq!(snippet.render("[", "]"), "Rust [error] handling");
+    }
+
+    #[test]
+    fn test_window_moves_to_match() {
+        let content = format!("{} needle {}", "a".repeat(200), "b".repeat(200));
+        let snippet = Snippet::generate(&content, &keywords(&["NEEDLE"]), &SearchMode::And, 40);
+
+        assert!(snippet.truncated_start && snippet.truncated_end);
+        assert_eq!(snippet.text.chars().count(), 40);
+        assert_eq!(snippet.matches.len(), 1);
+        assert_eq!(&snippet.text[snippet.matches[0].clone()], "needle");
+    }
+
+    #[test]
+    fn test_japanese_offsets_are_char_boundaries() {
+        let content = format!("{}エラーハンドリングについて{}", "あ".repeat(80), "い".repeat(80));
+        let snippet = Snippet::generate(&content, &keywords(&["ハンドリング"]), &SearchMode::And, 20);
+
+        assert_eq!(snippet.text.chars().count(), 20);
+        let m = snippet.matches[0].clone();
+        assert!(snippet.text.is_char_boundary(m.start) && snippet.text.is_char_boundary(m.end));
+        assert_eq!(&snippet.text[m], "ハンドリング");
+    }
+
+    #[test]
+    fn test_matches_cut_by_window_are_clipped() {
+        let content = format!("{}abcdef", "x".repeat(10));
+        let abcdef = 10..16;
+        let snippet = Snippet::from_window(&content, 0, 13, &[abcdef], false, true);
+
+        assert_eq!(snippet.text, "xxxxxxxxxxabc");
+        assert_eq!(snippet.matches, vec![10..13]);
+    }
+
+    #[test]
+    fn test_best_window_prefers_dense_matches() {
+        let content = format!("rust {} rust async rust {}", "x".repeat(300), "y".repeat(300));
+        let snippet = Snippet::generate(&content, &keywords(&["rust", "async"]), &SearchMode::Or, 60);
+
+        assert_eq!(snippet.matches.len(), 3);
+    }
+
+    #[test]
+    fn test_regex_mode_and_overlaps() {
+        let snippet = Snippet::generate("fn main() { fn_helper() }", &keywords(&[r"fn\s+\w+"]), &SearchMode::Regex, 100);
+        assert_eq!(snippet.matches, vec![0..7]);
+
+        let merged = find_matches("abcdef", &keywords(&["abc", "bcd"]), &SearchMode::Or);
+        assert_eq!(merged, vec![0..4]);
+    }
+
+    #[test]
+    fn test_newlines_are_folded() {
+        let snippet = Snippet::generate("line one\nline two", &keywords(&["two"]), &SearchMode::And, 100);
+
+        assert_eq!(snippet.text, "line one line two");
+        assert_eq!(snippet.segments(), vec![("line one line ", false), ("two", true)]);
+    }
+}
@@ -0,0 +1,534 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use crate::data_importer::ImportStats;
+use crate::db_connection::SqlValue;
+use crate::export::{ExportFormat, ExportRow};
+use crate::favorites::Favorite;
+use crate::jsonl_parser::{ClaudeMessage, JsonlRecord};
+use crate::notes::Note;
+use crate::raw_archive::RawSource;
+use crate::search::{ContextGroup, SearchQuery, SearchResult};
+use crate::session::SessionThread;
+use crate::sync::ImportState;
+use crate::tags::TagCount;
+use crate::tool_calls::{ToolCall, ToolCallQuery};
+
+/// What a favorite or tag points at. Both kinds are keyed by ids from the
+/// transcripts rather than the vault's row ids, so they survive `import --force`
+/// and rebuilding the database.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Target {
+    Message(String),
+    Session(String),
+}
+
+impl Target {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Target::Message(_) => "message",
+            Target::Session(_) => "session",
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        match self {
+            Target::Message(id) | Target::Session(id) => id,
+        }
+    }
+
+    pub(crate) fn key_params(&self) -> [SqlValue; 2] {
+        [SqlValue::Text(self.kind().to_string()), SqlValue::Text(self.id().to_string())]
+    }
+
+    pub(crate) fn from_parts(kind: &str, id: String) -> Result<Self> {
+        match kind {
+            "message" => Ok(Target::Message(id)),
+            "session" => Ok(Target::Session(id)),
+            other => Err(anyhow!("Unknown target kind: {}", other)),
+        }
+    }
+}
+
+/// How much the vault holds.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[allow(dead_code)]
+pub struct VaultStats {
+    pub messages: usize,
+    pub sessions: usize,
+    pub projects: usize,
+    pub favorites: usize,
+    pub summaries: usize,
+}
+
+/// Typed operations on the vault. Commands, the watcher and the TUI only go
+/// through this trait, so every backend has to give the same answers.
+pub trait VaultStore {
+    /// Inserts or updates a batch of records, typically one file, as one unit.
+    /// Only the last copy of a repeated uuid is stored, and messages identical
+    /// to the stored copy are skipped unless `force` is set.
+    fn upsert_messages(
+        &self,
+        records: &mut dyn Iterator<Item = Result<JsonlRecord>>,
+        project_path: &str,
+        force: bool,
+    ) -> Result<ImportStats>;
+
+    /// Brings the search index up to date after an import.
+    fn refresh_search_index(&self) -> Result<()>;
+
+    fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>>;
+
+    /// Every message `query` matches, oldest first. Unlike `search`, a query
+    /// without keywords or tags matches every message within its other filters.
+    fn export_rows(&self, query: &SearchQuery) -> Result<Vec<ExportRow>>;
+
+    /// Writes the rows `export_rows` returns to `path`, for the formats that
+    /// have `ExportFormat::copy_options`.
+    fn copy_rows(&self, query: &SearchQuery, format: ExportFormat, path: &Path) -> Result<()>;
+
+    /// Tool calls matching `query`, newest first.
+    fn find_tool_calls(&self, query: &ToolCallQuery) -> Result<Vec<ToolCall>>;
+
+    /// Adds a favorite, or updates its note. `None` keeps an existing note.
+    fn add_favorite(&self, target: &Target, note: Option<&str>) -> Result<()>;
+
+    /// Returns whether there was a favorite to remove.
+    fn remove_favorite(&self, target: &Target) -> Result<bool>;
+
+    /// Every favorite, newest first.
+    fn favorites(&self) -> Result<Vec<Favorite>>;
+
+    /// Tags `target` with an already normalized tag; tagging twice is a no-op.
+    fn add_tag(&self, target: &Target, tag: &str) -> Result<()>;
+
+    /// Returns whether the target carried the tag.
+    fn remove_tag(&self, target: &Target, tag: &str) -> Result<bool>;
+
+    /// Tags on exactly this target, in name order.
+    fn tags_of(&self, target: &Target) -> Result<Vec<String>>;
+
+    /// Every tag in use and how many messages and sessions carry it, in name order.
+    fn tag_counts(&self) -> Result<Vec<TagCount>>;
+
+    /// The note on exactly this target.
+    fn note(&self, target: &Target) -> Result<Option<Note>>;
+
+    /// Creates the note or replaces its text, and makes it searchable.
+    fn save_note(&self, target: &Target, body: &str) -> Result<()>;
+
+    /// Returns whether there was a note to remove.
+    fn remove_note(&self, target: &Target) -> Result<bool>;
+
+    /// Every note, most recently updated first.
+    fn notes(&self) -> Result<Vec<Note>>;
+
+    /// Resolves a session ID, a message UUID, or the numeric conversation ID
+    /// shown in search results. Returns the session ID and, for messages, the
+    /// message to highlight.
+    fn resolve_session(&self, id: &str) -> Result<Option<(String, Option<String>)>>;
+
+    fn load_session(&self, session_id: &str) -> Result<SessionThread>;
+
+    /// The session's messages with every field the vault keeps, oldest first.
+    fn session_messages(&self, session_id: &str) -> Result<Vec<ClaudeMessage>>;
+
+    /// Summary titles of the session, newest leaf first.
+    fn session_summaries(&self, session_id: &str) -> Result<Vec<String>>;
+
+    fn import_state(&self, source_path: &str) -> Result<Option<ImportState>>;
+
+    fn save_import_state(&self, state: &ImportState) -> Result<()>;
+
+    /// How many bytes of the source file the raw archive holds, `None` if none.
+    fn archived_len(&self, source_path: &str) -> Result<Option<u64>>;
+
+    /// Archives the lines read from `lines` as the file's bytes from `offset`
+    /// on, dropping anything archived after them. `offset` has to be where an
+    /// archived line starts or where the archive ends.
+    fn archive_raw(&self, source: &RawSource, offset: u64, lines: &mut dyn BufRead) -> Result<()>;
+
+    /// The file the session was last archived from.
+    fn raw_source(&self, session_id: &str) -> Result<Option<RawSource>>;
+
+    /// Writes the archived bytes of the file to `out`, returning how many there were.
+    fn write_raw_file(&self, source_path: &str, out: &mut dyn Write) -> Result<u64>;
+
+    #[allow(dead_code)]
+    fn stats(&self) -> Result<VaultStats>;
+
+    /// Runs `query` and attaches up to `before`/`after` neighbouring messages to each
+    /// hit; see `SessionThread::neighbors` for how neighbours are chosen.
+    fn search_with_context(&self, query: &SearchQuery, before: usize, after: usize) -> Result<Vec<ContextGroup>> {
+        let results = self.search(query)?;
+        let mut threads: HashMap<String, SessionThread> = HashMap::new();
+        let mut groups = Vec::with_capacity(results.len());
+
+        for hit in results {
+            if !threads.contains_key(&hit.session_id) {
+                let thread = self.load_session(&hit.session_id)?;
+                threads.insert(hit.session_id.clone(), thread);
+            }
+
+            let (preceding, following) = threads[&hit.session_id].neighbors(&hit.uuid, before, after);
+            groups.push(ContextGroup {
+                before: preceding.into_iter().cloned().collect(),
+                after: following.into_iter().cloned().collect(),
+                hit,
+            });
+        }
+
+        Ok(groups)
+    }
+}
+
+/// Rebuilds the search index after an import. Search still works without the
+/// index, only by substring, so a failure is reported and otherwise ignored.
+pub fn refresh_search_index(store: &dyn VaultStore) {
+    if let Err(e) = store.refresh_search_index() {
+        eprintln!("Warning: could not build full-text index, search will use substring matching: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duckdb_store::DuckDBStore;
+    use crate::jsonl_parser::JsonlParser;
+    use crate::memory_store::MemoryStore;
+    use crate::search::SearchMode;
+    use crate::tags::TagMode;
+    use crate::test_support::{line, raw_transcript, setup_db};
+    use serde_json::json;
+
+    fn records(lines: &[String]) -> Vec<Result<JsonlRecord>> {
+        let parser = JsonlParser::new();
+        lines.iter().map(|line| parser.parse_record(line)).collect()
+    }
+
+    fn upsert(store: &dyn VaultStore, lines: &[String], force: bool) -> ImportStats {
+        store.upsert_messages(&mut records(lines).into_iter(), "/test/project", force).unwrap()
+    }
+
+    /// A question, an answer with a tool call and the call's result in `s1`,
+    /// and one message in `s2`. The repeated m1, the summaries and the broken
+    /// line exercise the import.
+    fn fixture_lines() -> Vec<String> {
+        vec![
+            line("m1", None, "s1", 0, "assistant", "How do I handle errors in Rust?".into()),
+            line("m2", Some("m1"), "s1", 1, "assistant", json!([
+                {"type": "text", "text": "Use Result and the ? operator."},
+                {"type": "tool_use", "id": "toolu_1", "name": "Bash", "input": {"command": "cargo check"}},
+            ])),
+            line("m3", Some("m2"), "s1", 2, "assistant", json!([
+                {"type": "tool_result", "tool_use_id": "toolu_1", "content": "ok", "is_error": false},
+            ])),
+            line("m1", None, "s1", 0, "assistant", "How do I handle errors in Rust?".into()),
+            line("m4", None, "s2", 3, "assistant", "Another session entirely".into()),
+            r#"{"type":"summary","summary":"Error handling","leafUuid":"m3"}"#.to_string(),
+            r#"{"type":"summary","summary":"No leaf"}"#.to_string(),
+            "not json".to_string(),
+        ]
+    }
+
+    fn seed(store: &dyn VaultStore) {
+        upsert(store, &fixture_lines(), false);
+        // Building the FTS index needs the extension, which may not be available offline
+        let _ = store.refresh_search_index();
+    }
+
+    /// Runs `check` against every backend, which must all give the same answers.
+    fn check_each_store(check: impl Fn(&dyn VaultStore)) {
+        check(&MemoryStore::new());
+        let conn = setup_db();
+        check(&DuckDBStore::new(&conn));
+    }
+
+    fn sorted_uuids(results: Vec<SearchResult>) -> Vec<String> {
+        let mut uuids: Vec<String> = results.into_iter().map(|r| r.uuid).collect();
+        uuids.sort();
+        uuids
+    }
+
+    #[test]
+    fn test_upsert_messages() {
+        check_each_store(|store| {
+            // The repeated m1 is stored once
+            let lines = fixture_lines();
+            assert_eq!(upsert(store, &lines, false), ImportStats { inserted: 5, updated: 0, skipped: 2, errors: 1 });
+            assert_eq!(upsert(store, &lines, false), ImportStats { inserted: 0, updated: 0, skipped: 7, errors: 1 });
+            let edited = [line("m4", None, "s2", 3, "assistant", "Edited".into())];
+            assert_eq!(upsert(store, &edited, false), ImportStats { inserted: 0, updated: 1, skipped: 0, errors: 0 });
+            assert_eq!(upsert(store, &edited, true), ImportStats { inserted: 0, updated: 1, skipped: 0, errors: 0 });
+            assert_eq!(store.session_messages("s2").unwrap()[0].message.content, Some(json!("Edited")));
+        });
+    }
+
+    #[test]
+    fn test_stats() {
+        check_each_store(|store| {
+            assert_eq!(store.stats().unwrap(), VaultStats::default());
+            seed(store);
+            store.add_favorite(&Target::Session("s1".to_string()), None).unwrap();
+            assert_eq!(store.stats().unwrap(), VaultStats { messages: 4, sessions: 2, projects: 1, favorites: 1, summaries: 1 });
+        });
+    }
+
+    #[test]
+    fn test_search() {
+        check_each_store(|store| {
+            seed(store);
+
+            let query = SearchQuery { keywords: vec!["rust".to_string()], ..Default::default() };
+            let results = store.search(&query).unwrap();
+            assert_eq!(results.iter().map(|r| r.uuid.as_str()).collect::<Vec<_>>(), vec!["m1"]);
+            assert_eq!(results[0].message_content.as_deref(), Some("How do I handle errors in Rust?"));
+            assert!(results[0].snippet.is_some());
+
+            let query = SearchQuery {
+                keywords: vec!["handle".to_string(), "operator".to_string()],
+                mode: SearchMode::Or,
+                ..Default::default()
+            };
+            assert_eq!(sorted_uuids(store.search(&query).unwrap()), vec!["m1", "m2"]);
+
+            let groups = store.search_with_context(
+                &SearchQuery { keywords: vec!["operator".to_string()], ..Default::default() }, 1, 1,
+            ).unwrap();
+            assert_eq!(groups.len(), 1);
+            assert_eq!(groups[0].before[0].uuid, "m1");
+            assert_eq!(groups[0].after[0].uuid, "m3");
+        });
+    }
+
+    #[test]
+    fn test_favorites() {
+        check_each_store(|store| {
+            seed(store);
+
+            let m1 = Target::Message("m1".to_string());
+            store.add_favorite(&m1, Some("start here")).unwrap();
+            let favorites = SearchQuery { keywords: vec!["e".to_string()], favorites_only: Some(true), ..Default::default() };
+            assert_eq!(sorted_uuids(store.search(&favorites).unwrap()), vec!["m1"]);
+            let s2 = Target::Session("s2".to_string());
+            store.add_favorite(&s2, None).unwrap();
+            assert_eq!(sorted_uuids(store.search(&favorites).unwrap()), vec!["m1", "m4"]);
+            let all_favorites = SearchQuery { favorites_only: Some(true), ..Default::default() };
+            assert_eq!(sorted_uuids(store.search(&all_favorites).unwrap()), vec!["m1", "m4"]);
+            // Favorites are keyed by uuid, so a forced re-import keeps them
+            upsert(store, &fixture_lines(), true);
+            assert_eq!(store.search(&favorites).unwrap().len(), 2);
+            assert!(store.remove_favorite(&s2).unwrap());
+            assert!(!store.remove_favorite(&s2).unwrap());
+            let listed = store.favorites().unwrap();
+            assert_eq!(listed.iter().map(|f| (&f.target, f.note.as_deref())).collect::<Vec<_>>(), vec![(&m1, Some("start here"))]);
+        });
+    }
+
+    #[test]
+    fn test_tags() {
+        check_each_store(|store| {
+            seed(store);
+
+            let m1 = Target::Message("m1".to_string());
+            let s2 = Target::Session("s2".to_string());
+            store.add_tag(&m1, "snippet").unwrap();
+            store.add_tag(&m1, "postmortem").unwrap();
+            store.add_tag(&m1, "postmortem").unwrap();
+            store.add_tag(&s2, "postmortem").unwrap();
+            let tagged = |tags: &[&str], tag_mode: TagMode| {
+                let query = SearchQuery { tags: tags.iter().map(|t| t.to_string()).collect(), tag_mode, ..Default::default() };
+                sorted_uuids(store.search(&query).unwrap())
+            };
+            assert_eq!(tagged(&["snippet", "postmortem"], TagMode::All), vec!["m1"]);
+            assert_eq!(tagged(&["snippet", "postmortem"], TagMode::Any), vec!["m1", "m4"]);
+            assert!(tagged(&["missing"], TagMode::Any).is_empty());
+            let query = SearchQuery { keywords: vec!["rust".to_string()], tags: vec!["postmortem".to_string()], ..Default::default() };
+            let results = store.search(&query).unwrap();
+            assert_eq!(results.iter().map(|r| (r.uuid.as_str(), r.tags.clone())).collect::<Vec<_>>(), vec![
+                ("m1", vec!["postmortem".to_string(), "snippet".to_string()]),
+            ]);
+            assert_eq!(store.tags_of(&m1).unwrap(), vec!["postmortem", "snippet"]);
+            assert!(store.remove_tag(&m1, "snippet").unwrap());
+            assert!(!store.remove_tag(&m1, "snippet").unwrap());
+            assert_eq!(store.tag_counts().unwrap(), vec![TagCount { tag: "postmortem".to_string(), count: 2 }]);
+        });
+    }
+
+    #[test]
+    fn test_notes() {
+        check_each_store(|store| {
+            seed(store);
+
+            let s1 = Target::Session("s1".to_string());
+            store.save_note(&s1, "How we fixed the lock issue").unwrap();
+            store.save_note(&s1, "How we fixed the DuckDB lock issue").unwrap();
+            store.save_note(&Target::Message("m4".to_string()), "lock contention again").unwrap();
+            assert_eq!(store.note(&s1).unwrap().unwrap().body, "How we fixed the DuckDB lock issue");
+            let noted = |keywords: &[&str], mode: SearchMode| {
+                let query = SearchQuery { keywords: keywords.iter().map(|k| k.to_string()).collect(), mode, ..Default::default() };
+                let mut found: Vec<(String, Vec<String>)> = store.search(&query).unwrap().into_iter().map(|r| (r.uuid, r.notes)).collect();
+                found.sort();
+                found
+            };
+            let s1_note = vec!["How we fixed the DuckDB lock issue".to_string()];
+            assert_eq!(noted(&["duckdb", "lock"], SearchMode::And), vec![
+                ("m1".to_string(), s1_note.clone()),
+                ("m2".to_string(), s1_note.clone()),
+                ("m3".to_string(), s1_note.clone()),
+            ]);
+            assert_eq!(noted(&["contention", "operator"], SearchMode::Or).iter().map(|(uuid, _)| uuid.as_str()).collect::<Vec<_>>(), vec!["m2", "m4"]);
+            assert_eq!(noted(&[r"Duck\w+"], SearchMode::Regex).len(), 3);
+            assert!(store.remove_note(&s1).unwrap());
+            assert!(!store.remove_note(&s1).unwrap());
+            assert!(noted(&["duckdb"], SearchMode::And).is_empty());
+            assert_eq!(store.notes().unwrap().iter().map(|n| n.body.as_str()).collect::<Vec<_>>(), vec!["lock contention again"]);
+        });
+    }
+
+    #[test]
+    fn test_sessions() {
+        check_each_store(|store| {
+            seed(store);
+
+            assert_eq!(store.resolve_session("s1").unwrap(), Some(("s1".to_string(), None)));
+            assert_eq!(store.resolve_session("m4").unwrap(), Some(("s2".to_string(), Some("m4".to_string()))));
+            let results = store.search(&SearchQuery { keywords: vec!["rust".to_string()], ..Default::default() }).unwrap();
+            let id = results[0].id.to_string();
+            assert_eq!(store.resolve_session(&id).unwrap(), Some(("s1".to_string(), Some("m1".to_string()))));
+            assert_eq!(store.resolve_session("missing").unwrap(), None);
+
+            let thread = store.load_session("s1").unwrap();
+            assert_eq!(thread.path_through("m1").iter().map(|m| m.uuid.as_str()).collect::<Vec<_>>(), vec!["m1", "m2", "m3"]);
+            assert_eq!(thread.get("m2").unwrap().block_summary.as_deref(), Some("tool_use: Bash"));
+            assert_eq!(thread.get("m2").unwrap().project_path, "/test/project");
+            assert_eq!(store.session_summaries("s1").unwrap(), vec!["Error handling".to_string()]);
+            assert!(store.session_summaries("s2").unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_tool_calls() {
+        check_each_store(|store| {
+            seed(store);
+
+            let calls = store.find_tool_calls(&ToolCallQuery { tool_name: Some("Bash".to_string()), ..Default::default() }).unwrap();
+            assert_eq!(calls.len(), 1);
+            assert_eq!(calls[0].input_json.as_deref(), Some(r#"{"command":"cargo check"}"#));
+            assert_eq!((calls[0].result_text.as_deref(), calls[0].is_error), (Some("ok"), Some(false)));
+
+            // An edited result that no longer answers the call takes the result away
+            let bash = ToolCallQuery { tool_name: Some("Bash".to_string()), ..Default::default() };
+            upsert(store, &[line("m3", Some("m2"), "s1", 2, "assistant", "No result any more".into())], false);
+            let calls = store.find_tool_calls(&bash).unwrap();
+            assert_eq!((calls.len(), calls[0].result_text.as_deref(), calls[0].is_error), (1, None, None));
+
+            // and an edited message without the call takes the call away
+            upsert(store, &[line("m2", Some("m1"), "s1", 1, "assistant", "No call any more".into())], false);
+            assert!(store.find_tool_calls(&bash).unwrap().is_empty());
+            assert!(store.find_tool_calls(&ToolCallQuery::default()).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_import_state() {
+        check_each_store(|store| {
+            assert_eq!(store.import_state("/a.jsonl").unwrap(), None);
+            let state = ImportState {
+                source_path: "/a.jsonl".to_string(),
+                project_path: "/test/project".to_string(),
+                file_size: 10,
+                modified_at: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+                byte_offset: 8,
+                last_uuid: Some("m4".to_string()),
+            };
+            store.save_import_state(&state).unwrap();
+            assert_eq!(store.import_state("/a.jsonl").unwrap(), Some(state));
+        });
+    }
+
+    #[test]
+    fn test_raw_archive() {
+        check_each_store(|store| {
+            let old = RawSource::new(Path::new("/old/a.jsonl"), "/test/project");
+            let moved = RawSource::new(Path::new("/moved/b.jsonl"), "/test/project");
+            let first = b"{\"sessionId\":\"s1\",\"uuid\":\"m1\"}\n";
+            assert_eq!(store.archived_len(&old.source_path).unwrap(), None);
+            store.archive_raw(&old, 0, &mut &first[..]).unwrap();
+            store.archive_raw(&old, 31, &mut &b"\n{\"uuid\":\"m2\"}\r\n"[..]).unwrap();
+            assert!(store.archive_raw(&old, 64, &mut &b"gap\n"[..]).is_err());
+            assert!(store.archive_raw(&old, 10, &mut &b"inside\n"[..]).is_err());
+            assert_eq!(store.archived_len(&old.source_path).unwrap(), Some(47));
+            assert_eq!(raw_transcript(store, "s1").unwrap(), [&first[..], b"\n{\"uuid\":\"m2\"}\r\n"].concat());
+            store.archive_raw(&moved, 0, &mut &first[..]).unwrap();
+            assert_eq!(store.raw_source("s1").unwrap(), Some(moved));
+            assert_eq!(raw_transcript(store, "s1").unwrap(), first.to_vec());
+            assert_eq!(store.raw_source("a").unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn test_export_rows() {
+        check_each_store(|store| {
+            seed(store);
+            let m1 = Target::Message("m1".to_string());
+            store.add_favorite(&m1, None).unwrap();
+            store.add_tag(&m1, "postmortem").unwrap();
+            store.add_tag(&Target::Session("s2".to_string()), "postmortem").unwrap();
+            store.save_note(&Target::Message("m4".to_string()), "lock contention again").unwrap();
+
+            let everything = SearchQuery { limit: None, ..Default::default() };
+            let rows = store.export_rows(&everything).unwrap();
+            assert_eq!(rows.iter().map(|r| r.uuid.as_str()).collect::<Vec<_>>(), vec!["m1", "m2", "m3", "m4"]);
+            assert_eq!(rows[1], ExportRow {
+                uuid: "m2".to_string(),
+                parent_uuid: Some("m1".to_string()),
+                session_id: "s1".to_string(),
+                project_path: "/test/project".to_string(),
+                timestamp: chrono::DateTime::from_timestamp(1_753_099_260, 0).unwrap(),
+                message_type: "assistant".to_string(),
+                role: Some("assistant".to_string()),
+                content: Some("Use Result and the ? operator.".to_string()),
+                git_branch: Some("main".to_string()),
+                version: "1.0.51".to_string(),
+                is_favorite: false,
+                tags: Vec::new(),
+                note: None,
+                session_note: None,
+            });
+            assert_eq!((rows[0].is_favorite, rows[0].tags.clone()), (true, vec!["postmortem".to_string()]));
+            assert_eq!((rows[3].note.as_deref(), rows[3].tags.clone()), (Some("lock contention again"), vec!["postmortem".to_string()]));
+            let rows = store.export_rows(&SearchQuery { keywords: vec!["rust".to_string()], limit: None, ..Default::default() }).unwrap();
+            assert_eq!(rows.iter().map(|r| r.uuid.as_str()).collect::<Vec<_>>(), vec!["m1"]);
+            let rows = store.export_rows(&SearchQuery { project_filter: Some("/other".to_string()), ..Default::default() }).unwrap();
+            assert!(rows.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_memory_store_regex_and_filters() {
+        let store = MemoryStore::new();
+        upsert(&store, &[
+            line("a", None, "s1", 0, "assistant", "fn main() { fn_helper() }".into()),
+            line("b", None, "s1", 5, "assistant", "fn other()".into()),
+        ], false);
+
+        let query = SearchQuery { keywords: vec![r"fn\s+\w+".to_string()], mode: SearchMode::Regex, ..Default::default() };
+        let results = store.search(&query).unwrap();
+        assert_eq!(results.iter().map(|r| r.uuid.as_str()).collect::<Vec<_>>(), vec!["b", "a"]);
+
+        let invalid = SearchQuery { keywords: vec!["fn(".to_string()], mode: SearchMode::Regex, ..Default::default() };
+        assert!(store.search(&invalid).unwrap_err().to_string().contains("Invalid regex pattern"));
+
+        let query = SearchQuery {
+            keywords: vec!["fn".to_string()],
+            date_to: Some(chrono::DateTime::parse_from_rfc3339("2025-07-21T12:01:00Z").unwrap().into()),
+            ..Default::default()
+        };
+        assert_eq!(store.search(&query).unwrap().iter().map(|r| r.uuid.as_str()).collect::<Vec<_>>(), vec!["a"]);
+
+        let query = SearchQuery { keywords: vec!["fn".to_string()], project_filter: Some("/other".to_string()), ..Default::default() };
+        assert!(store.search(&query).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,494 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use crate::data_importer::ImportStats;
+use crate::db_connection::SqlValue;
+use crate::jsonl_parser::JsonlParser;
+use crate::raw_archive;
+use crate::real_db_connection::ExtendedDatabaseConnection;
+use crate::store::VaultStore;
+
+/// Bytes read per step when scanning backwards for the start of a line.
+const TAIL_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// What the last sync saw of one source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportState {
+    pub source_path: String,
+    pub project_path: String,
+    pub file_size: u64,
+    pub modified_at: DateTime<Utc>,
+    /// End of the last complete line that was imported
+    pub byte_offset: u64,
+    /// UUID of the last message read before `byte_offset`
+    pub last_uuid: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    /// Size and mtime match the recorded state
+    Unchanged,
+    /// Only bytes after the recorded offset are read
+    Append,
+    /// The file is new, shrank or was rewritten, so it is read from the start
+    Rescan,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSyncReport {
+    pub mode: SyncMode,
+    /// Records that were new or differed from the stored copy
+    pub imported: usize,
+    pub errors: usize,
+}
+
+/// Persists `ImportState` rows in the DuckDB `import_state` table.
+pub struct ImportStateStore<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
+}
+
+impl<'a, C: ExtendedDatabaseConnection> ImportStateStore<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
+        Self { connection }
+    }
+
+    pub fn get(&self, source_path: &str) -> Result<Option<ImportState>> {
+        if !self.connection.is_connected() {
+            return Err(anyhow!("Database not connected"));
+        }
+
+        let query = "SELECT source_path, project_path, file_size, epoch_us(modified_at), byte_offset, last_uuid \
+                     FROM import_state WHERE source_path = ?";
+        self.connection.query_row(query, &[SqlValue::Text(source_path.to_string())], |row| {
+            let modified_us: i64 = row.get(3)?;
+            Ok(ImportState {
+                source_path: row.get(0)?,
+                project_path: row.get(1)?,
+                file_size: row.get::<_, i64>(2)? as u64,
+                modified_at: DateTime::from_timestamp_micros(modified_us)
+                    .ok_or_else(|| anyhow!("Invalid timestamp: {}", modified_us))?,
+                byte_offset: row.get::<_, i64>(4)? as u64,
+                last_uuid: row.get(5)?,
+            })
+        })
+    }
+
+    pub fn save(&self, state: &ImportState) -> Result<()> {
+        if !self.connection.is_connected() {
+            return Err(anyhow!("Database not connected"));
+        }
+
+        self.connection.execute_with_params(
+            "INSERT INTO import_state (source_path, project_path, file_size, modified_at, byte_offset, last_uuid) \
+             VALUES (?, ?, ?, make_timestamp(?), ?, ?) \
+             ON CONFLICT (source_path) DO UPDATE SET project_path = excluded.project_path, \
+             file_size = excluded.file_size, modified_at = excluded.modified_at, \
+             byte_offset = excluded.byte_offset, last_uuid = excluded.last_uuid, synced_at = now()",
+            &[
+                SqlValue::Text(state.source_path.clone()),
+                SqlValue::Text(state.project_path.clone()),
+                (state.file_size as i64).into(),
+                state.modified_at.timestamp_micros().into(),
+                (state.byte_offset as i64).into(),
+                state.last_uuid.clone().into(),
+            ],
+        )
+    }
+}
+
+/// Imports the parts of `.jsonl` files that changed since the last sync.
+pub struct Syncer<'a> {
+    store: &'a dyn VaultStore,
+    parser: JsonlParser,
+    raw_archive: bool,
+}
+
+impl<'a> Syncer<'a> {
+    pub fn new(store: &'a dyn VaultStore) -> Self {
+        Self {
+            store,
+            parser: JsonlParser::new(),
+            raw_archive: false,
+        }
+    }
+
+    /// Also keeps the imported lines byte for byte in the raw archive.
+    pub fn with_raw_archive(mut self, enabled: bool) -> Self {
+        self.raw_archive = enabled;
+        self
+    }
+
+    /// Imports the complete lines of `path` that were not imported before and
+    /// records how far the file has been read. A trailing line without a newline
+    /// is still being written and is left for the next sync.
+    pub fn sync_file(&self, path: &Path, project_name: &str) -> Result<FileSyncReport> {
+        let source_path = path.to_string_lossy().to_string();
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to read metadata of {}", source_path))?;
+        let modified_at = Self::truncate_to_micros(DateTime::<Utc>::from(metadata.modified()?));
+
+        let previous = self.store.import_state(&source_path)?;
+        let mode = Self::sync_mode(path, previous.as_ref(), metadata.len(), modified_at)?;
+        if mode == SyncMode::Unchanged {
+            // Files synced before archiving was turned on still need archiving
+            if let Some(state) = &previous {
+                self.archive(path, project_name, state.byte_offset, state.byte_offset)?;
+            }
+            return Ok(FileSyncReport { mode, imported: 0, errors: 0 });
+        }
+
+        let start = match (&previous, mode) {
+            (Some(state), SyncMode::Append) => state.byte_offset,
+            _ => 0,
+        };
+
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to open {}", source_path))?;
+        file.seek(SeekFrom::Start(start))?;
+
+        let mut last_uuid = previous.as_ref()
+            .filter(|_| mode == SyncMode::Append)
+            .and_then(|state| state.last_uuid.clone());
+
+        let mut stream = self.parser.stream(BufReader::new(file));
+        let mut records = stream.by_ref()
+            // A parsed line without its newline may not be finished; it is left
+            // for the next sync like any other incomplete line
+            .take_while(|line| !matches!(line, Ok(line) if !line.terminated))
+            .map(|line| {
+                let line = line?;
+                match &line.record {
+                    // Lines without a uuid (summaries) leave the next append unverified
+                    Ok(record) => last_uuid = record.uuid().map(str::to_string),
+                    Err(e) => {
+                        eprintln!("  Error parsing line {} (byte {}): {}", line.line_number, start + line.offset, e);
+                        // The tail check can only verify a line that parses
+                        last_uuid = None;
+                    }
+                }
+                line.record
+            });
+        let stats = self.store.upsert_messages(&mut records, project_name, false)?;
+        let report = FileSyncReport {
+            mode,
+            imported: stats.inserted + stats.updated,
+            errors: stats.errors,
+        };
+        self.archive(path, project_name, start, start + stream.complete_offset())?;
+
+        self.store.save_import_state(&ImportState {
+            source_path,
+            project_path: project_name.to_string(),
+            file_size: start + stream.position(),
+            modified_at,
+            byte_offset: start + stream.complete_offset(),
+            last_uuid,
+        })?;
+
+        Ok(report)
+    }
+
+    /// Imports every line of `path`, whatever was synced before, and records
+    /// the file as synced so the next sync only reads what is appended to it.
+    /// Unlike a sync, a trailing line without a newline is imported too.
+    pub fn import_file(&self, path: &Path, project_name: &str, force: bool) -> Result<ImportStats> {
+        let source_path = path.to_string_lossy().to_string();
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to read metadata of {}", source_path))?;
+        let modified_at = Self::truncate_to_micros(DateTime::<Utc>::from(metadata.modified()?));
+
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open {}", source_path))?;
+        let mut last_uuid = None;
+        let mut stream = self.parser.stream(BufReader::new(file));
+        let mut records = stream.by_ref()
+            .map(|line| {
+                let line = line?;
+                // The recorded offset ends at the last newline, so the uuid has to as well
+                if line.terminated {
+                    last_uuid = line.record.as_ref().ok().and_then(|record| record.uuid()).map(str::to_string);
+                }
+                if let Err(e) = &line.record {
+                    eprintln!("  Error parsing line {}: {}", line.line_number, e);
+                }
+                line.record
+            });
+        let stats = self.store.upsert_messages(&mut records, project_name, force)?;
+        self.archive(path, project_name, 0, stream.complete_offset())?;
+
+        self.store.save_import_state(&ImportState {
+            source_path,
+            project_path: project_name.to_string(),
+            file_size: stream.position(),
+            modified_at,
+            byte_offset: stream.complete_offset(),
+            last_uuid,
+        })?;
+
+        Ok(stats)
+    }
+
+    /// Brings the raw archive of `path` up to `end`. Bytes before `unchanged_to`
+    /// are the ones already synced, so only what follows them or the end of the
+    /// archive is copied.
+    fn archive(&self, path: &Path, project_name: &str, unchanged_to: u64, end: u64) -> Result<()> {
+        if !self.raw_archive {
+            return Ok(());
+        }
+
+        let archived = self.store.archived_len(&path.to_string_lossy())?;
+        if archived == Some(end) && unchanged_to == end {
+            return Ok(());
+        }
+        let from = archived.unwrap_or(0).min(unchanged_to);
+        raw_archive::archive_range(self.store, path, project_name, from, end)
+    }
+
+    /// Decides how much of the file has to be read again. Growth alone is not
+    /// enough for an append: the line before the recorded offset must still be
+    /// the one that was imported last, otherwise the file was rewritten.
+    fn sync_mode(path: &Path, previous: Option<&ImportState>, size: u64, modified_at: DateTime<Utc>) -> Result<SyncMode> {
+        let Some(state) = previous else {
+            return Ok(SyncMode::Rescan);
+        };
+
+        if size < state.byte_offset {
+            return Ok(SyncMode::Rescan);
+        }
+
+        if size == state.file_size && modified_at == state.modified_at {
+            return Ok(SyncMode::Unchanged);
+        }
+
+        let (Some(last_uuid), true) = (&state.last_uuid, state.byte_offset > 0) else {
+            return Ok(SyncMode::Append);
+        };
+
+        let mut file = File::open(path)?;
+        let tail_matches = Self::line_ending_at(&mut file, state.byte_offset)?
+            .and_then(|line| serde_json::from_slice::<serde_json::Value>(&line).ok())
+            .is_some_and(|value| value.get("uuid").and_then(|v| v.as_str()) == Some(last_uuid.as_str()));
+
+        Ok(if tail_matches { SyncMode::Append } else { SyncMode::Rescan })
+    }
+
+    /// The line terminated by the newline at `end - 1`, without that newline.
+    /// Returns `None` when there is no newline at that position.
+    fn line_ending_at(file: &mut File, end: u64) -> Result<Option<Vec<u8>>> {
+        if end == 0 {
+            return Ok(None);
+        }
+
+        let mut newline = [0u8; 1];
+        file.seek(SeekFrom::Start(end - 1))?;
+        file.read_exact(&mut newline)?;
+        if newline[0] != b'\n' {
+            return Ok(None);
+        }
+
+        // Collect chunks backwards until the previous newline or the start of the file
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+        let mut position = end - 1;
+        while position > 0 {
+            let chunk_start = position.saturating_sub(TAIL_CHUNK_SIZE);
+            let mut chunk = vec![0u8; (position - chunk_start) as usize];
+            file.seek(SeekFrom::Start(chunk_start))?;
+            file.read_exact(&mut chunk)?;
+
+            if let Some(i) = chunk.iter().rposition(|&b| b == b'\n') {
+                chunks.push(chunk.split_off(i + 1));
+                break;
+            }
+            chunks.push(chunk);
+            position = chunk_start;
+        }
+
+        Ok(Some(chunks.into_iter().rev().flatten().collect()))
+    }
+
+    fn truncate_to_micros(time: DateTime<Utc>) -> DateTime<Utc> {
+        DateTime::from_timestamp_micros(time.timestamp_micros()).unwrap_or(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{line, raw_transcript, setup_db};
+    use crate::real_db_connection::RealDuckDBConnection;
+    use crate::db_connection::ConnectionConfig;
+    use crate::duckdb_store::DuckDBStore;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// A complete line of `session-1`, newline included.
+    fn user_line(uuid: &str, text: &str) -> String {
+        line(uuid, None, "session-1", 0, "user", text.into()) + "\n"
+    }
+
+    fn count(conn: &RealDuckDBConnection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM conversations", &[], |row| Ok(row.get(0)?))
+            .unwrap()
+            .unwrap()
+    }
+
+    fn append(path: &Path, content: &str) {
+        let mut file = std::fs::OpenOptions::new().append(true).create(true).open(path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_sync_reads_only_appended_lines() {
+        let conn = setup_db();
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session.jsonl");
+        append(&path, &(user_line("uuid-1", "first") + &user_line("uuid-2", "second")));
+
+        let store = DuckDBStore::new(&conn);
+        let syncer = Syncer::new(&store);
+        let report = syncer.sync_file(&path, "project").unwrap();
+        assert_eq!(report, FileSyncReport { mode: SyncMode::Rescan, imported: 2, errors: 0 });
+
+        let report = syncer.sync_file(&path, "project").unwrap();
+        assert_eq!(report.mode, SyncMode::Unchanged);
+
+        append(&path, &user_line("uuid-3", "third"));
+        let report = syncer.sync_file(&path, "project").unwrap();
+        assert_eq!(report, FileSyncReport { mode: SyncMode::Append, imported: 1, errors: 0 });
+        assert_eq!(count(&conn), 3);
+
+        let state = ImportStateStore::new(&conn).get(&path.to_string_lossy()).unwrap().unwrap();
+        assert_eq!(state.byte_offset, std::fs::metadata(&path).unwrap().len());
+        assert_eq!(state.last_uuid.as_deref(), Some("uuid-3"));
+    }
+
+    #[test]
+    fn test_import_records_state_for_next_sync() {
+        let conn = setup_db();
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let last = user_line("uuid-3", "third");
+        append(&path, &(user_line("uuid-1", "first") + &user_line("uuid-2", "second") + last.trim_end()));
+
+        let store = DuckDBStore::new(&conn);
+        let syncer = Syncer::new(&store);
+        let stats = syncer.import_file(&path, "project", false).unwrap();
+        assert_eq!(stats.inserted, 3);
+
+        let state = ImportStateStore::new(&conn).get(&path.to_string_lossy()).unwrap().unwrap();
+        assert_eq!(state.file_size, std::fs::metadata(&path).unwrap().len());
+        assert_eq!(state.byte_offset, state.file_size - last.trim_end().len() as u64);
+        assert_eq!(state.last_uuid.as_deref(), Some("uuid-2"));
+        assert_eq!(syncer.sync_file(&path, "project").unwrap().mode, SyncMode::Unchanged);
+
+        // The unterminated line is read again once it is finished, and is already stored
+        append(&path, &("\n".to_string() + &user_line("uuid-4", "fourth")));
+        let report = syncer.sync_file(&path, "project").unwrap();
+        assert_eq!(report, FileSyncReport { mode: SyncMode::Append, imported: 1, errors: 0 });
+        assert_eq!(count(&conn), 4);
+    }
+
+    #[test]
+    fn test_partial_last_line_waits_for_newline() {
+        let conn = setup_db();
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let second = user_line("uuid-2", "second");
+        let (head, tail) = second.split_at(40);
+        append(&path, &(user_line("uuid-1", "first") + head));
+
+        let store = DuckDBStore::new(&conn);
+        let syncer = Syncer::new(&store);
+        let report = syncer.sync_file(&path, "project").unwrap();
+        assert_eq!(report, FileSyncReport { mode: SyncMode::Rescan, imported: 1, errors: 0 });
+
+        append(&path, tail);
+        let report = syncer.sync_file(&path, "project").unwrap();
+        assert_eq!(report, FileSyncReport { mode: SyncMode::Append, imported: 1, errors: 0 });
+        assert_eq!(count(&conn), 2);
+    }
+
+    #[test]
+    fn test_shrunk_or_rewritten_file_is_rescanned() {
+        let conn = setup_db();
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session.jsonl");
+        append(&path, &(user_line("uuid-1", "first") + &user_line("uuid-2", "second")));
+
+        let store = DuckDBStore::new(&conn);
+        let syncer = Syncer::new(&store);
+        syncer.sync_file(&path, "project").unwrap();
+
+        // Shrinking below the recorded offset
+        std::fs::write(&path, user_line("uuid-1", "first")).unwrap();
+        let report = syncer.sync_file(&path, "project").unwrap();
+        // The remaining line is already stored unchanged
+        assert_eq!(report, FileSyncReport { mode: SyncMode::Rescan, imported: 0, errors: 0 });
+
+        // Rewritten with different lines but a larger size
+        std::fs::write(&path, user_line("uuid-x", "replaced") + &user_line("uuid-y", "replaced too")).unwrap();
+        let report = syncer.sync_file(&path, "project").unwrap();
+        assert_eq!(report, FileSyncReport { mode: SyncMode::Rescan, imported: 2, errors: 0 });
+        assert_eq!(count(&conn), 4);
+    }
+
+    #[test]
+    fn test_raw_archive_restores_file_byte_for_byte() {
+        let conn = setup_db();
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session-1.jsonl");
+        let restored = |store: &DuckDBStore<RealDuckDBConnection>| raw_transcript(store, "session-1");
+        append(&path, &user_line("uuid-1", "first"));
+
+        // Files synced before archiving was turned on are archived on the next sync
+        let store = DuckDBStore::new(&conn);
+        Syncer::new(&store).sync_file(&path, "project").unwrap();
+        assert_eq!(restored(&store), None);
+        let syncer = Syncer::new(&store).with_raw_archive(true);
+        let report = syncer.sync_file(&path, "project").unwrap();
+        assert_eq!(report.mode, SyncMode::Unchanged);
+        assert_eq!(restored(&store), Some(std::fs::read(&path).unwrap()));
+
+        // Blank, unparseable and unfinished lines are kept as they are
+        let second = user_line("uuid-2", "second");
+        let (head, tail) = second.split_at(40);
+        append(&path, &format!("\r\n{{\"type\":\"unknown\",\"extra\":[1, 2]}}\nnot json\n{}", head));
+        syncer.sync_file(&path, "project").unwrap();
+        let complete = std::fs::read(&path).unwrap();
+        assert_eq!(restored(&store).unwrap(), complete[..complete.len() - head.len()]);
+        append(&path, tail);
+        syncer.sync_file(&path, "project").unwrap();
+        assert_eq!(restored(&store), Some(std::fs::read(&path).unwrap()));
+
+        // A rewritten file replaces its archive
+        std::fs::write(&path, user_line("uuid-x", "replaced")).unwrap();
+        let report = syncer.sync_file(&path, "project").unwrap();
+        assert_eq!(report.mode, SyncMode::Rescan);
+        assert_eq!(restored(&store), Some(std::fs::read(&path).unwrap()));
+    }
+
+    #[test]
+    fn test_line_ending_at_spans_chunks() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("long.jsonl");
+        let long = "x".repeat(TAIL_CHUNK_SIZE as usize * 2 + 10);
+        std::fs::write(&path, format!("short\n{}\n", long)).unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        let end = std::fs::metadata(&path).unwrap().len();
+        assert_eq!(Syncer::line_ending_at(&mut file, end).unwrap(), Some(long.into_bytes()));
+        assert_eq!(Syncer::line_ending_at(&mut file, 6).unwrap(), Some(b"short".to_vec()));
+        assert_eq!(Syncer::line_ending_at(&mut file, 3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_get_state_when_not_connected() {
+        let conn = RealDuckDBConnection::new(ConnectionConfig::default());
+
+        let result = ImportStateStore::new(&conn).get("/tmp/a.jsonl");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Database not connected"));
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::{anyhow, Result};
+use crate::db_connection::SqlValue;
+use crate::real_db_connection::ExtendedDatabaseConnection;
+use crate::store::Target;
+
+/// How several `--tag` filters combine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TagMode {
+    /// Every tag must be present
+    #[default]
+    All,
+    /// At least one tag must be present
+    Any,
+}
+
+/// A tag and how many messages and sessions carry it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// Tags are compared case-insensitively, so they are stored lowercased.
+/// Whitespace and commas are rejected to keep them usable as plain words
+/// on the command line and in comma-separated lists.
+pub fn normalize_tag(tag: &str) -> Result<String> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err(anyhow!("Tag names cannot be empty"));
+    }
+    if tag.chars().any(|c| c.is_whitespace() || c == ',') {
+        return Err(anyhow!("Tag '{}' cannot contain whitespace or commas", tag));
+    }
+    Ok(tag)
+}
+
+const INSERT_TAG: &str = "INSERT INTO tags (name) VALUES (?) ON CONFLICT DO NOTHING";
+
+const INSERT_TAGGING: &str =
+    "INSERT INTO taggings (tag, target_kind, target_id) VALUES (?, ?, ?) ON CONFLICT DO NOTHING";
+
+const DELETE_TAGGING: &str =
+    "DELETE FROM taggings WHERE tag = ? AND target_kind = ? AND target_id = ? RETURNING tag";
+
+// Tags disappear from `tag list` once nothing carries them
+const DELETE_UNUSED_TAG: &str =
+    "DELETE FROM tags WHERE name = ? AND NOT EXISTS (SELECT 1 FROM taggings WHERE tag = name)";
+
+const TAGS_OF_TARGET: &str =
+    "SELECT tag FROM taggings WHERE target_kind = ? AND target_id = ? ORDER BY tag";
+
+const COUNT_TAGS: &str = r#"
+SELECT t.name, count(g.tag)
+FROM tags t LEFT JOIN taggings g ON g.tag = t.name
+GROUP BY t.name
+ORDER BY t.name"#;
+
+/// Tags on messages and sessions. Tag names are expected to be normalized already.
+pub struct TagStore<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
+}
+
+impl<'a, C: ExtendedDatabaseConnection> TagStore<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
+        Self { connection }
+    }
+
+    fn connected(&self) -> Result<&C> {
+        if !self.connection.is_connected() {
+            return Err(anyhow!("Database not connected"));
+        }
+        Ok(self.connection)
+    }
+
+    /// Tagging a target twice is a no-op.
+    pub fn add(&self, target: &Target, tag: &str) -> Result<()> {
+        let conn = self.connected()?;
+        let [kind, id] = target.key_params();
+        conn.execute_with_params(INSERT_TAG, &[SqlValue::Text(tag.to_string())])?;
+        conn.execute_with_params(INSERT_TAGGING, &[SqlValue::Text(tag.to_string()), kind, id])
+    }
+
+    /// Returns whether the target carried the tag.
+    pub fn remove(&self, target: &Target, tag: &str) -> Result<bool> {
+        let conn = self.connected()?;
+        let [kind, id] = target.key_params();
+        let removed: Vec<String> = conn.query_all(
+            DELETE_TAGGING,
+            &[SqlValue::Text(tag.to_string()), kind, id],
+            |row| Ok(row.get(0)?),
+        )?;
+        conn.execute_with_params(DELETE_UNUSED_TAG, &[SqlValue::Text(tag.to_string())])?;
+        Ok(!removed.is_empty())
+    }
+
+    /// Tags on exactly this target, in name order.
+    pub fn tags_of(&self, target: &Target) -> Result<Vec<String>> {
+        self.connected()?.query_all(TAGS_OF_TARGET, &target.key_params(), |row| Ok(row.get(0)?))
+    }
+
+    /// Every tag in name order.
+    pub fn counts(&self) -> Result<Vec<TagCount>> {
+        self.connected()?.query_all(COUNT_TAGS, &[], |row| {
+            let count: i64 = row.get(1)?;
+            Ok(TagCount { tag: row.get(0)?, count: count as usize })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::setup_db;
+    use crate::real_db_connection::RealDuckDBConnection;
+    use crate::db_connection::ConnectionConfig;
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag(" PostMortem ").unwrap(), "postmortem");
+        assert_eq!(normalize_tag("good-prompt").unwrap(), "good-prompt");
+        assert!(normalize_tag("  ").is_err());
+        assert!(normalize_tag("two words").is_err());
+        assert!(normalize_tag("a,b").is_err());
+    }
+
+    #[test]
+    fn test_add_remove_and_count() {
+        let conn = setup_db();
+        let tags = TagStore::new(&conn);
+        let message = Target::Message("uuid-1".to_string());
+        let session = Target::Session("session-1".to_string());
+
+        tags.add(&message, "snippet").unwrap();
+        tags.add(&message, "postmortem").unwrap();
+        tags.add(&message, "snippet").unwrap();
+        tags.add(&session, "postmortem").unwrap();
+
+        assert_eq!(tags.tags_of(&message).unwrap(), vec!["postmortem", "snippet"]);
+        assert_eq!(tags.tags_of(&session).unwrap(), vec!["postmortem"]);
+        assert_eq!(tags.counts().unwrap(), vec![
+            TagCount { tag: "postmortem".to_string(), count: 2 },
+            TagCount { tag: "snippet".to_string(), count: 1 },
+        ]);
+
+        assert!(tags.remove(&message, "snippet").unwrap());
+        assert!(!tags.remove(&message, "snippet").unwrap());
+        assert!(tags.remove(&session, "postmortem").unwrap());
+        assert_eq!(tags.counts().unwrap(), vec![TagCount { tag: "postmortem".to_string(), count: 1 }]);
+    }
+
+    #[test]
+    fn test_when_not_connected() {
+        let conn = RealDuckDBConnection::new(ConnectionConfig::default());
+        let result = TagStore::new(&conn).tags_of(&Target::Session("s".to_string()));
+        assert!(result.unwrap_err().to_string().contains("Database not connected"));
+    }
+}
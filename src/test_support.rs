@@ -0,0 +1,64 @@
+//! Fixtures shared by the unit tests of several modules.
+use chrono::{Duration, Utc};
+use serde_json::Value;
+use crate::data_importer::DataImporter;
+use crate::db_connection::{ConnectionConfig, DatabaseConnection};
+use crate::db_schema::Migrator;
+use crate::jsonl_parser::{ClaudeMessage, JsonlParser, JsonlRecord};
+use crate::real_db_connection::RealDuckDBConnection;
+use crate::store::VaultStore;
+
+/// An in-memory vault at the latest schema version.
+pub fn setup_db() -> RealDuckDBConnection {
+    let conn = RealDuckDBConnection::new(ConnectionConfig {
+        database: ":memory:".to_string(),
+        ..Default::default()
+    });
+    conn.connect().unwrap();
+    Migrator::new(&conn).ensure_latest().unwrap();
+    conn
+}
+
+/// One transcript line, without its newline: a message of `session` written
+/// at `minute` past noon on 2025-07-21.
+pub fn line(uuid: &str, parent: Option<&str>, session: &str, minute: u32, role: &str, content: Value) -> String {
+    serde_json::json!({
+        "parentUuid": parent,
+        "sessionId": session,
+        "userType": "external",
+        "cwd": "/work/app",
+        "version": "1.0.51",
+        "gitBranch": "main",
+        "type": role,
+        "message": {"role": role, "content": content},
+        "uuid": uuid,
+        "timestamp": format!("2025-07-21T12:{:02}:00Z", minute),
+    }).to_string()
+}
+
+/// The message of a `line`, parsed and timestamped `age` before now.
+pub fn message(uuid: &str, parent: Option<&str>, session: &str, role: &str, content: Value, age: Duration) -> ClaudeMessage {
+    let mut message = JsonlParser::new()
+        .parse_single_message(&line(uuid, parent, session, 0, role, content))
+        .unwrap();
+    message.timestamp = Utc::now() - age;
+    message
+}
+
+/// Imports `messages` as one batch, the way `import` stores a file.
+pub fn import_messages<'m>(
+    conn: &RealDuckDBConnection,
+    messages: impl IntoIterator<Item = &'m ClaudeMessage>,
+    project_path: &str,
+) {
+    let records = messages.into_iter().map(|message| Ok(JsonlRecord::Message(message.clone())));
+    DataImporter::new(conn).bulk_import(records, project_path, false).unwrap();
+}
+
+/// The archived file of the session, as `restore-jsonl` writes it.
+pub fn raw_transcript(store: &dyn VaultStore, session_id: &str) -> Option<Vec<u8>> {
+    let source = store.raw_source(session_id).unwrap()?;
+    let mut content = Vec::new();
+    store.write_raw_file(&source.source_path, &mut content).unwrap();
+    Some(content)
+}
@@ -0,0 +1,238 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use crate::db_connection::SqlParams;
+use crate::real_db_connection::ExtendedDatabaseConnection;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub tool_use_id: String,
+    pub tool_name: String,
+    pub input_json: Option<String>,
+    pub result_text: Option<String>,
+    pub is_error: Option<bool>,
+    pub session_id: String,
+    pub message_uuid: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ToolCallQuery {
+    pub tool_name: Option<String>,
+    pub input_contains: Option<String>,
+    pub session_id: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl Default for ToolCallQuery {
+    fn default() -> Self {
+        Self {
+            tool_name: None,
+            input_contains: None,
+            session_id: None,
+            limit: Some(100),
+        }
+    }
+}
+
+/// Columns selected by every tool call query, in the order `map_tool_call_row` expects.
+const TOOL_CALL_COLUMNS: &str = r#"
+    tool_use_id,
+    tool_name,
+    input_json,
+    result_text,
+    is_error,
+    session_id,
+    message_uuid,
+    epoch_us(timestamp) AS timestamp_us"#;
+
+/// Finds tool calls by name, input or session, each with its result if
+/// one was recorded.
+pub struct ToolCallSearch<'a, C: ExtendedDatabaseConnection> {
+    connection: &'a C,
+}
+
+impl<'a, C: ExtendedDatabaseConnection> ToolCallSearch<'a, C> {
+    pub fn new(connection: &'a C) -> Self {
+        Self { connection }
+    }
+
+    /// Tool calls matching `query`, newest first. Results that arrived without
+    /// their `tool_use` are not listed.
+    pub fn find(&self, query: &ToolCallQuery) -> Result<Vec<ToolCall>> {
+        if !self.connection.is_connected() {
+            return Err(anyhow!("Database not connected"));
+        }
+
+        let (sql, params) = self.build_sql(query);
+        self.connection.query_all(&sql, params.values(), Self::map_tool_call_row)
+    }
+
+    fn map_tool_call_row(row: &duckdb::Row) -> Result<ToolCall> {
+        let timestamp_us: i64 = row.get(7)?;
+        Ok(ToolCall {
+            tool_use_id: row.get(0)?,
+            tool_name: row.get(1)?,
+            input_json: row.get(2)?,
+            result_text: row.get(3)?,
+            is_error: row.get(4)?,
+            session_id: row.get(5)?,
+            message_uuid: row.get(6)?,
+            timestamp: DateTime::from_timestamp_micros(timestamp_us)
+                .ok_or_else(|| anyhow!("Invalid timestamp: {}", timestamp_us))?,
+        })
+    }
+
+    /// Tool names match exactly; the input filter is a case-insensitive substring
+    /// match over the serialized input JSON.
+    fn build_sql(&self, query: &ToolCallQuery) -> (String, SqlParams) {
+        let mut params = SqlParams::new();
+        let mut conditions = vec!["tool_name IS NOT NULL".to_string()];
+
+        if let Some(tool_name) = &query.tool_name {
+            conditions.push(format!("tool_name = {}", params.bind(tool_name.clone())));
+        }
+
+        if let Some(needle) = &query.input_contains {
+            conditions.push(format!(
+                "contains(lower(coalesce(input_json, '')), {})",
+                params.bind(needle.to_lowercase())
+            ));
+        }
+
+        if let Some(session_id) = &query.session_id {
+            conditions.push(format!("session_id = {}", params.bind(session_id.clone())));
+        }
+
+        let sql = format!(
+            "SELECT {} FROM tool_calls WHERE {} ORDER BY timestamp DESC, tool_use_id LIMIT {}",
+            TOOL_CALL_COLUMNS,
+            conditions.join(" AND "),
+            query.limit.unwrap_or(100)
+        );
+        (sql, params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::real_db_connection::RealDuckDBConnection;
+    use crate::test_support::{import_messages, message, setup_db};
+    use crate::db_connection::ConnectionConfig;
+    use crate::jsonl_parser::ClaudeMessage;
+    use serde_json::{json, Value};
+
+    fn create_message(uuid: &str, session: &str, content: Value, minutes_ago: i64) -> ClaudeMessage {
+        message(uuid, None, session, "assistant", content, chrono::Duration::minutes(minutes_ago))
+    }
+
+    fn setup_test_db() -> RealDuckDBConnection {
+        let conn = setup_db();
+
+        let messages = [
+            create_message("use-1", "session-1", json!([
+                {"type": "text", "text": "Publishing"},
+                {"type": "tool_use", "id": "toolu_1", "name": "Bash", "input": {"command": "cargo publish"}}
+            ]), 30),
+            create_message("result-1", "session-1", json!([
+                {"type": "tool_result", "tool_use_id": "toolu_1", "content": "error: not logged in", "is_error": true}
+            ]), 29),
+            create_message("use-2", "session-2", json!([
+                {"type": "tool_use", "id": "toolu_2", "name": "Edit", "input": {"file_path": "src/main.rs"}},
+                {"type": "tool_use", "id": "toolu_3", "name": "Bash", "input": {"command": "ls -la"}}
+            ]), 10),
+        ];
+        import_messages(&conn, &messages, "/test/project");
+
+        conn
+    }
+
+    fn ids(calls: &[ToolCall]) -> Vec<&str> {
+        calls.iter().map(|c| c.tool_use_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_find_links_results_to_tool_uses() {
+        let conn = setup_test_db();
+        let search = ToolCallSearch::new(&conn);
+
+        let calls = search.find(&ToolCallQuery::default()).unwrap();
+        assert_eq!(ids(&calls), vec!["toolu_2", "toolu_3", "toolu_1"]);
+
+        let publish = calls.iter().find(|c| c.tool_use_id == "toolu_1").unwrap();
+        assert_eq!(publish.tool_name, "Bash");
+        assert_eq!(publish.input_json.as_deref(), Some(r#"{"command":"cargo publish"}"#));
+        assert_eq!(publish.result_text.as_deref(), Some("error: not logged in"));
+        assert_eq!(publish.is_error, Some(true));
+        assert_eq!(publish.session_id, "session-1");
+        assert_eq!(publish.message_uuid, "use-1");
+
+        let edit = calls.iter().find(|c| c.tool_use_id == "toolu_2").unwrap();
+        assert_eq!(edit.result_text, None);
+    }
+
+    #[test]
+    fn test_find_by_tool_and_input() {
+        let conn = setup_test_db();
+        let search = ToolCallSearch::new(&conn);
+
+        let query = ToolCallQuery {
+            tool_name: Some("Bash".to_string()),
+            input_contains: Some("CARGO".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&search.find(&query).unwrap()), vec!["toolu_1"]);
+
+        let query = ToolCallQuery {
+            input_contains: Some("src/main.rs".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&search.find(&query).unwrap()), vec!["toolu_2"]);
+
+        let query = ToolCallQuery {
+            session_id: Some("session-2".to_string()),
+            tool_name: Some("Bash".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&search.find(&query).unwrap()), vec!["toolu_3"]);
+    }
+
+    #[test]
+    fn test_result_imported_before_tool_use() {
+        let conn = setup_test_db();
+
+        let result = create_message("result-4", "session-3", json!([
+            {"type": "tool_result", "tool_use_id": "toolu_4", "content": "done"}
+        ]), 1);
+        import_messages(&conn, [&result], "/test/project");
+
+        let search = ToolCallSearch::new(&conn);
+        let query = ToolCallQuery {
+            session_id: Some("session-3".to_string()),
+            ..Default::default()
+        };
+        // The orphaned result is not listed until its tool_use arrives
+        assert!(search.find(&query).unwrap().is_empty());
+
+        let tool_use = create_message("use-4", "session-3", json!([
+            {"type": "tool_use", "id": "toolu_4", "name": "Write", "input": {"file_path": "a.txt"}}
+        ]), 2);
+        import_messages(&conn, [&tool_use], "/test/project");
+
+        let calls = search.find(&query).unwrap();
+        assert_eq!(ids(&calls), vec!["toolu_4"]);
+        assert_eq!(calls[0].result_text.as_deref(), Some("done"));
+        assert_eq!(calls[0].is_error, Some(false));
+    }
+
+    #[test]
+    fn test_find_when_not_connected() {
+        let conn = RealDuckDBConnection::new(ConnectionConfig::default());
+
+        let search = ToolCallSearch::new(&conn);
+        let result = search.find(&ToolCallQuery::default());
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Database not connected"));
+    }
+}
@@ -13,8 +13,11 @@ use ratatui::{
     Frame, Terminal,
 };
 use std::io;
-use crate::search::{SearchResult, SearchEngine, SearchQuery, SearchMode};
-use crate::db_connection::DatabaseConnection;
+use crate::cjk;
+use crate::search::{SearchResult, SearchQuery, SearchMode};
+use crate::session::SessionThread;
+use crate::snippet::DEFAULT_SNIPPET_CHARS;
+use crate::store::VaultStore;
 
 #[derive(Debug, PartialEq)]
 pub enum AppState {
@@ -29,6 +32,8 @@ pub struct App {
     pub search_results: Vec<SearchResult>,
     pub selected_index: usize,
     pub should_quit: bool,
+    /// Session of the result being viewed, loaded on demand
+    pub thread: Option<SessionThread>,
 }
 
 impl Default for App {
@@ -39,6 +44,7 @@ impl Default for App {
             search_results: Vec::new(),
             selected_index: 0,
             should_quit: false,
+            thread: None,
         }
     }
 }
@@ -105,17 +111,24 @@ impl App {
         match key {
             KeyCode::Esc => {
                 self.state = AppState::ResultsList;
+                self.thread = None;
             }
             _ => {}
         }
     }
 
-    pub fn perform_search(&mut self, connection: &dyn DatabaseConnection) -> Result<()> {
-        let search_engine = SearchEngine::new(connection);
-        let keywords: Vec<String> = self.search_input
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .collect();
+    /// Loads the session of the selected result so the detail view can show its context.
+    pub fn load_thread(&mut self, store: &dyn VaultStore) -> Result<()> {
+        if let Some(result) = self.search_results.get(self.selected_index) {
+            let thread = store.load_session(&result.session_id)?;
+            self.thread = Some(thread);
+        }
+        Ok(())
+    }
+
+    pub fn perform_search(&mut self, store: &dyn VaultStore) -> Result<()> {
+        // Japanese input rarely contains spaces, so split on script changes too
+        let keywords = cjk::split_keywords(&self.search_input);
 
         let query = SearchQuery {
             keywords,
@@ -123,13 +136,13 @@ impl App {
             ..Default::default()
         };
 
-        self.search_results = search_engine.search(&query)?;
+        self.search_results = store.search(&query)?;
         self.selected_index = 0;
         Ok(())
     }
 }
 
-pub fn run_tui(connection: &dyn DatabaseConnection) -> Result<()> {
+pub fn run_tui(store: &dyn VaultStore) -> Result<()> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -139,7 +152,7 @@ pub fn run_tui(connection: &dyn DatabaseConnection) -> Result<()> {
 
     // Create app and run
     let mut app = App::new();
-    let res = run_app(&mut terminal, &mut app, connection);
+    let res = run_app(&mut terminal, &mut app, store);
 
     // Restore terminal
     disable_raw_mode()?;
@@ -156,7 +169,7 @@ pub fn run_tui(connection: &dyn DatabaseConnection) -> Result<()> {
 fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut App,
-    connection: &dyn DatabaseConnection,
+    store: &dyn VaultStore,
 ) -> Result<()> {
     loop {
         terminal.draw(|f| ui(f, app))?;
@@ -170,7 +183,12 @@ fn run_app<B: Backend>(
             
             // Perform search when entering results list
             if app.state == AppState::ResultsList && app.search_results.is_empty() {
-                app.perform_search(connection)?;
+                app.perform_search(store)?;
+            }
+            
+            // Load the conversation around a result when opening it
+            if app.state == AppState::ViewingResult && app.thread.is_none() {
+                app.load_thread(store)?;
             }
         }
 
@@ -229,23 +247,19 @@ fn render_results_list(f: &mut Frame, app: &App, area: Rect) {
         .iter()
         .enumerate()
         .map(|(i, result)| {
-            let content = format!(
-                "[{}] {} - {}",
-                result.id,
-                result.timestamp.format("%Y-%m-%d %H:%M"),
-                result.message_content.as_deref().unwrap_or("(empty)")
-                    .chars()
-                    .take(50)
-                    .collect::<String>()
-            );
-            
             let style = if i == app.selected_index {
                 Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
             } else {
                 Style::default()
             };
             
-            ListItem::new(content).style(style)
+            let mut spans = vec![Span::styled(
+                format!("[{}] {} - ", result.id, result.timestamp.format("%Y-%m-%d %H:%M")),
+                style,
+            )];
+            spans.extend(snippet_spans(result, style));
+            
+            ListItem::new(Line::from(spans))
         })
         .collect();
 
@@ -254,9 +268,35 @@ fn render_results_list(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(list, area);
 }
 
+/// The result's snippet with its matches highlighted, falling back to the
+/// start of the content when there is no snippet.
+fn snippet_spans(result: &SearchResult, style: Style) -> Vec<Span<'_>> {
+    let Some(snippet) = &result.snippet else {
+        let content = result.message_content.as_deref().unwrap_or("(empty)");
+        return vec![Span::styled(content.chars().take(DEFAULT_SNIPPET_CHARS).collect::<String>(), style)];
+    };
+
+    let mut spans = Vec::new();
+    if snippet.truncated_start {
+        spans.push(Span::styled("…", style));
+    }
+    for (segment, is_match) in snippet.segments() {
+        let segment_style = if is_match {
+            style.fg(Color::Red).add_modifier(Modifier::BOLD)
+        } else {
+            style
+        };
+        spans.push(Span::styled(segment, segment_style));
+    }
+    if snippet.truncated_end {
+        spans.push(Span::styled("…", style));
+    }
+    spans
+}
+
 fn render_result_view(f: &mut Frame, app: &App, area: Rect) {
     if let Some(result) = app.search_results.get(app.selected_index) {
-        let text = vec![
+        let mut text = vec![
             Line::from(vec![
                 Span::raw("ID: "),
                 Span::styled(result.id.to_string(), Style::default().fg(Color::Yellow)),
@@ -276,10 +316,48 @@ fn render_result_view(f: &mut Frame, app: &App, area: Rect) {
                     Style::default().fg(Color::Cyan),
                 ),
             ]),
+        ];
+        if !result.tags.is_empty() {
+            text.push(Line::from(vec![
+                Span::raw("Tags: "),
+                Span::styled(result.tags.join(", "), Style::default().fg(Color::Magenta)),
+            ]));
+        }
+        if !result.notes.is_empty() {
+            text.push(Line::from(""));
+            text.push(Line::from("Notes:"));
+            for line in result.notes.iter().flat_map(|note| note.lines()) {
+                text.push(Line::from(Span::styled(line, Style::default().fg(Color::Green))));
+            }
+        }
+        text.extend([
+            Line::from(""),
+            Line::from("Match:"),
+            Line::from(snippet_spans(result, Style::default())),
             Line::from(""),
             Line::from("Content:"),
             Line::from(result.message_content.as_deref().unwrap_or("(empty)")),
-        ];
+        ]);
+        
+        if let Some(thread) = &app.thread {
+            text.push(Line::from(""));
+            text.push(Line::from("Conversation:"));
+            for message in thread.path_through(&result.uuid) {
+                let role = message.message_role.as_deref().unwrap_or(&message.message_type);
+                let body = message.content_text.as_deref()
+                    .or(message.block_summary.as_deref())
+                    .unwrap_or("(no content)");
+                let style = if message.uuid == result.uuid {
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default()
+                };
+                text.push(Line::from(vec![
+                    Span::styled(format!("[{}] ", role), style.fg(Color::Cyan)),
+                    Span::styled(body.to_string(), style),
+                ]));
+            }
+        }
 
         let paragraph = Paragraph::new(text)
             .block(Block::default().borders(Borders::ALL).title("Details"))
@@ -291,7 +369,7 @@ fn render_result_view(f: &mut Frame, app: &App, area: Rect) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db_connection::MockDatabaseConnection;
+    use crate::memory_store::MemoryStore;
 
     #[test]
     fn test_app_initialization() {
@@ -340,6 +418,9 @@ mod tests {
                 timestamp: chrono::Utc::now(),
                 rank: 0.9,
                 is_favorite: false,
+                tags: Vec::new(),
+                notes: Vec::new(),
+                snippet: None,
             },
             SearchResult {
                 id: 2,
@@ -351,6 +432,9 @@ mod tests {
                 timestamp: chrono::Utc::now(),
                 rank: 0.8,
                 is_favorite: false,
+                tags: Vec::new(),
+                notes: Vec::new(),
+                snippet: None,
             },
         ];
         
@@ -390,6 +474,9 @@ mod tests {
             timestamp: chrono::Utc::now(),
             rank: 0.9,
             is_favorite: false,
+            tags: Vec::new(),
+            notes: Vec::new(),
+            snippet: None,
         });
         
         // ResultsList -> ViewingResult
@@ -405,16 +492,41 @@ mod tests {
         assert_eq!(app.state, AppState::SearchInput);
     }
 
+    #[test]
+    fn test_snippet_spans_highlight_matches() {
+        let content = "最初の行\nテストの結果です";
+        let result = SearchResult {
+            id: 1,
+            uuid: "uuid1".to_string(),
+            session_id: "session1".to_string(),
+            message_content: Some(content.to_string()),
+            message_role: Some("user".to_string()),
+            project_path: "/test".to_string(),
+            timestamp: chrono::Utc::now(),
+            rank: 0.9,
+            is_favorite: false,
+            tags: Vec::new(),
+            notes: Vec::new(),
+            snippet: Some(crate::snippet::Snippet::generate(
+                content, &["結果".to_string()], &SearchMode::And, 100,
+            )),
+        };
+
+        let spans = snippet_spans(&result, Style::default());
+        let texts: Vec<&str> = spans.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(texts, vec!["最初の行 テストの", "結果", "です"]);
+        assert_eq!(spans[1].style.fg, Some(Color::Red));
+        assert_eq!(spans[0].style.fg, None);
+    }
+
     #[test]
     fn test_perform_search() {
         let mut app = App::new();
         app.search_input = "test".to_string();
         
-        let mut mock_conn = MockDatabaseConnection::new();
-        mock_conn.expect_is_connected()
-            .returning(|| true);
+        let store = MemoryStore::new();
         
-        let result = app.perform_search(&mock_conn);
+        let result = app.perform_search(&store);
         assert!(result.is_ok());
     }
 
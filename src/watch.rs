@@ -0,0 +1,326 @@
+use anyhow::{anyhow, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use crate::claude_reader::ClaudeReader;
+use crate::store::{refresh_search_index, VaultStore};
+use crate::sync::Syncer;
+
+/// How often the event loop wakes up to flush debounced files and check for shutdown.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The least time between two search index rebuilds while watching.
+const INDEX_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Collects changed paths until they have been quiet for `window`, so a burst
+/// of writes to one file is synced once.
+#[derive(Debug)]
+pub struct Debouncer {
+    window: Duration,
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration) -> Self {
+        Self { window, pending: HashMap::new() }
+    }
+
+    pub fn add(&mut self, path: PathBuf, now: Instant) {
+        self.pending.insert(path, now);
+    }
+
+    /// Removes and returns the paths whose last change is at least `window` old.
+    pub fn due(&mut self, now: Instant) -> Vec<PathBuf> {
+        let window = self.window;
+        let mut due: Vec<PathBuf> = self.pending.iter()
+            .filter(|(_, &changed)| now.duration_since(changed) >= window)
+            .map(|(path, _)| path.clone())
+            .collect();
+        due.sort();
+        for path in &due {
+            self.pending.remove(path);
+        }
+        due
+    }
+
+    /// Removes and returns every pending path, due or not.
+    pub fn drain(&mut self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.pending.drain().map(|(path, _)| path).collect();
+        paths.sort();
+        paths
+    }
+}
+
+/// Decides when to rebuild the search index. A rebuild reindexes the whole
+/// vault, so while messages keep arriving it runs at most once per `interval`.
+#[derive(Debug)]
+pub struct IndexSchedule {
+    interval: Duration,
+    last_rebuild: Option<Instant>,
+    stale: bool,
+}
+
+impl IndexSchedule {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last_rebuild: None, stale: false }
+    }
+
+    /// Records that messages were imported since the last rebuild.
+    pub fn mark_stale(&mut self) {
+        self.stale = true;
+    }
+
+    /// Whether to rebuild now, counting the rebuild as done if so.
+    pub fn take_due(&mut self, now: Instant) -> bool {
+        let due = self.stale && self.last_rebuild
+            .is_none_or(|last| now.duration_since(last) >= self.interval);
+        if due {
+            self.stale = false;
+            self.last_rebuild = Some(now);
+        }
+        due
+    }
+
+    /// Whether anything imported is still unindexed, regardless of the interval.
+    pub fn take_pending(&mut self) -> bool {
+        std::mem::take(&mut self.stale)
+    }
+}
+
+/// The `.jsonl` files an event created or wrote to.
+pub fn changed_jsonl_paths(event: &Event) -> Vec<PathBuf> {
+    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+        return Vec::new();
+    }
+
+    event.paths.iter()
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("jsonl"))
+        .cloned()
+        .collect()
+}
+
+/// Keeps the vault in step with `~/.claude/projects`, syncing files as they change.
+pub struct VaultWatcher<'a> {
+    store: &'a dyn VaultStore,
+    reader: ClaudeReader,
+    syncer: Syncer<'a>,
+    debounce: Duration,
+}
+
+impl<'a> VaultWatcher<'a> {
+    pub fn new(store: &'a dyn VaultStore, reader: ClaudeReader, debounce: Duration) -> Self {
+        Self {
+            store,
+            reader,
+            syncer: Syncer::new(store),
+            debounce,
+        }
+    }
+
+    /// Also keeps synced lines byte for byte in the raw archive.
+    pub fn with_raw_archive(mut self, enabled: bool) -> Self {
+        self.syncer = self.syncer.with_raw_archive(enabled);
+        self
+    }
+
+    /// Syncs every file once, then follows changes until `shutdown` is set.
+    /// Files still waiting out their debounce window are synced, and the search
+    /// index brought up to date, before returning.
+    pub fn run(&self, shutdown: &AtomicBool) -> Result<()> {
+        if !self.reader.check_directory_exists() {
+            return Err(anyhow!("Claude projects directory not found at {}", self.reader.projects_path().display()));
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(self.reader.projects_path(), RecursiveMode::Recursive)?;
+
+        let mut schedule = IndexSchedule::new(INDEX_INTERVAL);
+
+        // Watching starts before the catch-up so nothing written in between is missed
+        if self.sync_paths(&self.reader.find_jsonl_files()?) > 0 {
+            schedule.mark_stale();
+        }
+
+        let mut debouncer = Debouncer::new(self.debounce);
+        while !shutdown.load(Ordering::SeqCst) {
+            match rx.recv_timeout(POLL_INTERVAL) {
+                Ok(Ok(event)) => {
+                    let now = Instant::now();
+                    for path in changed_jsonl_paths(&event) {
+                        debouncer.add(path, now);
+                    }
+                }
+                Ok(Err(e)) => eprintln!("Watch error: {}", e),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if self.sync_paths(&debouncer.due(Instant::now())) > 0 {
+                schedule.mark_stale();
+            }
+            if schedule.take_due(Instant::now()) {
+                refresh_search_index(self.store);
+            }
+        }
+
+        if self.sync_paths(&debouncer.drain()) > 0 {
+            schedule.mark_stale();
+        }
+        if schedule.take_pending() {
+            refresh_search_index(self.store);
+        }
+        Ok(())
+    }
+
+    /// Syncs `paths`, returning how many messages were imported. Errors are
+    /// reported and skipped so one bad file does not stop the watch.
+    fn sync_paths(&self, paths: &[PathBuf]) -> usize {
+        let mut imported = 0;
+
+        for path in paths {
+            match self.sync_path(path) {
+                Ok(0) => {}
+                Ok(count) => {
+                    println!("{}: {} new", path.display(), count);
+                    imported += count;
+                }
+                Err(e) => eprintln!("Error syncing {}: {}", path.display(), e),
+            }
+        }
+
+        imported
+    }
+
+    fn sync_path(&self, path: &Path) -> Result<usize> {
+        // The file may have been removed between the event and now
+        if !path.is_file() {
+            return Ok(0);
+        }
+
+        let project_name = self.reader.get_project_name_from_path(path)
+            .unwrap_or_else(|| "unknown".to_string());
+        Ok(self.syncer.sync_file(path, &project_name)?.imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{line, setup_db};
+    use crate::duckdb_store::DuckDBStore;
+    use crate::real_db_connection::ExtendedDatabaseConnection;
+    use notify::event::{CreateKind, ModifyKind, RemoveKind};
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// A complete line of `session-1`, newline included.
+    fn user_line(uuid: &str) -> String {
+        line(uuid, None, "session-1", 0, "user", "hello".into()) + "\n"
+    }
+
+    #[test]
+    fn test_debouncer_waits_for_quiet_period() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_millis(500));
+
+        debouncer.add(PathBuf::from("a.jsonl"), start);
+        debouncer.add(PathBuf::from("b.jsonl"), start + Duration::from_millis(300));
+        // A second write to a.jsonl restarts its window
+        debouncer.add(PathBuf::from("a.jsonl"), start + Duration::from_millis(400));
+
+        assert!(debouncer.due(start + Duration::from_millis(600)).is_empty());
+        assert_eq!(debouncer.due(start + Duration::from_millis(800)), vec![PathBuf::from("b.jsonl")]);
+        assert_eq!(debouncer.due(start + Duration::from_millis(900)), vec![PathBuf::from("a.jsonl")]);
+        assert!(debouncer.drain().is_empty());
+    }
+
+    #[test]
+    fn test_index_schedule_rate_limits_rebuilds() {
+        let start = Instant::now();
+        let mut schedule = IndexSchedule::new(Duration::from_secs(30));
+
+        // Nothing imported, nothing to rebuild
+        assert!(!schedule.take_due(start));
+
+        schedule.mark_stale();
+        assert!(schedule.take_due(start));
+        assert!(!schedule.take_due(start + Duration::from_secs(1)));
+
+        schedule.mark_stale();
+        assert!(!schedule.take_due(start + Duration::from_secs(10)));
+        assert!(schedule.take_due(start + Duration::from_secs(30)));
+
+        // On exit a pending rebuild runs however recent the last one was
+        schedule.mark_stale();
+        assert!(schedule.take_pending());
+        assert!(!schedule.take_pending());
+    }
+
+    #[test]
+    fn test_changed_jsonl_paths_filters_events() {
+        let event = |kind| Event::new(kind)
+            .add_path(PathBuf::from("/p/a.jsonl"))
+            .add_path(PathBuf::from("/p/notes.txt"));
+
+        let created = event(EventKind::Create(CreateKind::File));
+        assert_eq!(changed_jsonl_paths(&created), vec![PathBuf::from("/p/a.jsonl")]);
+
+        let modified = event(EventKind::Modify(ModifyKind::Any));
+        assert_eq!(changed_jsonl_paths(&modified), vec![PathBuf::from("/p/a.jsonl")]);
+
+        let removed = event(EventKind::Remove(RemoveKind::File));
+        assert!(changed_jsonl_paths(&removed).is_empty());
+    }
+
+    #[test]
+    fn test_watch_ingests_appended_lines_until_shutdown() {
+        let conn = setup_db();
+
+        let dir = TempDir::new().unwrap();
+        let project_dir = dir.path().join("my-project");
+        std::fs::create_dir_all(&project_dir).unwrap();
+        let path = project_dir.join("session.jsonl");
+        std::fs::write(&path, user_line("uuid-1")).unwrap();
+
+        let shutdown = AtomicBool::new(false);
+        let count = || -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM conversations", &[], |row| Ok(row.get(0)?)).unwrap().unwrap()
+        };
+        let wait_for = |expected: i64| {
+            let deadline = Instant::now() + Duration::from_secs(30);
+            while count() < expected {
+                if Instant::now() > deadline {
+                    // Stop the watcher so the scope can end and report the failure
+                    shutdown.store(true, Ordering::SeqCst);
+                    panic!("watch never reached {} rows", expected);
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        };
+
+        std::thread::scope(|scope| {
+            let watching = scope.spawn(|| {
+                let store = DuckDBStore::new(&conn);
+                VaultWatcher::new(&store, ClaudeReader::with_path(dir.path().to_path_buf()), Duration::from_millis(50))
+                    .run(&shutdown)
+            });
+
+            wait_for(1);
+            let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+            file.write_all((user_line("uuid-2") + &user_line("uuid-3")).as_bytes()).unwrap();
+            wait_for(3);
+            shutdown.store(true, Ordering::SeqCst);
+            watching.join().unwrap().unwrap();
+        });
+
+        let projects: Vec<(String, i64)> = conn.query_all(
+            "SELECT project_path, COUNT(*) FROM conversations GROUP BY project_path", &[],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!(projects, vec![("my-project".to_string(), 3)]);
+    }
+}